@@ -0,0 +1,108 @@
+//! Compares the throughput of the sequential and the parallel block coloring.
+//!
+//! Run with `cargo bench --features parallel`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use openassets_tapyrus::openassets::pipeline::{
+    color_blocks, color_blocks_parallel, color_raw_blocks_parallel, ChainColorer,
+    DEFAULT_CHUNK_SIZE,
+};
+use openassets_tapyrus::openassets::testing::{block, funding_tx, marker, p2pkh, tx, txout};
+use tapyrus::consensus::serialize;
+use tapyrus::hashes::Hash;
+use tapyrus::network::constants::Network;
+use tapyrus::{Block, BlockHash, OutPoint, Transaction};
+
+const BLOCKS: usize = 500;
+const TXS_PER_BLOCK: usize = 50;
+
+const ISSUED: u64 = 1_000_000;
+
+/// Every block continues `TXS_PER_BLOCK` independent transfer chains, each
+/// transfer keeping one unit less on the output spent by the next one.
+fn chain() -> Vec<Block> {
+    let funding = funding_tx(
+        0,
+        (0..TXS_PER_BLOCK)
+            .map(|_| txout(10_000, p2pkh(1)))
+            .collect(),
+    );
+    let issuances: Vec<Transaction> = (0..TXS_PER_BLOCK)
+        .map(|i| {
+            tx(
+                &[OutPoint::new(funding.txid(), i as u32)],
+                vec![txout(600, p2pkh(2)), marker(vec![ISSUED], b"")],
+            )
+        })
+        .collect();
+    let mut heads: Vec<OutPoint> = issuances
+        .iter()
+        .map(|t| OutPoint::new(t.txid(), 0))
+        .collect();
+    let mut txs = vec![funding];
+    txs.extend(issuances);
+    let mut blocks = vec![block(&BlockHash::from_slice(&[0; 32]).unwrap(), 0, &txs)];
+    for height in 1..BLOCKS {
+        let quantity = ISSUED - height as u64;
+        let txs: Vec<Transaction> = heads
+            .iter()
+            .map(|head| {
+                tx(
+                    &[*head],
+                    vec![
+                        marker(vec![quantity, 1], b""),
+                        txout(600, p2pkh(3)),
+                        txout(600, p2pkh(4)),
+                    ],
+                )
+            })
+            .collect();
+        heads = txs.iter().map(|t| OutPoint::new(t.txid(), 1)).collect();
+        let prev = blocks.last().unwrap().block_hash();
+        blocks.push(block(&prev, 0, &txs));
+    }
+    blocks
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let blocks = chain();
+    // a broken chain would only measure the coloring of uncolored outputs
+    let colorings = color_blocks(&mut ChainColorer::new(Network::Prod), &blocks).unwrap();
+    let last = ISSUED - (BLOCKS as u64 - 1);
+    for coloring in colorings.last().unwrap() {
+        let quantities: Vec<u64> = coloring.outputs.iter().map(|o| o.asset_quantity).collect();
+        assert_eq!(vec![0, last, 1], quantities);
+        assert!(coloring.outputs[1..].iter().all(|o| o.asset_id.is_some()));
+    }
+    let raw: Vec<Vec<u8>> = blocks.iter().map(serialize).collect();
+    let mut group = c.benchmark_group("coloring");
+    group.throughput(Throughput::Elements(blocks.len() as u64));
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter(|| color_blocks(&mut ChainColorer::new(Network::Prod), &blocks).unwrap())
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| {
+            color_blocks_parallel(
+                &mut ChainColorer::new(Network::Prod),
+                &blocks,
+                DEFAULT_CHUNK_SIZE,
+            )
+            .unwrap()
+        })
+    });
+    group.bench_function("parallel_raw", |b| {
+        b.iter(|| {
+            color_raw_blocks_parallel(
+                &mut ChainColorer::new(Network::Prod),
+                &raw,
+                DEFAULT_CHUNK_SIZE,
+            )
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_pipeline);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+//! `oa-server`: serves coloring and balance queries over HTTP from local block files.
+//!
+//! Run with `cargo run --features server --bin oa-server -- --blocks <dir> --magic <hex>`.
+//! `--network` takes `prod`, `dev` or the name of a preset, e.g. `bitcoin`,
+//! whose magic is used if `--magic` is omitted.
+
+use std::process;
+
+use openassets_tapyrus::openassets::blockfile::BlockScanner;
+use openassets_tapyrus::openassets::network::{self, NetworkParams};
+use openassets_tapyrus::openassets::server::{Index, Server};
+
+const USAGE: &str =
+    "usage: oa-server --blocks <dir> [--magic <hex>] [--network prod|dev|<name>] [--listen <addr>]";
+
+struct Options {
+    blocks: String,
+    magic: [u8; 4],
+    params: &'static NetworkParams,
+    listen: String,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut blocks = None;
+    let mut magic = None;
+    let mut params = &network::TAPYRUS_PROD;
+    let mut listen = "127.0.0.1:3000".to_string();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--blocks" => blocks = Some(value.clone()),
+            "--magic" => {
+                let bytes = hex::decode(value).map_err(|e| format!("invalid magic: {}", e))?;
+                let bytes: [u8; 4] = bytes
+                    .try_into()
+                    .map_err(|_| "the magic must be 4 bytes".to_string())?;
+                magic = Some(bytes);
+            }
+            "--network" => {
+                params = match value.as_str() {
+                    "prod" => &network::TAPYRUS_PROD,
+                    "dev" => &network::TAPYRUS_DEV,
+                    name => NetworkParams::by_name(name)
+                        .ok_or_else(|| format!("unknown network {}", value))?,
+                }
+            }
+            "--listen" => listen = value.clone(),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    Ok(Options {
+        blocks: blocks.ok_or("missing option --blocks")?,
+        magic: magic.or(params.magic).ok_or("missing option --magic")?,
+        params,
+        listen,
+    })
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_options(args).map_err(|e| format!("{}\n\n{}", e, USAGE))?;
+    let scanner = BlockScanner::open(&options.blocks, options.magic)?;
+    let mut index = Index::with_params(options.params);
+    for block in scanner.blocks() {
+        index.add_block(&block?)?;
+    }
+    let server = Server::bind(options.listen.as_str(), index)?;
+    eprintln!(
+        "colored {} blocks, listening on {}",
+        scanner.len(),
+        server.local_addr()?
+    );
+    server.serve()?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("oa-server: {}", e);
+        process::exit(1);
+    }
+}
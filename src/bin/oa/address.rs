@@ -0,0 +1,75 @@
+use std::str::FromStr;
+
+use openassets_tapyrus::openassets::address::{Address, OAAddressConverter};
+use serde_json::{json, Value};
+
+use crate::args::Args;
+use crate::{network_name, Result};
+
+pub fn run(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["json"])?;
+    let value = match &args.positional[..] {
+        [direction, address] => convert(direction, address)?,
+        _ => return Err("usage: oa address to-oa|to-tapyrus <address> [--json]".into()),
+    };
+    if args.flag("json") {
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        println!("{}", value["converted"].as_str().unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Converts `address` in `direction` (`to-oa` or `to-tapyrus`).
+pub fn convert(direction: &str, address: &str) -> Result<Value> {
+    let (tapyrus_address, oa_address, converted) = match direction {
+        "to-oa" => {
+            let tapyrus_address = tapyrus::Address::from_str(address)?;
+            let oa_address = tapyrus_address.to_oa_address()?;
+            let converted = oa_address.to_string();
+            (tapyrus_address, oa_address, converted)
+        }
+        "to-tapyrus" => {
+            let oa_address = Address::from_str(address)?;
+            let tapyrus_address = oa_address.to_btc_addr()?;
+            let converted = tapyrus_address.to_string();
+            (tapyrus_address, oa_address, converted)
+        }
+        _ => return Err(format!("unknown conversion {}", direction).into()),
+    };
+    Ok(json!({
+        "network": network_name(tapyrus_address.network),
+        "address": tapyrus_address.to_string(),
+        "oa_address": oa_address.to_string(),
+        "converted": converted,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::address::convert;
+    use serde_json::json;
+
+    #[test]
+    fn test_convert() {
+        let expected = json!({
+            "network": "dev",
+            "address": "mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4",
+            "oa_address": "bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE",
+            "converted": "bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE",
+        });
+        assert_eq!(
+            expected,
+            convert("to-oa", "mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4").unwrap()
+        );
+        let value = convert("to-tapyrus", "bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE").unwrap();
+        assert_eq!(
+            json!("mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4"),
+            value["converted"]
+        );
+
+        assert!(convert("to-oa", "bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE").is_err());
+        assert!(convert("to-tapyrus", "mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4").is_err());
+        assert!(convert("to-bitcoin", "mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4").is_err());
+    }
+}
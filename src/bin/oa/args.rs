@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use crate::Result;
+
+/// The command-line arguments of a subcommand.
+///
+/// Options are written `--name value`, except the flags given to `parse`
+/// which take no value. Everything else is positional.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String], flags: &[&str]) -> Result<Args> {
+        let mut parsed = Args::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if flags.contains(&name) => parsed.flags.push(name.to_string()),
+                Some(name) => {
+                    let value = iter
+                        .next()
+                        .ok_or_else(|| format!("missing value for --{}", name))?;
+                    parsed.options.insert(name.to_string(), value.clone());
+                }
+                None => parsed.positional.push(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    pub fn required(&self, name: &str) -> Result<&str> {
+        self.option(name)
+            .ok_or_else(|| format!("missing option --{}", name).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed =
+            Args::parse(&args(&["--json", "abcd", "--file", "tx.hex"]), &["json"]).unwrap();
+        assert!(parsed.flag("json"));
+        assert!(!parsed.flag("file"));
+        assert_eq!(Some("tx.hex"), parsed.option("file"));
+        assert_eq!(vec!["abcd".to_string()], parsed.positional);
+        assert_eq!(None, parsed.option("network"));
+        assert!(parsed.required("network").is_err());
+
+        assert!(Args::parse(&args(&["--file"]), &["json"]).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use openassets_tapyrus::openassets::asset_id::AssetId;
+use serde_json::{json, Value};
+use tapyrus::Script;
+
+use crate::args::Args;
+use crate::{network_name, parse_address, parse_network, Result};
+
+pub fn run(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["json"])?;
+    let value = compute(&args)?;
+    if args.flag("json") {
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        println!("{}", value["asset_id"].as_str().unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Computes the asset id issued by `--script` or `--address`. The network of
+/// an address is used unless `--network` is given.
+pub fn compute(args: &Args) -> Result<Value> {
+    let (script, network) = match (args.option("script"), args.option("address")) {
+        (Some(hex), None) => {
+            let script = Script::from(hex::decode(hex)?);
+            (script, parse_network(args.required("network")?)?)
+        }
+        (None, Some(address)) => {
+            let address = parse_address(address)?;
+            let network = match args.option("network") {
+                Some(name) => parse_network(name)?,
+                None => address.network,
+            };
+            (address.script_pubkey(), network)
+        }
+        _ => return Err("either --script or --address is required".into()),
+    };
+    Ok(json!({
+        "asset_id": AssetId::new(&script, network).to_string(),
+        "script": hex::encode(script.as_bytes()),
+        "network": network_name(network),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::asset_id::compute;
+    use serde_json::json;
+
+    fn args(args: &[&str]) -> Args {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        Args::parse(&args, &["json"]).unwrap()
+    }
+
+    #[test]
+    fn test_compute() {
+        let script = "76a914010966776006953d5567439e5e39f86a0d273bee88ac";
+        let value = compute(&args(&["--script", script, "--network", "prod"])).unwrap();
+        assert_eq!(
+            json!("ALn3aK1fSuG27N96UGYB1kUYUpGKRhBuBC"),
+            value["asset_id"]
+        );
+        assert!(compute(&args(&["--script", script])).is_err());
+        assert!(compute(&args(&["--script", script, "--network", "main"])).is_err());
+
+        // the Tapyrus and Open Assets addresses of the same key issue the same asset
+        let tapyrus = compute(&args(&["--address", "mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4"])).unwrap();
+        let oa = compute(&args(&["--address", "bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE"])).unwrap();
+        assert_eq!(tapyrus, oa);
+        assert_eq!(json!("dev"), oa["network"]);
+
+        assert!(compute(&args(&[])).is_err());
+    }
+}
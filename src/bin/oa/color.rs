@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+
+use openassets_tapyrus::openassets::coloring::{needs_inputs, Colorer, OutputType};
+use serde_json::{json, Value};
+use tapyrus::network::constants::Network;
+use tapyrus::{Transaction, Txid};
+
+use crate::args::Args;
+use crate::{parse_network, parse_tx, read_tx, Result};
+
+pub fn run(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["json"])?;
+    let tx = read_tx(&args)?;
+    let bundle = match args.option("prev-txs") {
+        Some(path) => parse_bundle(&serde_json::from_slice(&fs::read(path)?)?)?,
+        None => HashMap::new(),
+    };
+    let network = parse_network(args.option("network").unwrap_or("prod"))?;
+    let value = color(&tx, bundle, network)?;
+    if args.flag("json") {
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        print!("{}", to_text(&value));
+    }
+    match value["missing"].as_array() {
+        Some(missing) if !missing.is_empty() => Err(format!(
+            "{} ancestor transactions missing from the bundle",
+            missing.len()
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Parses a bundle of previous transactions: a JSON array of raw transaction
+/// hex, or an object mapping txids to raw transaction hex.
+pub fn parse_bundle(value: &Value) -> Result<HashMap<Txid, Transaction>> {
+    let hexes: Vec<&Value> = match value {
+        Value::Array(entries) => entries.iter().collect(),
+        Value::Object(entries) => entries.values().collect(),
+        _ => return Err("the bundle must be an array or an object of transactions".into()),
+    };
+    let mut bundle = HashMap::new();
+    for hex in hexes {
+        let tx = parse_tx(hex.as_str().ok_or("bundle entries must be hex strings")?)?;
+        bundle.insert(tx.txid(), tx);
+    }
+    Ok(bundle)
+}
+
+/// Returns the ancestors of `tx` needed to color it which are not in `bundle`.
+///
+/// Like the colorer, the walk stops at transactions without a marker output.
+pub fn missing_ancestors(tx: &Transaction, bundle: &HashMap<Txid, Transaction>) -> Vec<Txid> {
+    let mut missing = vec![];
+    let mut visited = HashSet::new();
+    let mut stack = vec![tx];
+    while let Some(top) = stack.pop() {
+        if !needs_inputs(top) {
+            continue;
+        }
+        for input in top.input.iter() {
+            let txid = input.previous_output.txid;
+            if !visited.insert(txid) {
+                continue;
+            }
+            match bundle.get(&txid) {
+                Some(prev) => stack.push(prev),
+                None => missing.push(txid),
+            }
+        }
+    }
+    missing
+}
+
+/// Colors `tx` from the transactions of `bundle`, explaining why each
+/// uncolored output is uncolored.
+pub fn color(
+    tx: &Transaction,
+    bundle: HashMap<Txid, Transaction>,
+    network: Network,
+) -> Result<Value> {
+    let missing = missing_ancestors(tx, &bundle);
+    if !missing.is_empty() {
+        return Ok(json!({
+            "txid": tx.txid().to_string(),
+            "missing": missing.iter().map(|txid| txid.to_string()).collect::<Vec<String>>(),
+        }));
+    }
+    let mut colorer = Colorer::new(bundle, network);
+    let outputs = colorer.color_transaction(tx)?;
+    let diagnosis = colorer.diagnose_transaction(tx)?;
+    let outputs: Vec<Value> = outputs
+        .iter()
+        .enumerate()
+        .map(|(i, o)| {
+            let reason = match (&diagnosis, o.output_type) {
+                (Some(diagnosis), _) => Some(diagnosis.to_string()),
+                (None, OutputType::MarkerOutput) => Some("marker output".to_string()),
+                (None, _) if o.asset_id.is_none() => {
+                    Some("the marker output assigns no asset quantity".to_string())
+                }
+                (None, _) => None,
+            };
+            json!({
+                "index": i,
+                "value": o.value,
+                "asset_id": o.asset_id.as_ref().map(|id| id.to_string()),
+                "asset_quantity": o.asset_quantity,
+                "output_type": o.output_type.to_string(),
+                "reason": reason,
+            })
+        })
+        .collect();
+    Ok(json!({
+        "txid": tx.txid().to_string(),
+        "missing": [],
+        "diagnosis": diagnosis.as_ref().map(|d| json!(d)),
+        "outputs": outputs,
+    }))
+}
+
+fn to_text(value: &Value) -> String {
+    let mut text = String::new();
+    writeln!(text, "txid: {}", value["txid"].as_str().unwrap_or_default()).unwrap();
+    let missing = value["missing"].as_array().cloned().unwrap_or_default();
+    if !missing.is_empty() {
+        writeln!(text, "missing ancestors:").unwrap();
+        for txid in missing {
+            writeln!(text, "  {}", txid.as_str().unwrap_or_default()).unwrap();
+        }
+        return text;
+    }
+    for output in value["outputs"].as_array().into_iter().flatten() {
+        match output["asset_id"].as_str() {
+            Some(asset_id) => writeln!(
+                text,
+                "output {}: {} {} ({})",
+                output["index"],
+                asset_id,
+                output["asset_quantity"],
+                output["output_type"].as_str().unwrap_or_default()
+            ),
+            None => writeln!(
+                text,
+                "output {}: uncolored ({})",
+                output["index"],
+                output["reason"].as_str().unwrap_or_default()
+            ),
+        }
+        .unwrap();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::{color, missing_ancestors, parse_bundle, to_text};
+    use crate::parse_tx;
+    use openassets_tapyrus::openassets::marker_output::{Metadata, Payload};
+    use serde_json::json;
+    use tapyrus::blockdata::opcodes;
+    use tapyrus::blockdata::script::Builder;
+    use tapyrus::consensus::serialize;
+    use tapyrus::network::constants::Network;
+    use tapyrus::{OutPoint, TxOut};
+
+    /// Issues 100 units to output 0 and transfers 123 units to output 3.
+    const TX: &str = "010000000100000000000000000000000000000000000000000000000000000000000000000000000000ffffffff0458020000000000001976a914010101010101010101010101010101010101010188ac0000000000000000266a244f4101000364007b1b753d68747470733a2f2f6370722e736d2f35596753553150672d7158020000000000001976a914020202020202020202020202020202020202020288ac58020000000000001976a914030303030303030303030303030303030303030388ac00000000";
+
+    #[test]
+    fn test_color() {
+        let decoded = parse_tx(TX).unwrap();
+        // a transaction without a marker, funding the issuance
+        let mut funding = decoded.clone();
+        funding.output.truncate(1);
+        funding.output[0].value = 10_000;
+        let mut issuance = decoded;
+        issuance.input[0].previous_output = OutPoint::new(funding.txid(), 0);
+
+        let missing = color(&issuance, Default::default(), Network::Prod).unwrap();
+        assert_eq!(json!([funding.txid().to_string()]), missing["missing"]);
+        assert!(to_text(&missing).contains(&funding.txid().to_string()));
+
+        let bundle = parse_bundle(&json!([hex::encode(serialize(&funding))])).unwrap();
+        assert!(missing_ancestors(&issuance, &bundle).is_empty());
+        // the 123 units transferred to output 3 have no input to come from
+        let value = color(&issuance, bundle.clone(), Network::Prod).unwrap();
+        assert_eq!(
+            json!("insufficient_input_quantity"),
+            value["diagnosis"]["rule"]
+        );
+        assert!(value["outputs"][0]["asset_id"].is_null());
+        assert_eq!(value["diagnosis"]["message"], value["outputs"][0]["reason"]);
+
+        issuance.output[1] = TxOut {
+            value: 0,
+            script_pubkey: Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .push_slice(&serialize(&Payload {
+                    quantities: vec![100],
+                    metadata: Metadata::new(vec![]),
+                }))
+                .into_script(),
+        };
+        let value = color(&issuance, bundle, Network::Prod).unwrap();
+        assert!(value["diagnosis"].is_null());
+        assert_eq!(json!(100), value["outputs"][0]["asset_quantity"]);
+        assert_eq!(json!("marker output"), value["outputs"][1]["reason"]);
+        assert_eq!(
+            json!("the marker output assigns no asset quantity"),
+            value["outputs"][2]["reason"]
+        );
+        assert!(to_text(&value).contains("output 1: uncolored (marker output)\n"));
+    }
+}
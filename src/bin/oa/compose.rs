@@ -0,0 +1,263 @@
+use std::fs;
+use std::str::FromStr;
+
+use openassets_tapyrus::openassets::asset_id::AssetId;
+use openassets_tapyrus::openassets::builder::{TransactionBuilder, UnsignedTransaction, Utxo};
+use openassets_tapyrus::openassets::coloring::{color_outputs, ColoredOutput, OutputType};
+use serde_json::{json, Value};
+use tapyrus::consensus::serialize;
+use tapyrus::hashes::hex::FromHex;
+use tapyrus::{OutPoint, Script, TxOut, Txid};
+
+use crate::args::Args;
+use crate::{parse_address, parse_network, Result};
+
+pub fn run_issue(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &[])?;
+    let utxos = read_utxos(args.required("utxos")?)?;
+    println!("{}", serde_json::to_string_pretty(&issue(&args, &utxos)?)?);
+    Ok(())
+}
+
+pub fn run_transfer(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &[])?;
+    let utxos = read_utxos(args.required("utxos")?)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&transfer(&args, &utxos)?)?
+    );
+    Ok(())
+}
+
+pub fn issue(args: &Args, utxos: &[Utxo]) -> Result<Value> {
+    let to = parse_address(args.required("to")?)?;
+    let quantity = u64::from_str(args.required("quantity")?)?;
+    let metadata = args.option("metadata").unwrap_or_default();
+    let (builder, change) = builder(args)?;
+    let built = builder.issue(
+        utxos,
+        &to.script_pubkey(),
+        quantity,
+        metadata.as_bytes().to_vec(),
+        &change,
+    )?;
+    Ok(summary(&builder, &built))
+}
+
+/// Builds a transfer to the positional `<address>:<quantity>` recipients.
+pub fn transfer(args: &Args, utxos: &[Utxo]) -> Result<Value> {
+    let asset_id = AssetId::from_str(args.required("asset-id")?)?;
+    let mut recipients = vec![];
+    for recipient in args.positional.iter() {
+        let (address, quantity) = recipient.split_once(':').ok_or_else(|| {
+            format!(
+                "invalid recipient {}, expected <address>:<quantity>",
+                recipient
+            )
+        })?;
+        recipients.push((
+            parse_address(address)?.script_pubkey(),
+            u64::from_str(quantity)?,
+        ));
+    }
+    let (builder, change) = builder(args)?;
+    let built = builder.transfer(utxos, &asset_id, &recipients, &change)?;
+    Ok(summary(&builder, &built))
+}
+
+/// Returns the builder configured by `--fee-rate` and `--network`, which
+/// defaults to the network of `--change`, with the change script.
+fn builder(args: &Args) -> Result<(TransactionBuilder, Script)> {
+    let change = parse_address(args.required("change")?)?;
+    let network = match args.option("network") {
+        Some(name) => parse_network(name)?,
+        None => change.network,
+    };
+    let mut builder = TransactionBuilder::new(network);
+    if let Some(fee_rate) = args.option("fee-rate") {
+        builder = builder.with_fee_rate(u64::from_str(fee_rate)?);
+    }
+    Ok((builder, change.script_pubkey()))
+}
+
+/// Describes the unsigned transaction with the coloring its outputs get once
+/// it is confirmed.
+fn summary(builder: &TransactionBuilder, built: &UnsignedTransaction) -> Value {
+    let outputs: Vec<Value> = color_outputs(&built.tx, &built.inputs, builder.network())
+        .iter()
+        .enumerate()
+        .map(|(i, o)| {
+            json!({
+                "index": i,
+                "value": o.value,
+                "script": hex::encode(o.script_pubkey.as_bytes()),
+                "asset_id": o.asset_id.as_ref().map(|id| id.to_string()),
+                "asset_quantity": o.asset_quantity,
+                "output_type": o.output_type.to_string(),
+            })
+        })
+        .collect();
+    let inputs: Vec<Value> = built
+        .tx
+        .input
+        .iter()
+        .map(|i| {
+            json!({
+                "txid": i.previous_output.txid.to_string(),
+                "vout": i.previous_output.vout,
+            })
+        })
+        .collect();
+    json!({
+        "hex": hex::encode(serialize(&built.tx)),
+        "txid": built.tx.txid().to_string(),
+        "fee": built.fee,
+        "inputs": inputs,
+        "outputs": outputs,
+    })
+}
+
+pub fn read_utxos(path: &str) -> Result<Vec<Utxo>> {
+    parse_utxos(&serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Parses a JSON array of outputs with their `txid`, `vout`, `value`, `script`
+/// (or `address`) and, for colored outputs, `asset_id` and `asset_quantity`.
+pub fn parse_utxos(value: &Value) -> Result<Vec<Utxo>> {
+    let entries = value
+        .as_array()
+        .ok_or("the UTXO file must contain an array")?;
+    let mut utxos = vec![];
+    for entry in entries {
+        let field = |name: &str| format!("invalid or missing {} in {}", name, entry);
+        let txid = entry["txid"]
+            .as_str()
+            .and_then(|s| Txid::from_hex(s).ok())
+            .ok_or_else(|| field("txid"))?;
+        let vout = entry["vout"].as_u64().ok_or_else(|| field("vout"))? as u32;
+        let value = entry["value"].as_u64().ok_or_else(|| field("value"))?;
+        let script = match (entry["script"].as_str(), entry["address"].as_str()) {
+            (Some(hex), _) => Script::from(hex::decode(hex)?),
+            (None, Some(address)) => parse_address(address)?.script_pubkey(),
+            _ => return Err(field("script").into()),
+        };
+        let mut output = ColoredOutput::uncolored(&TxOut {
+            value,
+            script_pubkey: script,
+        });
+        if let Some(asset_id) = entry["asset_id"].as_str() {
+            output.asset_id = Some(AssetId::from_str(asset_id)?);
+            output.asset_quantity = entry["asset_quantity"]
+                .as_u64()
+                .ok_or_else(|| field("asset_quantity"))?;
+            output.output_type = OutputType::Transfer;
+        }
+        utxos.push(Utxo {
+            outpoint: OutPoint::new(txid, vout),
+            output,
+        });
+    }
+    Ok(utxos)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::compose::{issue, parse_utxos, transfer};
+    use crate::parse_tx;
+    use serde_json::json;
+
+    const ISSUER_SCRIPT: &str = "76a914010966776006953d5567439e5e39f86a0d273bee88ac";
+    /// The dev and Open Assets addresses of the same key.
+    const ADDRESS: &str = "mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4";
+    const OA_ADDRESS: &str = "bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE";
+
+    fn txid(n: u8) -> String {
+        format!("{:02x}", n).repeat(32)
+    }
+
+    fn args(args: &[&str]) -> Args {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        Args::parse(&args, &[]).unwrap()
+    }
+
+    #[test]
+    fn test_issue() {
+        let utxos = parse_utxos(&json!([
+            {"txid": txid(1), "vout": 0, "value": 100_000, "script": ISSUER_SCRIPT},
+        ]))
+        .unwrap();
+        let value = issue(
+            &args(&[
+                "--to",
+                OA_ADDRESS,
+                "--quantity",
+                "500",
+                "--change",
+                ADDRESS,
+                "--metadata",
+                "u=https://example.com",
+            ]),
+            &utxos,
+        )
+        .unwrap();
+        let tx = parse_tx(value["hex"].as_str().unwrap()).unwrap();
+        assert_eq!(json!(tx.txid().to_string()), value["txid"]);
+        assert_eq!(json!("issuance"), value["outputs"][0]["output_type"]);
+        assert_eq!(json!(500), value["outputs"][0]["asset_quantity"]);
+        assert_eq!(json!("marker_output"), value["outputs"][1]["output_type"]);
+        // the change follows the marker output, as a transfer output without asset
+        assert_eq!(json!("transfer"), value["outputs"][2]["output_type"]);
+        assert!(value["outputs"][2]["asset_id"].is_null());
+
+        assert!(issue(&args(&["--to", OA_ADDRESS, "--quantity", "500"]), &utxos).is_err());
+    }
+
+    #[test]
+    fn test_transfer() {
+        // the asset issued by ISSUER_SCRIPT on the dev network
+        let issued = issue(
+            &args(&["--to", ADDRESS, "--quantity", "1", "--change", ADDRESS]),
+            &parse_utxos(&json!([
+                {"txid": txid(1), "vout": 0, "value": 100_000, "script": ISSUER_SCRIPT},
+            ]))
+            .unwrap(),
+        )
+        .unwrap();
+        let asset_id = issued["outputs"][0]["asset_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let utxos = parse_utxos(&json!([
+            {"txid": txid(1), "vout": 0, "value": 600, "address": OA_ADDRESS,
+             "asset_id": asset_id, "asset_quantity": 100},
+            {"txid": txid(2), "vout": 1, "value": 50_000, "address": ADDRESS},
+        ]))
+        .unwrap();
+        let value = transfer(
+            &args(&[
+                "--asset-id",
+                &asset_id,
+                "--change",
+                ADDRESS,
+                "--fee-rate",
+                "1000",
+                &format!("{}:30", OA_ADDRESS),
+            ]),
+            &utxos,
+        )
+        .unwrap();
+        let quantities: Vec<u64> = value["outputs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["asset_quantity"].as_u64().unwrap())
+            .collect();
+        assert_eq!(vec![0, 30, 70, 0], quantities);
+        assert_eq!(2, value["inputs"].as_array().unwrap().len());
+
+        let missing = args(&["--asset-id", &asset_id, "--change", ADDRESS, OA_ADDRESS]);
+        assert!(transfer(&missing, &utxos).is_err());
+        assert!(parse_utxos(&json!([{"txid": txid(1), "vout": 0}])).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::Write;
+
+use openassets_tapyrus::openassets::coloring::{output_roles, OutputType};
+use serde_json::{json, Value};
+use tapyrus::Transaction;
+
+use crate::args::Args;
+use crate::{read_tx, Result};
+
+pub fn run(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["json"])?;
+    let tx = read_tx(&args)?;
+    if args.flag("json") {
+        println!("{}", serde_json::to_string_pretty(&to_json(&tx))?);
+    } else {
+        print!("{}", to_text(&tx));
+    }
+    Ok(())
+}
+
+pub fn to_json(tx: &Transaction) -> Value {
+    let decoded = output_roles(tx);
+    let outputs: Vec<Value> = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(i, o)| {
+            let (role, quantity) = match decoded {
+                Some((_, _, ref roles)) => (roles[i].output_type, roles[i].quantity),
+                None => (OutputType::Uncolored, 0),
+            };
+            json!({
+                "index": i,
+                "value": o.value,
+                "script": hex::encode(o.script_pubkey.as_bytes()),
+                "role": role.to_string(),
+                "quantity": quantity,
+            })
+        })
+        .collect();
+    json!({
+        "txid": tx.txid().to_string(),
+        "marker_index": decoded.as_ref().map(|(marker, _, _)| *marker),
+        "quantities": decoded.as_ref().map(|(_, payload, _)| payload.quantities.clone()),
+        "metadata": decoded.as_ref().map(|(_, payload, _)| json!(payload.metadata)),
+        "outputs": outputs,
+    })
+}
+
+pub fn to_text(tx: &Transaction) -> String {
+    let value = to_json(tx);
+    let mut text = String::new();
+    writeln!(text, "txid: {}", value["txid"].as_str().unwrap_or_default()).unwrap();
+    match value["marker_index"].as_u64() {
+        Some(marker) => {
+            writeln!(text, "marker output: {}", marker).unwrap();
+            let quantities: Vec<String> = value["quantities"]
+                .as_array()
+                .map(|q| q.iter().map(Value::to_string).collect())
+                .unwrap_or_default();
+            writeln!(text, "quantities: [{}]", quantities.join(", ")).unwrap();
+            let metadata = &value["metadata"];
+            writeln!(
+                text,
+                "metadata (hex): {}",
+                metadata["hex"].as_str().unwrap_or_default()
+            )
+            .unwrap();
+            if let Some(utf8) = metadata["utf8"].as_str() {
+                writeln!(text, "metadata (utf8): {}", utf8).unwrap();
+            }
+        }
+        None => writeln!(text, "marker output: none").unwrap(),
+    }
+    writeln!(text, "outputs:").unwrap();
+    for output in value["outputs"].as_array().into_iter().flatten() {
+        writeln!(
+            text,
+            "  {} {:<13} quantity={} value={} script={}",
+            output["index"],
+            output["role"].as_str().unwrap_or_default(),
+            output["quantity"],
+            output["value"],
+            output["script"].as_str().unwrap_or_default(),
+        )
+        .unwrap();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::{to_json, to_text};
+    use crate::parse_tx;
+    use serde_json::json;
+
+    /// Issues 100 units to output 0 and transfers 123 units to output 3;
+    /// output 2 gets a zero quantity.
+    const TX: &str = "010000000100000000000000000000000000000000000000000000000000000000000000000000000000ffffffff0458020000000000001976a914010101010101010101010101010101010101010188ac0000000000000000266a244f4101000364007b1b753d68747470733a2f2f6370722e736d2f35596753553150672d7158020000000000001976a914020202020202020202020202020202020202020288ac58020000000000001976a914030303030303030303030303030303030303030388ac00000000";
+
+    #[test]
+    fn test_decode() {
+        let tx = parse_tx(TX).unwrap();
+        let value = to_json(&tx);
+        assert_eq!(json!(1), value["marker_index"]);
+        assert_eq!(json!([100, 0, 123]), value["quantities"]);
+        assert_eq!(
+            json!("u=https://cpr.sm/5YgSU1Pg-q"),
+            value["metadata"]["utf8"]
+        );
+        let roles: Vec<&str> = value["outputs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            vec!["issuance", "marker_output", "transfer", "transfer"],
+            roles
+        );
+        assert_eq!(json!(0), value["outputs"][2]["quantity"]);
+        assert_eq!(json!(123), value["outputs"][3]["quantity"]);
+
+        let text = to_text(&tx);
+        assert!(text.contains("marker output: 1\n"));
+        assert!(text.contains("quantities: [100, 0, 123]\n"));
+        assert!(text.contains("metadata (utf8): u=https://cpr.sm/5YgSU1Pg-q\n"));
+    }
+
+    #[test]
+    fn test_decode_without_marker() {
+        let mut tx = parse_tx(TX).unwrap();
+        tx.output.remove(1);
+        let value = to_json(&tx);
+        assert!(value["marker_index"].is_null());
+        assert_eq!(json!("uncolored"), value["outputs"][2]["role"]);
+        assert!(to_text(&tx).contains("marker output: none\n"));
+        assert!(parse_tx("zz").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! `oa`: command-line tools for Open Assets on Tapyrus.
+
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+use std::str::FromStr;
+
+use tapyrus::consensus::deserialize;
+use tapyrus::network::constants::Network;
+use tapyrus::Transaction;
+
+use openassets_tapyrus::openassets::address::Address;
+
+mod address;
+mod args;
+mod asset_id;
+mod color;
+mod compose;
+mod decode;
+
+use args::Args;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const USAGE: &str = "usage: oa <command> [options]
+
+commands:
+  decode [<txhex> | --file <path> | -] [--json]
+      print the marker output and the role of each output of a raw transaction
+  address to-oa|to-tapyrus <address> [--json]
+      convert between Tapyrus and Open Assets addresses
+  asset-id (--script <hex> | --address <address>) [--network prod|dev] [--json]
+      compute the asset id issued by a script or address
+  issue --utxos <file> --to <address> --quantity <n> --change <address>
+        [--metadata <text>] [--fee-rate <n>] [--network prod|dev]
+      build an unsigned issuance transaction
+  transfer --utxos <file> --asset-id <id> --change <address> <address>:<quantity>...
+        [--fee-rate <n>] [--network prod|dev]
+      build an unsigned transfer transaction
+  color [<txhex> | --file <path> | -] [--prev-txs <bundle>] [--network prod|dev] [--json]
+      color the outputs of a transaction using only the previous transactions of a bundle";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("decode") => decode::run(&args[1..]),
+        Some("address") => address::run(&args[1..]),
+        Some("asset-id") => asset_id::run(&args[1..]),
+        Some("issue") => compose::run_issue(&args[1..]),
+        Some("transfer") => compose::run_transfer(&args[1..]),
+        Some("color") => color::run(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command {}\n\n{}", command, USAGE).into()),
+        None => Err(USAGE.into()),
+    };
+    if let Err(e) = result {
+        eprintln!("oa: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Reads the hex of a raw transaction from the first positional argument, the
+/// file given by `--file`, or stdin when neither is given or the argument is `-`.
+pub fn read_tx(args: &Args) -> Result<Transaction> {
+    let hex = match (
+        args.positional.first().map(String::as_str),
+        args.option("file"),
+    ) {
+        (Some("-"), _) | (None, None) => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        (Some(hex), _) => hex.to_string(),
+        (None, Some(path)) => fs::read_to_string(path)?,
+    };
+    parse_tx(&hex)
+}
+
+pub fn parse_tx(hex: &str) -> Result<Transaction> {
+    let bytes = hex::decode(hex.trim()).map_err(|e| format!("invalid transaction hex: {}", e))?;
+    Ok(deserialize(&bytes)?)
+}
+
+/// Parses an Open Assets or Tapyrus address.
+pub fn parse_address(s: &str) -> Result<tapyrus::Address> {
+    match Address::from_str(s) {
+        Ok(oa_address) => Ok(oa_address.to_btc_addr()?),
+        Err(_) => Ok(tapyrus::Address::from_str(s)?),
+    }
+}
+
+pub fn parse_network(name: &str) -> Result<Network> {
+    match name {
+        "prod" => Ok(Network::Prod),
+        "dev" => Ok(Network::Dev),
+        _ => Err(format!("unknown network {}, expected prod or dev", name).into()),
+    }
+}
+
+pub fn network_name(network: Network) -> &'static str {
+    match network {
+        Network::Prod => "prod",
+        Network::Dev => "dev",
+    }
+}
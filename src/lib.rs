@@ -4,5 +4,11 @@ extern crate hex;
 extern crate serde;
 extern crate tapyrus;
 extern crate serde_json;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "tls")]
+extern crate native_tls;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 
 pub mod openassets;
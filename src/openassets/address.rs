@@ -1,25 +1,57 @@
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 use tapyrus::consensus::encode;
 use tapyrus::hashes::hex::FromHex;
 use tapyrus::network::constants::Network;
 use tapyrus::util::address::Payload;
 use tapyrus::util::base58;
 
+use crate::openassets::network::{self, NetworkParams};
+
 /// A Open Assets Address
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Address {
     pub network: Network,
     pub payload: Payload,
+    params: &'static NetworkParams,
 }
 
-const NAMESPACE: u8 = 0x13;
-
 impl Address {
     pub fn new(
         payload: Payload,
         network: tapyrus::network::constants::Network,
     ) -> Result<Self, encode::Error> {
-        Ok(Address { payload, network })
+        Address::with_params(payload, NetworkParams::tapyrus(network))
+    }
+
+    /// Fails if the chain of `params` has no address for `payload`.
+    pub fn with_params(
+        payload: Payload,
+        params: &'static NetworkParams,
+    ) -> Result<Self, encode::Error> {
+        match params.address_prefix(&payload) {
+            Some(_) => Ok(Address {
+                network: params.network,
+                payload,
+                params,
+            }),
+            None => Err(encode::Error::ParseFailed(
+                "payload not supported by the network",
+            )),
+        }
+    }
+
+    /// The parameters the address is encoded with: those it was created
+    /// with, or the Tapyrus preset of `network` once the public fields were
+    /// changed to something they can not encode.
+    pub fn params(&self) -> &'static NetworkParams {
+        if self.params.network == self.network
+            && self.params.address_prefix(&self.payload).is_some()
+        {
+            self.params
+        } else {
+            NetworkParams::tapyrus(self.network)
+        }
     }
 
     pub fn to_btc_addr(&self) -> Result<tapyrus::Address, encode::Error> {
@@ -28,54 +60,71 @@ impl Address {
             payload: self.payload.clone(),
         })
     }
+
+    /// Parses an Open Assets address encoded with `params`.
+    ///
+    /// The address prefix is mapped to the Tapyrus prefix of the same payload
+    /// kind and the rest is parsed as a Tapyrus address.
+    pub fn from_str_with_params(
+        s: &str,
+        params: &'static NetworkParams,
+    ) -> Result<Address, base58::Error> {
+        let mut data = base58::from_check(s)?;
+        if data.len() < 2 {
+            return Err(base58::Error::TooShort(data.len()));
+        }
+        if data[0] != params.oa_namespace {
+            return Err(base58::Error::InvalidVersion(vec![data[0]]));
+        }
+        data[1] = params
+            .tapyrus_prefix(data[1])
+            .ok_or_else(|| base58::Error::InvalidVersion(vec![data[1]]))?;
+        let address = tapyrus::Address::from_str(&base58::check_encode_slice(&data[1..]))
+            .map_err(|_| base58::Error::InvalidVersion(vec![data[1]]))?;
+        Ok(Address {
+            network: params.network,
+            payload: address.payload,
+            params,
+        })
+    }
 }
 
 impl Display for Address {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let params = self.params();
+        // the Tapyrus presets have a prefix for every payload
+        let prefix = params.address_prefix(&self.payload).unwrap();
+        let mut prefixed = vec![params.oa_namespace, prefix];
         match self.payload {
-            Payload::PubkeyHash(ref hash) => {
-                let mut prefixed = [0; 22];
-                prefixed[0] = NAMESPACE;
-                prefixed[1] = match self.network {
-                    Network::Prod => 0,
-                    Network::Dev => 111,
-                };
-                prefixed[2..].copy_from_slice(&hash[..]);
-                base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
-            }
-            Payload::ScriptHash(ref hash) => {
-                let mut prefixed = [0; 22];
-                prefixed[0] = NAMESPACE;
-                prefixed[1] = match self.network {
-                    Network::Prod => 5,
-                    Network::Dev => 196,
-                };
-                prefixed[2..].copy_from_slice(&hash[..]);
-                base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
-            }
+            Payload::PubkeyHash(ref hash) => prefixed.extend_from_slice(&hash[..]),
+            Payload::ScriptHash(ref hash) => prefixed.extend_from_slice(&hash[..]),
             Payload::ColoredPubkeyHash(ref color_id, ref hash) => {
-                let mut prefixed = [0; 55];
-                prefixed[0] = NAMESPACE;
-                prefixed[1] = match self.network {
-                    Network::Prod => 1,
-                    Network::Dev => 112,
-                };
-                prefixed[2..35].copy_from_slice(&Vec::from_hex(&format!("{}", color_id)).unwrap());
-                prefixed[35..].copy_from_slice(&hash[..]);
-                base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
+                prefixed.extend(Vec::from_hex(&format!("{}", color_id)).unwrap());
+                prefixed.extend_from_slice(&hash[..]);
             }
             Payload::ColoredScriptHash(ref color_id, ref hash) => {
-                let mut prefixed = [0; 55];
-                prefixed[0] = NAMESPACE;
-                prefixed[1] = match self.network {
-                    Network::Prod => 6,
-                    Network::Dev => 197,
-                };
-                prefixed[2..35].copy_from_slice(&Vec::from_hex(&format!("{}", color_id)).unwrap());
-                prefixed[35..].copy_from_slice(&hash[..]);
-                base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
+                prefixed.extend(Vec::from_hex(&format!("{}", color_id)).unwrap());
+                prefixed.extend_from_slice(&hash[..]);
+            }
+        }
+        base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
+    }
+}
+
+impl FromStr for Address {
+    type Err = base58::Error;
+
+    /// Parses an Open Assets address of the first preset or registered
+    /// network it is valid on.
+    fn from_str(s: &str) -> Result<Address, base58::Error> {
+        let mut error = base58::Error::TooShort(0);
+        for params in network::all() {
+            match Address::from_str_with_params(s, params) {
+                Ok(address) => return Ok(address),
+                Err(e) => error = e,
             }
         }
+        Err(error)
     }
 }
 
@@ -91,7 +140,8 @@ impl OAAddressConverter for tapyrus::Address {
 
 #[cfg(test)]
 mod tests {
-    use crate::openassets::address::OAAddressConverter;
+    use crate::openassets::address::{Address, OAAddressConverter};
+    use crate::openassets::network::{BITCOIN, BITCOIN_TESTNET, TAPYRUS_DEV, TAPYRUS_PROD};
     use std::str::FromStr;
     use std::string::ToString;
     use tapyrus::network::constants::Network;
@@ -210,4 +260,62 @@ mod tests {
             dev_addr.to_oa_address().unwrap().to_btc_addr().unwrap()
         );
     }
+
+    #[test]
+    fn test_parse_oa_address() {
+        for s in [
+            "akQz3f1v9JrnJAeGBC4pNzGNRdWXKan4U6E",
+            "bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE",
+            "anQin2TDYaubr6M5MQM8kNXMitHc2hsmfGc",
+            "c7GGz6C9aCN7CJ8hu5UkczULz6dpCWSBVnF",
+            "mJkjc5fgLN5sbo5FHJDj5M5YuhmRYNS8D8A5EFg4tRuohzLfNCNf4L1k7xBRm46mReKxkaUnpZutQyeJ",
+            "o3XMFv4SNCnicQR2RPKt8cVbxV9D96eqHFPCqjSa7qg12rJmJZf6p1XT1e1mToXuAcHaoPQKQ4w1AmkL",
+        ] {
+            assert_eq!(s, Address::from_str(s).unwrap().to_string());
+        }
+        let addr = Address::from_str("bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE").unwrap();
+        assert_eq!(Network::Dev, addr.network);
+        assert_eq!(
+            "mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4",
+            addr.to_btc_addr().unwrap().to_string()
+        );
+
+        // a Tapyrus address lacks the namespace
+        assert!(Address::from_str("1F2AQr6oqNtcJQ6p9SiCLQTrHuM9en44H8").is_err());
+        assert!(Address::from_str("akQz3f1v9JrnJAeGBC4pNzGNRdWXKan4U6F").is_err());
+    }
+
+    #[test]
+    fn test_oa_address_with_params() {
+        let p2pkh = tapyrus::Address::from_str("1F2AQr6oqNtcJQ6p9SiCLQTrHuM9en44H8").unwrap();
+        let bitcoin = Address::with_params(p2pkh.payload.clone(), &BITCOIN).unwrap();
+        assert_eq!("akQz3f1v9JrnJAeGBC4pNzGNRdWXKan4U6E", bitcoin.to_string());
+        assert!(
+            bitcoin
+                == Address::from_str_with_params("akQz3f1v9JrnJAeGBC4pNzGNRdWXKan4U6E", &BITCOIN)
+                    .unwrap()
+        );
+        assert!(Address::from_str_with_params(
+            "akQz3f1v9JrnJAeGBC4pNzGNRdWXKan4U6E",
+            &BITCOIN_TESTNET
+        )
+        .is_err());
+        let address = Address::from_str("akQz3f1v9JrnJAeGBC4pNzGNRdWXKan4U6E").unwrap();
+        assert_eq!(&TAPYRUS_PROD, address.params());
+
+        // Bitcoin has no colored addresses
+        let cp2pkh =
+            "mJkjc5fgLN5sbo5FHJDj5M5YuhmRYNS8D8A5EFg4tRuohzLfNCNf4L1k7xBRm46mReKxkaUnpZutQyeJ";
+        let payload = Address::from_str(cp2pkh).unwrap().payload;
+        assert!(Address::with_params(payload.clone(), &BITCOIN).is_err());
+        assert!(Address::from_str_with_params(cp2pkh, &BITCOIN).is_err());
+
+        // changing the public fields falls back to the Tapyrus presets
+        let mut changed = bitcoin.clone();
+        changed.payload = payload;
+        assert_eq!(&TAPYRUS_PROD, changed.params());
+        assert_eq!(cp2pkh, changed.to_string());
+        changed.network = Network::Dev;
+        assert_eq!(&TAPYRUS_DEV, changed.params());
+    }
 }
@@ -1,31 +1,73 @@
 use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 use tapyrus::hashes::{hash160, Hash};
+use tapyrus::network::constants::Network;
 use tapyrus::util::base58;
 use tapyrus::Script;
 
+use crate::openassets::network::{self, NetworkParams};
+
+/// An Open Assets asset id.
+///
+/// Assets of different networks are different assets, even when their ids
+/// encode to the same string, so equality also compares the parameters.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct AssetId {
     pub hash: hash160::Hash,
-    pub network: tapyrus::network::constants::Network,
+    pub network: Network,
+    params: &'static NetworkParams,
 }
 
 impl AssetId {
-    pub fn new(script: &Script, network: tapyrus::network::constants::Network) -> AssetId {
+    pub fn new(script: &Script, network: Network) -> AssetId {
+        AssetId::with_params(script, NetworkParams::tapyrus(network))
+    }
+
+    pub fn with_params(script: &Script, params: &'static NetworkParams) -> AssetId {
+        AssetId::from_hash(hash160::Hash::hash(&script.to_bytes()), params)
+    }
+
+    pub(crate) fn from_hash(hash: hash160::Hash, params: &'static NetworkParams) -> AssetId {
         AssetId {
-            hash: hash160::Hash::hash(&script.to_bytes()),
-            network,
+            hash,
+            network: params.network,
+            params,
+        }
+    }
+
+    /// The parameters the asset id is encoded with: those it was created
+    /// with, or the Tapyrus preset of `network` once that was changed.
+    pub fn params(&self) -> &'static NetworkParams {
+        if self.params.network == self.network {
+            self.params
+        } else {
+            NetworkParams::tapyrus(self.network)
         }
     }
+
+    /// Parses an asset id encoded with `params`.
+    pub fn from_str_with_params(
+        s: &str,
+        params: &'static NetworkParams,
+    ) -> Result<AssetId, base58::Error> {
+        let data = base58::from_check(s)?;
+        if data.len() != 21 {
+            return Err(base58::Error::InvalidLength(data.len()));
+        }
+        if data[0] != params.asset_id_version {
+            return Err(base58::Error::InvalidVersion(vec![data[0]]));
+        }
+        Ok(AssetId::from_hash(
+            hash160::Hash::from_slice(&data[1..]).unwrap(),
+            params,
+        ))
+    }
 }
 
 impl Display for AssetId {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
         let mut prefixed = [0; 21];
-        prefixed[0] = match self.network {
-            tapyrus::network::constants::Network::Prod => 0x17,
-            tapyrus::network::constants::Network::Dev => 0x73,
-        };
+        prefixed[0] = self.params().asset_id_version;
         prefixed[1..].copy_from_slice(&self.hash[..]);
         base58::check_encode_slice_to_fmt(fmt, &prefixed[..])
     }
@@ -34,29 +76,27 @@ impl Display for AssetId {
 impl FromStr for AssetId {
     type Err = base58::Error;
 
+    /// Parses an asset id of the first preset or registered network with a
+    /// matching version byte.
     fn from_str(s: &str) -> Result<AssetId, base58::Error> {
         let data = base58::from_check(s)?;
-        let (network, hash) = match data[0] {
-            0x17 => (
-                tapyrus::network::constants::Network::Prod,
-                hash160::Hash::from_slice(&data[1..]).unwrap(),
-            ),
-            0x73 => (
-                tapyrus::network::constants::Network::Dev,
-                hash160::Hash::from_slice(&data[1..]).unwrap(),
-            ),
-            x => return Err(base58::Error::InvalidVersion(vec![x])),
-        };
-        Ok(AssetId { hash, network })
+        let version = *data.first().ok_or(base58::Error::TooShort(0))?;
+        let params = network::all()
+            .into_iter()
+            .find(|p| p.asset_id_version == version)
+            .ok_or_else(|| base58::Error::InvalidVersion(vec![version]))?;
+        AssetId::from_str_with_params(s, params)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use hex::decode as hex_decode;
     use crate::openassets::asset_id::AssetId;
+    use crate::openassets::network::{BITCOIN, BITCOIN_TESTNET, TAPYRUS_DEV, TAPYRUS_PROD};
+    use hex::decode as hex_decode;
     use std::str::FromStr;
     use tapyrus::blockdata::script::Builder;
+    use tapyrus::network::constants::Network;
 
     #[test]
     fn test_calculate_asset_id() {
@@ -87,4 +127,35 @@ mod tests {
             testnet_asset
         );
     }
+
+    #[test]
+    fn test_asset_id_with_params() {
+        let script = Builder::from(
+            hex_decode("76a914010966776006953d5567439e5e39f86a0d273bee88ac").unwrap(),
+        )
+        .into_script();
+        let bitcoin = AssetId::with_params(&script, &BITCOIN);
+        assert_eq!("ALn3aK1fSuG27N96UGYB1kUYUpGKRhBuBC", bitcoin.to_string());
+        assert_ne!(AssetId::new(&script, Network::Prod), bitcoin);
+        assert_eq!(
+            bitcoin,
+            AssetId::from_str_with_params("ALn3aK1fSuG27N96UGYB1kUYUpGKRhBuBC", &BITCOIN).unwrap()
+        );
+        assert_eq!(
+            &TAPYRUS_PROD,
+            AssetId::from_str("ALn3aK1fSuG27N96UGYB1kUYUpGKRhBuBC")
+                .unwrap()
+                .params()
+        );
+        assert!(AssetId::from_str_with_params(
+            "ALn3aK1fSuG27N96UGYB1kUYUpGKRhBuBC",
+            &BITCOIN_TESTNET
+        )
+        .is_err());
+
+        let mut changed = bitcoin.clone();
+        changed.network = Network::Dev;
+        assert_eq!(&TAPYRUS_DEV, changed.params());
+        assert_eq!(AssetId::new(&script, Network::Dev).to_string(), changed.to_string());
+    }
 }
@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+use futures::stream::{self, StreamExt};
+use tapyrus::network::constants::Network;
+use tapyrus::{OutPoint, Transaction, Txid};
+
+use crate::openassets::coloring::{needs_inputs_with, ColoredOutput, Colorer, Error};
+use crate::openassets::marker_output::MarkerVersions;
+use crate::openassets::network::NetworkParams;
+
+/// The default number of transactions fetched concurrently.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Provides previous transactions to the `AsyncColorer`.
+pub trait AsyncTxSource {
+    fn get_transaction(
+        &self,
+        txid: &Txid,
+    ) -> impl Future<Output = Result<Transaction, Error>> + Send;
+}
+
+impl<T: AsyncTxSource + Sync + ?Sized> AsyncTxSource for &T {
+    fn get_transaction(
+        &self,
+        txid: &Txid,
+    ) -> impl Future<Output = Result<Transaction, Error>> + Send {
+        (**self).get_transaction(txid)
+    }
+}
+
+/// Colors transactions by fetching their ancestry from an `AsyncTxSource`.
+///
+/// The ancestry is fetched level by level, fetching independent ancestors
+/// concurrently, and then colored by the synchronous `Colorer`, so that the
+/// results are identical to the synchronous path.
+pub struct AsyncColorer<S: AsyncTxSource> {
+    source: S,
+    concurrency: usize,
+    colorer: Colorer<HashMap<Txid, Transaction>>,
+}
+
+impl<S: AsyncTxSource> AsyncColorer<S> {
+    pub fn new(source: S, network: Network) -> Self {
+        AsyncColorer::with_params(source, NetworkParams::tapyrus(network))
+    }
+
+    /// Colors with the asset ids of `params`, e.g. of a Bitcoin network.
+    pub fn with_params(source: S, params: &'static NetworkParams) -> Self {
+        AsyncColorer {
+            source,
+            concurrency: DEFAULT_CONCURRENCY,
+            colorer: Colorer::with_params(HashMap::new(), params),
+        }
+    }
+
+    /// See `Colorer::with_marker_versions`.
+    pub fn with_marker_versions(mut self, versions: MarkerVersions) -> Self {
+        self.colorer = self.colorer.with_marker_versions(versions);
+        self
+    }
+
+    /// Sets the maximum number of transactions fetched at the same time.
+    pub fn with_concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = std::cmp::max(limit, 1);
+        self
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Returns the colored output referenced by `outpoint`.
+    pub async fn get_output(&mut self, outpoint: &OutPoint) -> Result<ColoredOutput, Error> {
+        let tx = match self.colorer.source().get(&outpoint.txid) {
+            Some(tx) => tx.clone(),
+            None => self.source.get_transaction(&outpoint.txid).await?,
+        };
+        self.color_transaction(&tx)
+            .await?
+            .get(outpoint.vout as usize)
+            .cloned()
+            .ok_or(Error::OutputNotFound(*outpoint))
+    }
+
+    /// Colors all outputs of `tx`.
+    pub async fn color_transaction(
+        &mut self,
+        tx: &Transaction,
+    ) -> Result<Vec<ColoredOutput>, Error> {
+        self.fetch_ancestry(tx).await?;
+        self.colorer.color_transaction(tx)
+    }
+
+    /// Fetches every ancestor needed to color `tx` that is not known yet.
+    async fn fetch_ancestry(&mut self, tx: &Transaction) -> Result<(), Error> {
+        let mut frontier = self.unknown_inputs(tx, &HashSet::new());
+        while !frontier.is_empty() {
+            let source = &self.source;
+            let results: Vec<Result<Transaction, Error>> = stream::iter(frontier)
+                .map(|txid| async move { source.get_transaction(&txid).await })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+
+            let mut next = HashSet::new();
+            let mut fetched = Vec::with_capacity(results.len());
+            for result in results {
+                let prev_tx = result?;
+                next.extend(self.unknown_inputs(&prev_tx, &next));
+                fetched.push(prev_tx);
+            }
+            let known = self.colorer.source_mut();
+            for prev_tx in fetched {
+                known.insert(prev_tx.txid(), prev_tx);
+            }
+            frontier = next
+                .into_iter()
+                .filter(|txid| !self.colorer.source().contains_key(txid))
+                .collect();
+        }
+        Ok(())
+    }
+
+    fn unknown_inputs(&self, tx: &Transaction, pending: &HashSet<Txid>) -> HashSet<Txid> {
+        if !needs_inputs_with(tx, self.colorer.marker_versions()) {
+            return HashSet::new();
+        }
+        tx.input
+            .iter()
+            .map(|input| input.previous_output.txid)
+            .filter(|txid| !self.colorer.source().contains_key(txid) && !pending.contains(txid))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::async_coloring::{AsyncColorer, AsyncTxSource};
+    use crate::openassets::coloring::{Colorer, Error};
+    use crate::openassets::test_helpers::*;
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tapyrus::network::constants::Network;
+    use tapyrus::{OutPoint, Transaction, Txid};
+
+    struct SlowSource {
+        txs: HashMap<Txid, Transaction>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        fetched: AtomicUsize,
+    }
+
+    impl AsyncTxSource for SlowSource {
+        fn get_transaction(
+            &self,
+            txid: &Txid,
+        ) -> impl Future<Output = Result<Transaction, Error>> + Send {
+            let txid = *txid;
+            async move {
+                let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(current, Ordering::SeqCst);
+                self.fetched.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                self.txs
+                    .get(&txid)
+                    .cloned()
+                    .ok_or(Error::TransactionNotFound(txid))
+            }
+        }
+    }
+
+    /// Builds a transfer merging assets issued by `width` independent issuances.
+    fn fixture(width: u8) -> (HashMap<Txid, Transaction>, Transaction) {
+        let mut txs = HashMap::new();
+        let mut issued = vec![];
+        for i in 0..width {
+            let funding = funding_tx(i as u32, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+            let issuance = tx(
+                &[OutPoint::new(funding.txid(), 0)],
+                vec![txout(600, p2pkh(i)), marker(vec![10], b"")],
+            );
+            issued.push(OutPoint::new(issuance.txid(), 0));
+            txs.insert(funding.txid(), funding);
+            txs.insert(issuance.txid(), issuance);
+        }
+        let transfer = tx(
+            &issued,
+            vec![
+                marker(vec![10 * width as u64 - 1], b""),
+                txout(600, p2pkh(100)),
+            ],
+        );
+        txs.insert(transfer.txid(), transfer.clone());
+        (txs, transfer)
+    }
+
+    #[tokio::test]
+    async fn test_matches_sync_colorer() {
+        let (txs, transfer) = fixture(6);
+        let mut sync_colorer = Colorer::new(&txs, Network::Prod);
+        let expected = sync_colorer.color_transaction(&transfer).unwrap();
+
+        let source = SlowSource {
+            txs,
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+            fetched: AtomicUsize::new(0),
+        };
+        let mut colorer = AsyncColorer::new(&source, Network::Prod).with_concurrency(4);
+        assert_eq!(
+            expected,
+            colorer.color_transaction(&transfer).await.unwrap()
+        );
+        assert_eq!(59, expected[1].asset_quantity);
+
+        let max = source.max_in_flight.load(Ordering::SeqCst);
+        assert!(max > 1 && max <= 4, "max in flight: {}", max);
+        assert_eq!(12, source.fetched.load(Ordering::SeqCst));
+
+        // already fetched ancestry is not fetched again
+        let output = colorer
+            .get_output(&OutPoint::new(transfer.txid(), 1))
+            .await
+            .unwrap();
+        assert_eq!(expected[1], output);
+        assert_eq!(13, source.fetched.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_missing_ancestor() {
+        let (mut txs, transfer) = fixture(2);
+        let missing = transfer.input[1].previous_output.txid;
+        txs.remove(&missing);
+        let source = SlowSource {
+            txs,
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+            fetched: AtomicUsize::new(0),
+        };
+        let mut colorer = AsyncColorer::new(&source, Network::Prod).with_concurrency(1);
+        match colorer.color_transaction(&transfer).await {
+            Err(Error::TransactionNotFound(txid)) => assert_eq!(missing, txid),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert_eq!(1, source.max_in_flight.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use tapyrus::blockdata::constants::MAX_BLOCK_WEIGHT;
+use tapyrus::consensus::{deserialize, encode, Decodable};
+use tapyrus::{Block, BlockHash, BlockHeader, Transaction};
+
+use crate::openassets::marker_output::TxOutExt;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Encode(encode::Error),
+    /// A record does not start with the expected network magic.
+    InvalidMagic(PathBuf, [u8; 4]),
+    /// A record claims a size larger than `MAX_RECORD_SIZE`.
+    OversizedRecord(PathBuf, u32),
+}
+
+/// The largest block record read. A serialized block is never larger than
+/// its weight.
+pub const MAX_RECORD_SIZE: u32 = MAX_BLOCK_WEIGHT;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Encode(ref e) => write!(f, "block decode error: {}", e),
+            Error::InvalidMagic(ref path, magic) => write!(
+                f,
+                "invalid magic {} in {}",
+                hex::encode(magic),
+                path.display()
+            ),
+            Error::OversizedRecord(ref path, len) => write!(
+                f,
+                "record of {} bytes in {} exceeds the maximum block size",
+                len,
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+/// Iterates over the blocks stored in a single block file.
+///
+/// Each record is the network magic, the block size as a little endian u32 and
+/// the serialized block. Reading stops at the zero padding Tapyrus Core leaves
+/// at the end of preallocated files.
+pub struct BlockFileIter<R: Read> {
+    reader: R,
+    path: PathBuf,
+    magic: [u8; 4],
+    done: bool,
+}
+
+impl BlockFileIter<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P, magic: [u8; 4]) -> Result<Self, Error> {
+        let file = File::open(path.as_ref())?;
+        Ok(BlockFileIter::new(
+            BufReader::new(file),
+            path.as_ref().to_path_buf(),
+            magic,
+        ))
+    }
+}
+
+impl<R: Read> BlockFileIter<R> {
+    pub fn new(reader: R, path: PathBuf, magic: [u8; 4]) -> Self {
+        BlockFileIter {
+            reader,
+            path,
+            magic,
+            done: false,
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Block>, Error> {
+        let len = match read_record_size(&mut self.reader, &self.path, self.magic)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let mut data = vec![0u8; len as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(deserialize(&data)?))
+    }
+}
+
+/// Reads the magic and the size of the next record, or `None` at the end of
+/// the file or of its records.
+fn read_record_size<R: Read>(
+    reader: &mut R,
+    path: &Path,
+    magic: [u8; 4],
+) -> Result<Option<u32>, Error> {
+    let mut found = [0u8; 4];
+    match reader.read_exact(&mut found) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(Error::Io(e)),
+    }
+    if found == [0; 4] {
+        return Ok(None);
+    }
+    if found != magic {
+        return Err(Error::InvalidMagic(path.to_path_buf(), found));
+    }
+    let len = reader.read_u32::<LittleEndian>()?;
+    if len > MAX_RECORD_SIZE {
+        return Err(Error::OversizedRecord(path.to_path_buf(), len));
+    }
+    Ok(Some(len))
+}
+
+/// The location of a block record in a block file.
+#[derive(Debug, Clone)]
+struct Record {
+    header: BlockHeader,
+    file: usize,
+    /// The position of the serialized block in the file.
+    offset: u64,
+    len: u32,
+}
+
+/// Reads the headers of the blocks in the file at `path`, skipping their
+/// transactions.
+fn index_file(path: &Path, file: usize, magic: [u8; 4]) -> Result<Vec<Record>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let file_len = reader.get_ref().metadata()?.len();
+    let mut records = vec![];
+    let mut offset = 0;
+    while let Some(len) = read_record_size(&mut reader, path, magic)? {
+        offset += 8;
+        if offset + u64::from(len) > file_len {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let mut record = (&mut reader).take(u64::from(len));
+        let header = BlockHeader::consensus_decode(&mut record)?;
+        let rest = record.limit();
+        reader.seek_relative(rest as i64)?;
+        records.push(Record {
+            header,
+            file,
+            offset,
+            len,
+        });
+        offset += u64::from(len);
+    }
+    Ok(records)
+}
+
+impl<R: Read> Iterator for BlockFileIter<R> {
+    type Item = Result<Block, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.read_record();
+        if !matches!(record, Ok(Some(_))) {
+            self.done = true;
+        }
+        record.transpose()
+    }
+}
+
+/// Returns the `blk*.dat` files in `dir` in file number order.
+pub fn block_files<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, Error> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_block_file = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("blk") && name.ends_with(".dat"));
+        if is_block_file {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Orders `blocks` along the longest chain found by following prev hashes.
+///
+/// Blocks which are not on that chain (stale forks, orphans) are dropped.
+pub fn order_by_chain(blocks: Vec<Block>) -> Vec<Block> {
+    let headers: Vec<&BlockHeader> = blocks.iter().map(|b| &b.header).collect();
+    let order = chain_order(&headers);
+    let mut blocks: Vec<Option<Block>> = blocks.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .map(|i| blocks[i].take().unwrap())
+        .collect()
+}
+
+/// Returns the indexes of `headers` along the longest chain, oldest first.
+fn chain_order(headers: &[&BlockHeader]) -> Vec<usize> {
+    let mut by_hash: HashMap<BlockHash, usize> = HashMap::new();
+    let mut order: Vec<BlockHash> = vec![];
+    for (i, header) in headers.iter().enumerate() {
+        let hash = header.block_hash();
+        if let Entry::Vacant(entry) = by_hash.entry(hash) {
+            entry.insert(i);
+            order.push(hash);
+        }
+    }
+
+    // heights relative to the first block whose parent is not in the files
+    let mut heights: HashMap<BlockHash, u64> = HashMap::new();
+    for hash in order.iter() {
+        let mut path = vec![];
+        let mut current = *hash;
+        let mut height = loop {
+            if let Some(&h) = heights.get(&current) {
+                break Some(h);
+            }
+            path.push(current);
+            let prev = headers[by_hash[&current]].prev_blockhash;
+            if !by_hash.contains_key(&prev) {
+                break None;
+            }
+            current = prev;
+        };
+        for h in path.into_iter().rev() {
+            let next = height.map_or(0, |height| height + 1);
+            heights.insert(h, next);
+            height = Some(next);
+        }
+    }
+
+    let tip = order
+        .iter()
+        .fold(None, |best: Option<&BlockHash>, hash| match best {
+            Some(b) if heights[b] >= heights[hash] => Some(b),
+            _ => Some(hash),
+        });
+    let mut chain = vec![];
+    let mut current = tip.cloned();
+    while let Some(hash) = current {
+        let i = by_hash.remove(&hash).unwrap();
+        let prev = headers[i].prev_blockhash;
+        current = if by_hash.contains_key(&prev) {
+            Some(prev)
+        } else {
+            None
+        };
+        chain.push(i);
+    }
+    chain.reverse();
+    chain
+}
+
+/// Returns true if one of the outputs of `tx` is an Open Assets marker output.
+pub fn has_marker(tx: &Transaction) -> bool {
+    tx.output.iter().any(|o| o.is_openassets_marker())
+}
+
+/// Reads the blocks of a Tapyrus Core blocks directory in chain order.
+///
+/// Only the headers are kept in memory; blocks are read from their file
+/// when iterated over.
+pub struct BlockScanner {
+    files: Vec<PathBuf>,
+    chain: Vec<Record>,
+}
+
+impl BlockScanner {
+    /// Indexes every `blk*.dat` file in `dir`, expecting records with `magic`.
+    pub fn open<P: AsRef<Path>>(dir: P, magic: [u8; 4]) -> Result<Self, Error> {
+        let files = block_files(dir)?;
+        let mut records = vec![];
+        for (file, path) in files.iter().enumerate() {
+            records.extend(index_file(path, file, magic)?);
+        }
+        let headers: Vec<&BlockHeader> = records.iter().map(|r| &r.header).collect();
+        let chain = chain_order(&headers)
+            .into_iter()
+            .map(|i| records[i].clone())
+            .collect();
+        Ok(BlockScanner { files, chain })
+    }
+
+    /// The number of blocks in the chain.
+    pub fn len(&self) -> usize {
+        self.chain.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+
+    /// The headers of the chain, starting with the oldest one.
+    pub fn headers(&self) -> impl Iterator<Item = &BlockHeader> {
+        self.chain.iter().map(|r| &r.header)
+    }
+
+    /// Reads the blocks of the chain, starting with the oldest one.
+    pub fn blocks(&self) -> Blocks<'_> {
+        Blocks {
+            scanner: self,
+            next: 0,
+            open: None,
+        }
+    }
+
+    /// Iterates over the transactions containing a valid marker output, with
+    /// the height of their block relative to the first block read.
+    pub fn oa_transactions(&self) -> impl Iterator<Item = Result<(usize, Transaction), Error>> {
+        self.blocks()
+            .enumerate()
+            .flat_map(|(height, block)| match block {
+                Ok(block) => block
+                    .txdata
+                    .into_iter()
+                    .filter(has_marker)
+                    .map(|tx| Ok((height, tx)))
+                    .collect(),
+                Err(e) => vec![Err(e)],
+            })
+    }
+}
+
+/// Reads the blocks indexed by a `BlockScanner` one at a time.
+pub struct Blocks<'a> {
+    scanner: &'a BlockScanner,
+    next: usize,
+    /// The last file read from, kept open for the following blocks.
+    open: Option<(usize, BufReader<File>)>,
+}
+
+impl Blocks<'_> {
+    fn read(&mut self, record: &Record) -> Result<Block, Error> {
+        if self
+            .open
+            .as_ref()
+            .is_none_or(|(file, _)| *file != record.file)
+        {
+            let file = File::open(&self.scanner.files[record.file])?;
+            self.open = Some((record.file, BufReader::new(file)));
+        }
+        let (_, reader) = self.open.as_mut().unwrap();
+        reader.seek(SeekFrom::Start(record.offset))?;
+        let mut data = vec![0u8; record.len as usize];
+        reader.read_exact(&mut data)?;
+        Ok(deserialize(&data)?)
+    }
+}
+
+impl Iterator for Blocks<'_> {
+    type Item = Result<Block, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.scanner.chain.get(self.next)?;
+        self.next += 1;
+        Some(self.read(record))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.scanner.chain.len() - self.next;
+        (left, Some(left))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::blockfile::{
+        block_files, BlockFileIter, BlockScanner, Error, MAX_RECORD_SIZE,
+    };
+    use crate::openassets::test_helpers::*;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use tapyrus::consensus::serialize;
+    use tapyrus::hashes::Hash;
+    use tapyrus::{Block, BlockHash, OutPoint, Transaction};
+
+    const MAGIC: [u8; 4] = [0x00, 0xf0, 0xff, 0x01];
+
+    fn write_block_file(path: &Path, blocks: &[&Block], padding: usize) {
+        let mut file = fs::File::create(path).unwrap();
+        for block in blocks {
+            let data = serialize(*block);
+            file.write_all(&MAGIC).unwrap();
+            file.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+            file.write_all(&data).unwrap();
+        }
+        file.write_all(&vec![0u8; padding]).unwrap();
+    }
+
+    #[test]
+    fn test_scan_block_files() {
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![txout(600, p2pkh(1)), marker(vec![100], b"")],
+        );
+        let transfer = tx(
+            &[OutPoint::new(issuance.txid(), 0)],
+            vec![marker(vec![100], b""), txout(600, p2pkh(2))],
+        );
+        let plain = tx(
+            &[OutPoint::new(transfer.txid(), 1)],
+            vec![txout(500, p2pkh(3))],
+        );
+
+        let genesis = block(&BlockHash::from_slice(&[0; 32]).unwrap(), 0, &[funding]);
+        let block1 = block(&genesis.block_hash(), 1, std::slice::from_ref(&issuance));
+        let block2 = block(&block1.block_hash(), 2, &[plain, transfer.clone()]);
+        let stale = block(&genesis.block_hash(), 3, &[]);
+
+        // blocks are not stored in chain order
+        let dir = temp_dir("blocks-scan");
+        write_block_file(&dir.join("blk00000.dat"), &[&genesis, &block2], 1024);
+        write_block_file(&dir.join("blk00001.dat"), &[&stale, &block1], 0);
+        fs::write(dir.join("rev00000.dat"), b"undo").unwrap();
+        assert_eq!(2, block_files(&dir).unwrap().len());
+
+        let scanner = BlockScanner::open(&dir, MAGIC).unwrap();
+        assert_eq!(3, scanner.len());
+        let hashes: Vec<BlockHash> = scanner.blocks().map(|b| b.unwrap().block_hash()).collect();
+        assert_eq!(
+            vec![
+                genesis.block_hash(),
+                block1.block_hash(),
+                block2.block_hash()
+            ],
+            hashes
+        );
+        let headers: Vec<BlockHash> = scanner.headers().map(|h| h.block_hash()).collect();
+        assert_eq!(hashes, headers);
+        let found: Vec<(usize, Transaction)> =
+            scanner.oa_transactions().map(|r| r.unwrap()).collect();
+        assert_eq!(vec![(1, issuance), (2, transfer)], found);
+
+        // another network
+        match BlockScanner::open(&dir, [0xf9, 0xbe, 0xb4, 0xd9]) {
+            Err(Error::InvalidMagic(_, magic)) => assert_eq!(MAGIC, magic),
+            r => panic!("unexpected result {:?}", r.err()),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_block_file() {
+        let genesis = block(&BlockHash::from_slice(&[0; 32]).unwrap(), 0, &[]);
+        let dir = temp_dir("blocks-truncated");
+        let path = dir.join("blk00000.dat");
+        write_block_file(&path, &[&genesis], 0);
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+
+        let mut iter = BlockFileIter::open(&path, MAGIC).unwrap();
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_oversized_record() {
+        let dir = temp_dir("blocks-oversized");
+        let path = dir.join("blk00000.dat");
+        let mut file = fs::File::create(&path).unwrap();
+        file.write_all(&MAGIC).unwrap();
+        file.write_u32::<LittleEndian>(MAX_RECORD_SIZE + 1).unwrap();
+        file.write_all(&[0u8; 80]).unwrap();
+        drop(file);
+
+        let mut iter = BlockFileIter::open(&path, MAGIC).unwrap();
+        match iter.next() {
+            Some(Err(Error::OversizedRecord(_, len))) => assert_eq!(MAX_RECORD_SIZE + 1, len),
+            r => panic!("unexpected result {:?}", r.map(|r| r.is_ok())),
+        }
+        assert!(matches!(
+            BlockScanner::open(&dir, MAGIC),
+            Err(Error::OversizedRecord(..))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_index() {
+        let genesis = block(&BlockHash::from_slice(&[0; 32]).unwrap(), 0, &[]);
+        let dir = temp_dir("blocks-truncated-index");
+        let path = dir.join("blk00000.dat");
+        write_block_file(&path, &[&genesis], 0);
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+
+        assert!(matches!(BlockScanner::open(&dir, MAGIC), Err(Error::Io(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
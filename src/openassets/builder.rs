@@ -0,0 +1,702 @@
+use std::fmt;
+
+use tapyrus::blockdata::opcodes;
+use tapyrus::blockdata::script::Builder;
+use tapyrus::consensus::{deserialize, encode, serialize, Encodable};
+use tapyrus::network::constants::Network;
+use tapyrus::{OutPoint, Script, Transaction, TxOut, VarInt};
+
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::coloring::{ColoredOutput, MAX_ASSET_QUANTITY};
+use crate::openassets::dust::DustPolicy;
+use crate::openassets::fee::{self, converge_change, estimate_size, Change, InputType};
+use crate::openassets::marker_output::{Metadata, Payload};
+use crate::openassets::network::NetworkParams;
+use crate::openassets::validator::ExpectedOutput;
+
+/// The default fee rate of built transactions, in tapyrus per 1000 bytes.
+pub const DEFAULT_FEE_RATE: u64 = 10_000;
+
+/// The input type assumed for outputs whose script does not tell the size of
+/// their scriptSig: a P2SH 2-of-3 multisig.
+pub const DEFAULT_P2SH_INPUT: InputType = InputType::P2shMultisig {
+    required: 2,
+    total: 3,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Encode(encode::Error),
+    Fee(fee::Error),
+    /// The uncolored outputs do not cover the outputs and the fee.
+    InsufficientFunds {
+        missing: u64,
+    },
+    /// The outputs of the asset do not hold the quantity to transfer.
+    InsufficientAssets {
+        asset_id: AssetId,
+        missing: u64,
+    },
+    /// An issuance needs an uncolored output to spend, whose script defines the asset id.
+    NoIssuer,
+    /// A transfer without recipients, or an output of zero asset units.
+    NothingToSend,
+    /// The units of an asset to send add up to more than `MAX_ASSET_QUANTITY`.
+    QuantityTooLarge {
+        asset_id: AssetId,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Encode(ref e) => write!(f, "encoding error: {}", e),
+            Error::Fee(ref e) => write!(f, "fee error: {}", e),
+            Error::InsufficientFunds { missing } => {
+                write!(f, "insufficient funds: {} tapyrus missing", missing)
+            }
+            Error::InsufficientAssets {
+                ref asset_id,
+                missing,
+            } => write!(
+                f,
+                "insufficient assets: {} units of {} missing",
+                missing, asset_id
+            ),
+            Error::NoIssuer => write!(f, "no uncolored output to issue from"),
+            Error::NothingToSend => write!(f, "no asset units to send"),
+            Error::QuantityTooLarge { ref asset_id } => {
+                write!(f, "too many units of {} to send", asset_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+impl From<fee::Error> for Error {
+    fn from(e: fee::Error) -> Self {
+        Error::Fee(e)
+    }
+}
+
+/// An output available to fund a transaction.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub output: ColoredOutput,
+}
+
+/// A transaction built by `TransactionBuilder`, with the outputs it spends and
+/// the coloring its outputs are intended to get.
+#[derive(Debug, Clone)]
+pub struct UnsignedTransaction {
+    pub tx: Transaction,
+    pub inputs: Vec<ColoredOutput>,
+    pub expected: Vec<ExpectedOutput>,
+    pub fee: u64,
+}
+
+/// Builds unsigned issuance and transfer transactions.
+///
+/// Colored outputs get the dust threshold of their script, the fee is paid
+/// from uncolored outputs, and the remaining tapyrus go to an uncolored change
+/// output unless they would be dust. Inputs are priced by the script they spend,
+/// and as `DEFAULT_P2SH_INPUT` when the script does not tell the size of their
+/// scriptSig.
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder {
+    params: &'static NetworkParams,
+    fee_rate: u64,
+    fixed_fee: Option<u64>,
+    dust_policy: DustPolicy,
+    p2sh_input: InputType,
+}
+
+impl TransactionBuilder {
+    pub fn new(network: Network) -> Self {
+        TransactionBuilder::with_params(NetworkParams::tapyrus(network))
+    }
+
+    /// Issues the asset ids of `params`, with the dust policy of its chain.
+    pub fn with_params(params: &'static NetworkParams) -> Self {
+        TransactionBuilder {
+            params,
+            fee_rate: DEFAULT_FEE_RATE,
+            fixed_fee: None,
+            dust_policy: params.dust_policy(),
+            p2sh_input: DEFAULT_P2SH_INPUT,
+        }
+    }
+
+    /// Sets the fee rate in tapyrus per 1000 bytes.
+    pub fn with_fee_rate(mut self, fee_rate: u64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Pays exactly `fee` tapyrus instead of a fee computed from the fee rate.
+    pub fn with_fixed_fee(mut self, fee: u64) -> Self {
+        self.fixed_fee = Some(fee);
+        self
+    }
+
+    pub fn with_dust_policy(mut self, dust_policy: DustPolicy) -> Self {
+        self.dust_policy = dust_policy;
+        self
+    }
+
+    /// Prices inputs whose script does not tell the size of their scriptSig,
+    /// such as P2SH, as `input_type`.
+    pub fn with_p2sh_input_type(mut self, input_type: InputType) -> Self {
+        self.p2sh_input = input_type;
+        self
+    }
+
+    pub fn network(&self) -> Network {
+        self.params.network
+    }
+
+    pub fn params(&self) -> &'static NetworkParams {
+        self.params
+    }
+
+    /// Issues `quantity` units to `to`, spending the uncolored outputs of
+    /// `utxos` in order. The asset id is derived from the script of the first
+    /// uncolored output.
+    pub fn issue(
+        &self,
+        utxos: &[Utxo],
+        to: &Script,
+        quantity: u64,
+        metadata: Vec<u8>,
+        change: &Script,
+    ) -> Result<UnsignedTransaction, Error> {
+        if quantity == 0 {
+            return Err(Error::NothingToSend);
+        }
+        let uncolored: Vec<&Utxo> = utxos.iter().filter(|u| !u.output.is_colored()).collect();
+        let issuer = uncolored.first().ok_or(Error::NoIssuer)?;
+        let asset_id = AssetId::with_params(&issuer.output.script_pubkey, self.params);
+        if quantity > MAX_ASSET_QUANTITY {
+            return Err(Error::QuantityTooLarge { asset_id });
+        }
+
+        let outputs = vec![self.colored_output(to)];
+        let payload = Payload {
+            quantities: vec![quantity],
+            metadata: Metadata::new(metadata),
+        };
+        let mut built = self.fund(vec![], uncolored, outputs, Some((1, payload)), change)?;
+        built.expected = vec![ExpectedOutput::new(0, asset_id, quantity)];
+        Ok(built)
+    }
+
+    /// Transfers units of `asset_id` to each recipient, spending the outputs
+    /// of the asset in `utxos` in order and returning the rest to `change`.
+    pub fn transfer(
+        &self,
+        utxos: &[Utxo],
+        asset_id: &AssetId,
+        recipients: &[(Script, u64)],
+        change: &Script,
+    ) -> Result<UnsignedTransaction, Error> {
+        let transfers: Vec<(AssetId, Script, u64)> = recipients
+            .iter()
+            .map(|(script, quantity)| (asset_id.clone(), script.clone(), *quantity))
+            .collect();
+        self.transfer_assets(utxos, &transfers, change)
+    }
+
+    /// Transfers several assets at once. Each `(asset_id, script, quantity)`
+    /// sends `quantity` units of `asset_id` to `script`, and the rest of each
+    /// asset returns to `change`.
+    pub fn transfer_assets(
+        &self,
+        utxos: &[Utxo],
+        transfers: &[(AssetId, Script, u64)],
+        change: &Script,
+    ) -> Result<UnsignedTransaction, Error> {
+        if transfers.is_empty() || transfers.iter().any(|(_, _, q)| *q == 0) {
+            return Err(Error::NothingToSend);
+        }
+        let mut asset_ids: Vec<&AssetId> = vec![];
+        for (asset_id, _, _) in transfers.iter() {
+            if !asset_ids.contains(&asset_id) {
+                asset_ids.push(asset_id);
+            }
+        }
+
+        // the inputs of each asset pay for its outputs, in the same order
+        let mut colored = vec![];
+        let mut quantities = vec![];
+        let mut outputs = vec![];
+        for asset_id in asset_ids {
+            let mut total: u64 = 0;
+            for (_, _, quantity) in transfers.iter().filter(|(id, _, _)| id == asset_id) {
+                total = total
+                    .checked_add(*quantity)
+                    .filter(|&t| t <= MAX_ASSET_QUANTITY)
+                    .ok_or_else(|| Error::QuantityTooLarge {
+                        asset_id: asset_id.clone(),
+                    })?;
+            }
+            let mut selected = 0;
+            for utxo in utxos.iter() {
+                if selected >= total {
+                    break;
+                }
+                if utxo.output.asset_id.as_ref() == Some(asset_id) {
+                    selected += utxo.output.asset_quantity;
+                    colored.push(utxo);
+                }
+            }
+            if selected < total {
+                return Err(Error::InsufficientAssets {
+                    asset_id: asset_id.clone(),
+                    missing: total - selected,
+                });
+            }
+            for (_, script, quantity) in transfers.iter().filter(|(id, _, _)| id == asset_id) {
+                quantities.push((asset_id, *quantity));
+                outputs.push(self.colored_output(script));
+            }
+            if selected > total {
+                quantities.push((asset_id, selected - total));
+                outputs.push(self.colored_output(change));
+            }
+        }
+
+        // the marker output comes first, so every output is a transfer output
+        let expected = quantities
+            .iter()
+            .enumerate()
+            .map(|(i, (asset_id, q))| ExpectedOutput::new(i + 1, (*asset_id).clone(), *q))
+            .collect();
+        let payload = Payload {
+            quantities: quantities.iter().map(|(_, q)| *q).collect(),
+            metadata: Metadata::new(vec![]),
+        };
+        let uncolored = utxos.iter().filter(|u| !u.output.is_colored()).collect();
+        let mut built = self.fund(colored, uncolored, outputs, Some((0, payload)), change)?;
+        built.expected = expected;
+        Ok(built)
+    }
+
+    /// Destroys every unit of `asset_id` in `utxos` by spending them in a
+    /// transaction without a marker output, sending the tapyrus to `change`.
+    pub fn burn(
+        &self,
+        utxos: &[Utxo],
+        asset_id: &AssetId,
+        change: &Script,
+    ) -> Result<UnsignedTransaction, Error> {
+        let colored: Vec<&Utxo> = utxos
+            .iter()
+            .filter(|u| u.output.asset_id.as_ref() == Some(asset_id))
+            .collect();
+        if colored.is_empty() {
+            return Err(Error::NothingToSend);
+        }
+        let uncolored = utxos.iter().filter(|u| !u.output.is_colored()).collect();
+        self.fund(colored, uncolored, vec![], None, change)
+    }
+
+    fn colored_output(&self, script: &Script) -> TxOut {
+        TxOut {
+            value: self.dust_policy.threshold(script),
+            script_pubkey: script.clone(),
+        }
+    }
+
+    /// Adds uncolored outputs from `pool` to `inputs` until they pay for
+    /// `outputs` and the fee, then inserts the marker output carrying the
+    /// payload at the given index, if any, and appends the change.
+    fn fund<'a>(
+        &self,
+        mut inputs: Vec<&'a Utxo>,
+        pool: Vec<&'a Utxo>,
+        outputs: Vec<TxOut>,
+        marker: Option<(usize, Payload)>,
+        change: &Script,
+    ) -> Result<UnsignedTransaction, Error> {
+        let payload = marker.as_ref().map(|(_, payload)| payload);
+        let spent: u64 = outputs.iter().map(|o| o.value).sum();
+        let dust_threshold = self.dust_policy.threshold(change);
+        let mut pool = pool.into_iter();
+        let priced = loop {
+            let input_value: u64 = inputs.iter().map(|u| u.output.value).sum();
+            let input_types: Vec<InputType> = inputs
+                .iter()
+                .map(|u| InputType::from_script(&u.output.script_pubkey).unwrap_or(self.p2sh_input))
+                .collect();
+            let result = match (input_value.checked_sub(spent), self.fixed_fee) {
+                (Some(available), Some(fee)) => fixed_change(available, fee, dust_threshold),
+                (Some(available), None) => {
+                    converge_change(available, self.fee_rate, dust_threshold, |value| {
+                        let mut outputs = outputs.clone();
+                        if let Some(value) = value {
+                            outputs.push(TxOut {
+                                value,
+                                script_pubkey: change.clone(),
+                            });
+                        }
+                        estimate_size(&input_types, &outputs, payload)
+                    })
+                }
+                (None, _) => Err(fee::Error::InsufficientFunds {
+                    missing: spent - input_value,
+                }),
+            };
+            match result {
+                Ok(priced) => break priced,
+                Err(fee::Error::InsufficientFunds { missing }) => match pool.next() {
+                    Some(utxo) => inputs.push(utxo),
+                    None => return Err(Error::InsufficientFunds { missing }),
+                },
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let mut outputs = outputs;
+        if let Some((index, payload)) = marker {
+            outputs.insert(
+                index,
+                TxOut {
+                    value: 0,
+                    script_pubkey: Builder::new()
+                        .push_opcode(opcodes::all::OP_RETURN)
+                        .push_slice(&serialize(&payload))
+                        .into_script(),
+                },
+            );
+        }
+        if let Some(value) = priced.value {
+            outputs.push(TxOut {
+                value,
+                script_pubkey: change.clone(),
+            });
+        }
+        let outpoints: Vec<OutPoint> = inputs.iter().map(|u| u.outpoint).collect();
+        Ok(UnsignedTransaction {
+            tx: unsigned_tx(&outpoints, &outputs)?,
+            inputs: inputs.iter().map(|u| u.output.clone()).collect(),
+            expected: vec![],
+            fee: priced.fee,
+        })
+    }
+}
+
+/// Returns the change left by paying exactly `fee` out of `available`, giving
+/// dust change to the fee.
+fn fixed_change(available: u64, fee: u64, dust_threshold: u64) -> Result<Change, fee::Error> {
+    let left = available
+        .checked_sub(fee)
+        .ok_or_else(|| fee::Error::InsufficientFunds {
+            missing: fee - available,
+        })?;
+    let value = if left >= dust_threshold {
+        Some(left)
+    } else {
+        None
+    };
+    Ok(Change {
+        value,
+        fee: available - value.unwrap_or(0),
+    })
+}
+
+/// Builds a version 1 transaction spending `inputs` with empty scriptSigs.
+fn unsigned_tx(inputs: &[OutPoint], outputs: &[TxOut]) -> Result<Transaction, Error> {
+    let mut bytes: Vec<u8> = vec![];
+    1i32.consensus_encode(&mut bytes)?;
+    VarInt(inputs.len() as u64).consensus_encode(&mut bytes)?;
+    for outpoint in inputs {
+        outpoint.consensus_encode(&mut bytes)?;
+        Script::new().consensus_encode(&mut bytes)?;
+        0xffff_ffffu32.consensus_encode(&mut bytes)?;
+    }
+    outputs.to_vec().consensus_encode(&mut bytes)?;
+    0u32.consensus_encode(&mut bytes)?;
+    Ok(deserialize(&bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::builder::{
+        Error, TransactionBuilder, Utxo, DEFAULT_FEE_RATE, DEFAULT_P2SH_INPUT,
+    };
+    use crate::openassets::coloring::{
+        color_outputs, ColoredOutput, OutputType, MAX_ASSET_QUANTITY,
+    };
+    use crate::openassets::fee::{estimate_size, fee, InputType};
+    use crate::openassets::marker_output::{MarkerVersions, Metadata, Payload, TxOutExt};
+    use crate::openassets::network::{NetworkParams, BITCOIN};
+    use crate::openassets::test_helpers::*;
+    use crate::openassets::validator::{validate, validate_with};
+    use std::str::FromStr;
+    use tapyrus::network::constants::Network;
+    use tapyrus::{OutPoint, Script};
+
+    fn utxo(seed: u32, value: u64, script: Script, color: Option<(&AssetId, u64)>) -> Utxo {
+        let mut output = ColoredOutput::uncolored(&txout(value, script));
+        if let Some((asset_id, quantity)) = color {
+            output.asset_id = Some(asset_id.clone());
+            output.asset_quantity = quantity;
+            output.output_type = OutputType::Transfer;
+        }
+        Utxo {
+            outpoint: OutPoint::new(funding_tx(seed, vec![]).txid(), 0),
+            output,
+        }
+    }
+
+    #[test]
+    fn test_issue() {
+        let builder = TransactionBuilder::new(Network::Prod);
+        let utxos = vec![
+            utxo(0, 2000, script(ISSUER_SCRIPT), None),
+            utxo(1, 50_000, p2pkh(9), None),
+        ];
+        let built = builder
+            .issue(
+                &utxos,
+                &p2pkh(1),
+                1000,
+                b"u=https://example.com".to_vec(),
+                &p2pkh(9),
+            )
+            .unwrap();
+        let tx = &built.tx;
+        // the first input does not cover the fee, so the second one is added
+        assert_eq!(2, tx.input.len());
+        assert_eq!(3, tx.output.len());
+        assert_eq!(546, tx.output[0].value);
+        assert!(tx.output[1].is_openassets_marker());
+        assert_eq!(52_000 - 546 - built.fee, tx.output[2].value);
+        let size = estimate_size(&[InputType::P2pkh; 2], &tx.output, None);
+        assert_eq!(fee(size, 10_000), built.fee);
+
+        let asset_id = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        assert_eq!(asset_id, built.expected[0].asset_id);
+        let colored = color_outputs(tx, &built.inputs, Network::Prod);
+        assert_eq!(Some(asset_id), colored[0].asset_id);
+        assert_eq!(1000, colored[0].asset_quantity);
+        assert!(!colored[2].is_colored());
+        assert!(validate(tx, &built.inputs, &built.expected, Network::Prod).is_empty());
+
+        assert!(matches!(
+            builder.issue(&utxos[..1], &p2pkh(1), 1000, vec![], &p2pkh(9)),
+            Err(Error::InsufficientFunds { .. })
+        ));
+
+        // issuing on Bitcoin, with the dust relay fee of Bitcoin
+        let params: &'static NetworkParams = &BITCOIN;
+        let builder = TransactionBuilder::with_params(params);
+        let built = builder
+            .issue(&utxos, &p2pkh(1), 1000, vec![], &p2pkh(9))
+            .unwrap();
+        let bitcoin = AssetId::with_params(&script(ISSUER_SCRIPT), params);
+        assert_eq!(bitcoin, built.expected[0].asset_id);
+        assert_eq!(
+            params.dust_policy().threshold(&p2pkh(1)),
+            built.tx.output[0].value
+        );
+        let versions = MarkerVersions::default();
+        assert!(
+            validate_with(&built.tx, &built.inputs, &built.expected, params, &versions).is_empty()
+        );
+        assert!(matches!(
+            builder.issue(&[], &p2pkh(1), 1000, vec![], &p2pkh(9)),
+            Err(Error::NoIssuer)
+        ));
+    }
+
+    #[test]
+    fn test_transfer() {
+        let builder = TransactionBuilder::new(Network::Prod).with_fee_rate(1000);
+        let asset_id = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        let other = AssetId::new(&p2pkh(7), Network::Prod);
+        let utxos = vec![
+            utxo(0, 600, p2pkh(1), Some((&other, 10))),
+            utxo(1, 600, p2pkh(1), Some((&asset_id, 30))),
+            utxo(2, 600, p2pkh(1), Some((&asset_id, 50))),
+            utxo(3, 600, p2pkh(1), Some((&asset_id, 70))),
+            utxo(4, 10_000, p2pkh(9), None),
+        ];
+        let recipients = vec![(p2pkh(2), 40), (p2pkh(3), 20)];
+        let built = builder
+            .transfer(&utxos, &asset_id, &recipients, &p2pkh(9))
+            .unwrap();
+        let tx = &built.tx;
+        let spent: Vec<OutPoint> = tx.input.iter().map(|i| i.previous_output).collect();
+        assert_eq!(
+            vec![utxos[1].outpoint, utxos[2].outpoint, utxos[4].outpoint],
+            spent
+        );
+        assert_eq!(5, tx.output.len());
+        assert!(tx.output[0].is_openassets_marker());
+
+        let colored = color_outputs(tx, &built.inputs, Network::Prod);
+        let quantities: Vec<u64> = colored.iter().map(|o| o.asset_quantity).collect();
+        assert_eq!(vec![0, 40, 20, 20, 0], quantities);
+        assert_eq!(p2pkh(9), colored[3].script_pubkey);
+        assert!(!colored[4].is_colored());
+        assert!(validate(tx, &built.inputs, &built.expected, Network::Prod).is_empty());
+        assert_eq!(11_200 - 3 * 546, tx.output[4].value + built.fee);
+
+        match builder.transfer(&utxos, &asset_id, &[(p2pkh(2), 151)], &p2pkh(9)) {
+            Err(Error::InsufficientAssets { missing, .. }) => assert_eq!(1, missing),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(matches!(
+            builder.transfer(&utxos, &asset_id, &[], &p2pkh(9)),
+            Err(Error::NothingToSend)
+        ));
+    }
+
+    #[test]
+    fn test_transfer_assets() {
+        let builder = TransactionBuilder::new(Network::Prod).with_fixed_fee(1000);
+        let asset_id = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        let other = AssetId::new(&p2pkh(7), Network::Prod);
+        let utxos = vec![
+            utxo(0, 600, p2pkh(1), Some((&other, 10))),
+            utxo(1, 600, p2pkh(1), Some((&asset_id, 30))),
+            utxo(2, 5000, p2pkh(9), None),
+        ];
+        let transfers = vec![
+            (asset_id.clone(), p2pkh(2), 30),
+            (other.clone(), p2pkh(3), 4),
+        ];
+        let built = builder
+            .transfer_assets(&utxos, &transfers, &p2pkh(9))
+            .unwrap();
+        let tx = &built.tx;
+        let spent: Vec<OutPoint> = tx.input.iter().map(|i| i.previous_output).collect();
+        assert_eq!(
+            vec![utxos[1].outpoint, utxos[0].outpoint, utxos[2].outpoint],
+            spent
+        );
+        assert_eq!(1000, built.fee);
+        assert_eq!(6200 - 3 * 546 - 1000, tx.output[4].value);
+        let colored = color_outputs(tx, &built.inputs, Network::Prod);
+        assert_eq!(Some(asset_id.clone()), colored[1].asset_id);
+        assert_eq!(Some(other.clone()), colored[2].asset_id);
+        assert_eq!(4, colored[2].asset_quantity);
+        // the rest of the other asset
+        assert_eq!(6, colored[3].asset_quantity);
+        assert!(validate(tx, &built.inputs, &built.expected, Network::Prod).is_empty());
+
+        assert!(matches!(
+            builder
+                .clone()
+                .with_fixed_fee(10_000)
+                .transfer_assets(&utxos, &transfers, &p2pkh(9)),
+            Err(Error::InsufficientFunds { missing: 5438 })
+        ));
+
+        let overflowing = vec![
+            (asset_id.clone(), p2pkh(2), MAX_ASSET_QUANTITY),
+            (asset_id.clone(), p2pkh(3), 1),
+        ];
+        assert!(matches!(
+            builder.transfer_assets(&utxos, &overflowing, &p2pkh(9)),
+            Err(Error::QuantityTooLarge { .. })
+        ));
+        assert!(matches!(
+            builder.issue(
+                &utxos[2..],
+                &p2pkh(1),
+                MAX_ASSET_QUANTITY + 1,
+                vec![],
+                &p2pkh(9)
+            ),
+            Err(Error::QuantityTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_input_types() {
+        let p2sh = tapyrus::Address::p2sh(&p2pkh(1), Network::Prod).script_pubkey();
+        let utxos = vec![
+            utxo(0, 2000, script(ISSUER_SCRIPT), None),
+            utxo(1, 50_000, p2sh, None),
+        ];
+        let issue = |builder: TransactionBuilder| {
+            builder
+                .issue(&utxos, &p2pkh(1), 1000, vec![], &p2pkh(9))
+                .unwrap()
+        };
+        let built = issue(TransactionBuilder::new(Network::Prod));
+        let size = estimate_size(
+            &[InputType::P2pkh, DEFAULT_P2SH_INPUT],
+            &built.tx.output,
+            None,
+        );
+        assert_eq!(fee(size, DEFAULT_FEE_RATE), built.fee);
+
+        let input_type = InputType::ScriptSig(500);
+        let built = issue(TransactionBuilder::new(Network::Prod).with_p2sh_input_type(input_type));
+        let size = estimate_size(&[InputType::P2pkh, input_type], &built.tx.output, None);
+        assert_eq!(fee(size, DEFAULT_FEE_RATE), built.fee);
+    }
+
+    #[test]
+    fn test_burn() {
+        let builder = TransactionBuilder::new(Network::Prod).with_fee_rate(1000);
+        let asset_id = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        let utxos = vec![
+            utxo(0, 600, p2pkh(1), Some((&asset_id, 30))),
+            utxo(1, 600, p2pkh(1), Some((&asset_id, 50))),
+            utxo(2, 5000, p2pkh(9), None),
+        ];
+        let built = builder.burn(&utxos, &asset_id, &p2pkh(9)).unwrap();
+        let tx = &built.tx;
+        // the burned outputs pay for the fee
+        assert_eq!(2, tx.input.len());
+        assert_eq!(1, tx.output.len());
+        assert!(!tx.output[0].is_openassets_marker());
+        assert_eq!(1200, tx.output[0].value + built.fee);
+        let colored = color_outputs(tx, &built.inputs, Network::Prod);
+        assert!(!colored[0].is_colored());
+
+        assert!(matches!(
+            builder.burn(&utxos[2..], &asset_id, &p2pkh(9)),
+            Err(Error::NothingToSend)
+        ));
+    }
+
+    #[test]
+    fn test_high_fee_rate() {
+        let builder = TransactionBuilder::new(Network::Prod).with_fee_rate(20_000);
+        let payload = Payload {
+            quantities: vec![1000],
+            metadata: Metadata::new(vec![]),
+        };
+        let size = estimate_size(&[InputType::P2pkh], &[txout(546, p2pkh(1))], Some(&payload));
+        // 600 tapyrus are left, less than the 680 a change output costs
+        let utxos = vec![
+            utxo(
+                0,
+                546 + fee(size, 20_000) + 600,
+                script(ISSUER_SCRIPT),
+                None,
+            ),
+            utxo(1, 50_000, p2pkh(9), None),
+        ];
+        let built = builder
+            .issue(&utxos, &p2pkh(1), 1000, vec![], &p2pkh(9))
+            .unwrap();
+        assert_eq!(1, built.tx.input.len());
+        assert_eq!(2, built.tx.output.len());
+        assert_eq!(fee(size, 20_000) + 600, built.fee);
+    }
+}
@@ -0,0 +1,573 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use tapyrus::hashes::{hash160, sha256d, Hash};
+use tapyrus::{OutPoint, TxOut, Txid};
+
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::coloring::{ColoredOutput, OutputType};
+use crate::openassets::network::{NetworkParams, PRESETS};
+
+/// Identifies the on-disk format of `FileCache`.
+pub const MAGIC: [u8; 4] = *b"OACC";
+/// The current version of the on-disk format.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The network byte of asset ids of registered networks, followed by the
+/// network name.
+pub(crate) const CUSTOM_NETWORK: u8 = 0xff;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file is not a coloring cache.
+    InvalidMagic,
+    /// The file was written with an unsupported format version.
+    UnsupportedVersion(u32),
+    /// The file content does not match its checksum.
+    InvalidChecksum,
+    /// The file content can not be parsed.
+    Corrupted(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::InvalidMagic => write!(f, "not a coloring cache file"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported cache version: {}", v),
+            Error::InvalidChecksum => write!(f, "invalid cache checksum"),
+            Error::Corrupted(s) => write!(f, "corrupted cache: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// The coloring of a single output, without the output itself.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct OutputColor {
+    pub asset_id: Option<AssetId>,
+    pub asset_quantity: u64,
+    pub output_type: OutputType,
+}
+
+impl OutputColor {
+    /// Combines the coloring with the output it belongs to.
+    pub fn to_colored_output(&self, txout: &TxOut) -> ColoredOutput {
+        ColoredOutput {
+            value: txout.value,
+            script_pubkey: txout.script_pubkey.clone(),
+            asset_id: self.asset_id.clone(),
+            asset_quantity: self.asset_quantity,
+            output_type: self.output_type,
+        }
+    }
+
+    /// Writes the coloring as `FileCache` stores it and PSBT annotations carry
+    /// it: the output type, the quantity and the asset id with its network.
+    pub(crate) fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(match self.output_type {
+            OutputType::Uncolored => 0,
+            OutputType::MarkerOutput => 1,
+            OutputType::Issuance => 2,
+            OutputType::Transfer => 3,
+        })?;
+        w.write_u64::<LittleEndian>(self.asset_quantity)?;
+        match self.asset_id {
+            None => w.write_u8(0)?,
+            Some(ref asset_id) => {
+                match PRESETS.iter().position(|p| *p == asset_id.params()) {
+                    Some(preset) => w.write_u8(preset as u8 + 1)?,
+                    None => {
+                        // registered names are at most `network::MAX_NAME_LEN`, 255, bytes
+                        let name = asset_id.params().name.as_bytes();
+                        w.write_u8(CUSTOM_NETWORK)?;
+                        w.write_u8(name.len() as u8)?;
+                        w.write_all(name)?;
+                    }
+                }
+                w.write_all(&asset_id.hash[..])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a coloring written by `write_to`.
+    pub(crate) fn read_from<R: Read>(r: &mut R) -> Result<OutputColor, Error> {
+        let output_type = match r.read_u8()? {
+            0 => OutputType::Uncolored,
+            1 => OutputType::MarkerOutput,
+            2 => OutputType::Issuance,
+            3 => OutputType::Transfer,
+            _ => return Err(Error::Corrupted("unknown output type")),
+        };
+        let asset_quantity = r.read_u64::<LittleEndian>()?;
+        let params = match r.read_u8()? {
+            0 => None,
+            CUSTOM_NETWORK => {
+                let mut name = vec![0u8; r.read_u8()? as usize];
+                r.read_exact(&mut name)?;
+                let name = String::from_utf8(name)
+                    .map_err(|_| Error::Corrupted("invalid network name"))?;
+                Some(
+                    NetworkParams::by_name(&name)
+                        .ok_or(Error::Corrupted("unregistered network"))?,
+                )
+            }
+            n => Some(
+                *PRESETS
+                    .get(n as usize - 1)
+                    .ok_or(Error::Corrupted("unknown network"))?,
+            ),
+        };
+        let asset_id = match params {
+            None => None,
+            Some(params) => {
+                let mut hash = [0u8; 20];
+                r.read_exact(&mut hash)?;
+                Some(AssetId::from_hash(
+                    hash160::Hash::from_slice(&hash).unwrap(),
+                    params,
+                ))
+            }
+        };
+        Ok(OutputColor {
+            asset_id,
+            asset_quantity,
+            output_type,
+        })
+    }
+}
+
+impl From<&ColoredOutput> for OutputColor {
+    fn from(output: &ColoredOutput) -> Self {
+        OutputColor {
+            asset_id: output.asset_id.clone(),
+            asset_quantity: output.asset_quantity,
+            output_type: output.output_type,
+        }
+    }
+}
+
+/// A store of per-outpoint coloring results.
+pub trait ColoringCache {
+    fn get(&mut self, outpoint: &OutPoint) -> Option<OutputColor>;
+
+    fn put(&mut self, outpoint: OutPoint, color: OutputColor);
+
+    /// Persists the pending changes, if the cache is persistent.
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// An in-memory cache holding at most `capacity` entries, evicting the least
+/// recently used entry first.
+pub struct MemoryCache {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<OutPoint, (OutputColor, u64)>,
+    recency: BTreeMap<u64, OutPoint>,
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        MemoryCache {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Iterates over the entries from the least to the most recently used.
+    pub fn iter(&self) -> impl Iterator<Item = (&OutPoint, &OutputColor)> {
+        self.recency
+            .values()
+            .map(move |outpoint| (outpoint, &self.entries[outpoint].0))
+    }
+
+    fn touch(&mut self, outpoint: &OutPoint) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(outpoint) {
+            self.recency.remove(&entry.1);
+            entry.1 = tick;
+            self.recency.insert(tick, *outpoint);
+        }
+    }
+}
+
+impl ColoringCache for MemoryCache {
+    fn get(&mut self, outpoint: &OutPoint) -> Option<OutputColor> {
+        self.touch(outpoint);
+        self.entries.get(outpoint).map(|entry| entry.0.clone())
+    }
+
+    fn put(&mut self, outpoint: OutPoint, color: OutputColor) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, tick)) = self.entries.insert(outpoint, (color, self.tick)) {
+            self.recency.remove(&tick);
+        }
+        self.recency.insert(self.tick, outpoint);
+        while self.entries.len() > self.capacity {
+            let oldest = *self.recency.keys().next().unwrap();
+            let outpoint = self.recency.remove(&oldest).unwrap();
+            self.entries.remove(&outpoint);
+        }
+    }
+}
+
+/// A `MemoryCache` persisted to a file.
+///
+/// `flush` writes the whole cache to a temporary file and renames it over the
+/// cache file, so a crash never leaves a partially written cache behind.
+pub struct FileCache {
+    path: PathBuf,
+    memory: MemoryCache,
+    dirty: bool,
+}
+
+impl FileCache {
+    /// Opens the cache at `path`, starting empty if the file does not exist.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut memory = MemoryCache::new(capacity);
+        match File::open(&path) {
+            Ok(mut file) => {
+                let mut data = vec![];
+                file.read_to_end(&mut data)?;
+                for (outpoint, color) in decode(&data)? {
+                    memory.put(outpoint, color);
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::Io(e)),
+        }
+        Ok(FileCache {
+            path,
+            memory,
+            dirty: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty()
+    }
+}
+
+impl ColoringCache for FileCache {
+    fn get(&mut self, outpoint: &OutPoint) -> Option<OutputColor> {
+        self.memory.get(outpoint)
+    }
+
+    fn put(&mut self, outpoint: OutPoint, color: OutputColor) {
+        self.memory.put(outpoint, color);
+        self.dirty = true;
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let data = encode(self.memory.iter())?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        if let Some(dir) = self.path.parent() {
+            // make the rename durable where directories can be synced
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for FileCache {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn encode<'a, I>(entries: I) -> Result<Vec<u8>, Error>
+where
+    I: Iterator<Item = (&'a OutPoint, &'a OutputColor)>,
+{
+    let entries: Vec<_> = entries.collect();
+    let mut data = vec![];
+    data.write_all(&MAGIC)?;
+    data.write_u32::<LittleEndian>(FORMAT_VERSION)?;
+    data.write_u64::<LittleEndian>(entries.len() as u64)?;
+    for (outpoint, color) in entries {
+        data.write_all(&outpoint.txid[..])?;
+        data.write_u32::<LittleEndian>(outpoint.vout)?;
+        color.write_to(&mut data)?;
+    }
+    let checksum = sha256d::Hash::hash(&data);
+    data.write_all(&checksum[..4])?;
+    Ok(data)
+}
+
+fn decode(data: &[u8]) -> Result<Vec<(OutPoint, OutputColor)>, Error> {
+    if data.len() < 8 || data[..4] != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    let mut version = &data[4..8];
+    let version = version.read_u32::<LittleEndian>()?;
+    if version != FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    if data.len() < 20 {
+        return Err(Error::Corrupted("truncated header"));
+    }
+    let (body, checksum) = data.split_at(data.len() - 4);
+    if sha256d::Hash::hash(body)[..4] != *checksum {
+        return Err(Error::InvalidChecksum);
+    }
+
+    let mut cursor = Cursor::new(&body[8..]);
+    let count = cursor.read_u64::<LittleEndian>()?;
+    let mut entries = vec![];
+    for _ in 0..count {
+        let mut txid = [0u8; 32];
+        cursor.read_exact(&mut txid)?;
+        let vout = cursor.read_u32::<LittleEndian>()?;
+        let color = OutputColor::read_from(&mut cursor)?;
+        entries.push((OutPoint::new(Txid::from_slice(&txid).unwrap(), vout), color));
+    }
+    if cursor.position() as usize != body.len() - 8 {
+        return Err(Error::Corrupted("trailing data"));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::cache::{decode, encode};
+    use crate::openassets::cache::{ColoringCache, Error, FileCache, MemoryCache, OutputColor};
+    use crate::openassets::coloring::{Colorer, OutputType};
+    use crate::openassets::network::{self, NetworkParams};
+    use crate::openassets::test_helpers::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::str::FromStr;
+    use tapyrus::network::constants::Network;
+    use tapyrus::{OutPoint, Transaction, Txid};
+
+    fn outpoint(n: u32) -> OutPoint {
+        OutPoint::new(funding_tx(n, vec![]).txid(), n)
+    }
+
+    fn color(quantity: u64) -> OutputColor {
+        OutputColor {
+            asset_id: Some(AssetId::new(&p2pkh(1), Network::Dev)),
+            asset_quantity: quantity,
+            output_type: OutputType::Transfer,
+        }
+    }
+
+    #[test]
+    fn test_memory_cache_eviction() {
+        let mut cache = MemoryCache::new(2);
+        cache.put(outpoint(1), color(1));
+        cache.put(outpoint(2), color(2));
+        // touching 1 makes 2 the least recently used entry
+        assert_eq!(Some(color(1)), cache.get(&outpoint(1)));
+        cache.put(outpoint(3), color(3));
+        assert_eq!(2, cache.len());
+        assert_eq!(None, cache.get(&outpoint(2)));
+        assert_eq!(Some(color(1)), cache.get(&outpoint(1)));
+        assert_eq!(Some(color(3)), cache.get(&outpoint(3)));
+
+        // overwriting does not grow the cache
+        cache.put(outpoint(3), color(4));
+        assert_eq!(2, cache.len());
+        assert_eq!(Some(color(4)), cache.get(&outpoint(3)));
+    }
+
+    #[test]
+    fn test_file_cache_roundtrip() {
+        let dir = temp_dir("cache-roundtrip");
+        let path = dir.join("cache");
+        {
+            let mut cache = FileCache::open(&path, 10).unwrap();
+            assert!(cache.is_empty());
+            cache.put(outpoint(1), color(1));
+            cache.put(
+                outpoint(2),
+                OutputColor {
+                    asset_id: None,
+                    asset_quantity: 0,
+                    output_type: OutputType::MarkerOutput,
+                },
+            );
+            cache.flush().unwrap();
+            cache.put(outpoint(3), color(3));
+            // dropping the cache flushes pending entries
+        }
+        let mut cache = FileCache::open(&path, 2).unwrap();
+        assert_eq!(2, cache.len());
+        assert_eq!(None, cache.get(&outpoint(1)));
+        assert_eq!(
+            Some(OutputType::MarkerOutput),
+            cache.get(&outpoint(2)).map(|c| c.output_type)
+        );
+        assert_eq!(Some(color(3)), cache.get(&outpoint(3)));
+        drop(cache);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_network_roundtrip() {
+        let custom = network::register(NetworkParams {
+            name: "test-cache",
+            network_id: Some(9_000_002),
+            p2pkh_prefix: 60,
+            p2sh_prefix: 61,
+            cp2pkh_prefix: Some(62),
+            cp2sh_prefix: Some(63),
+            asset_id_version: 64,
+            ..network::TAPYRUS_PROD
+        })
+        .unwrap();
+        let colors: Vec<(OutPoint, OutputColor)> = [
+            AssetId::new(&p2pkh(1), Network::Prod),
+            AssetId::with_params(&p2pkh(1), &network::BITCOIN_REGTEST),
+            AssetId::with_params(&p2pkh(1), custom),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, asset_id)| {
+            let color = OutputColor {
+                asset_id: Some(asset_id),
+                ..color(1)
+            };
+            (outpoint(i as u32), color)
+        })
+        .collect();
+        let data = encode(colors.iter().map(|(o, c)| (o, c))).unwrap();
+        assert_eq!(colors, decode(&data).unwrap());
+    }
+
+    #[test]
+    fn test_file_cache_rejects_invalid_files() {
+        let dir = temp_dir("cache-invalid");
+        let path = dir.join("cache");
+        {
+            let mut cache = FileCache::open(&path, 10).unwrap();
+            cache.put(outpoint(1), color(1));
+        }
+        let valid = fs::read(&path).unwrap();
+
+        let mut data = valid.clone();
+        data[4] = 2;
+        fs::write(&path, &data).unwrap();
+        match FileCache::open(&path, 10) {
+            Err(Error::UnsupportedVersion(2)) => {}
+            r => panic!("unexpected result {:?}", r.err()),
+        }
+
+        let mut data = valid.clone();
+        data[20] ^= 1;
+        fs::write(&path, &data).unwrap();
+        match FileCache::open(&path, 10) {
+            Err(Error::InvalidChecksum) => {}
+            r => panic!("unexpected result {:?}", r.err()),
+        }
+
+        fs::write(&path, b"garbage").unwrap();
+        match FileCache::open(&path, 10) {
+            Err(Error::InvalidMagic) => {}
+            r => panic!("unexpected result {:?}", r.err()),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_colorer_with_cache() {
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![txout(600, p2pkh(1)), marker(vec![100], b"")],
+        );
+        let transfer = tx(
+            &[OutPoint::new(issuance.txid(), 0)],
+            vec![marker(vec![100], b""), txout(600, p2pkh(2))],
+        );
+        let mut source: HashMap<Txid, Transaction> = HashMap::new();
+        for t in [&funding, &issuance, &transfer] {
+            source.insert(t.txid(), t.clone());
+        }
+
+        let dir = temp_dir("cache-colorer");
+        let path = dir.join("cache");
+        let mut colorer = Colorer::new(&source, Network::Prod)
+            .with_cache(Box::new(FileCache::open(&path, 100).unwrap()));
+        let expected = colorer
+            .get_output(&OutPoint::new(issuance.txid(), 0))
+            .unwrap();
+        colorer.flush_cache().unwrap();
+        drop(colorer);
+
+        // the ancestry of the issuance is no longer needed after a restart
+        source.remove(&funding.txid());
+        let mut colorer = Colorer::new(&source, Network::Prod)
+            .with_cache(Box::new(FileCache::open(&path, 100).unwrap()));
+        assert_eq!(
+            expected,
+            colorer
+                .get_output(&OutPoint::new(issuance.txid(), 0))
+                .unwrap()
+        );
+        let output = colorer
+            .get_output(&OutPoint::new(transfer.txid(), 1))
+            .unwrap();
+        assert_eq!(AssetId::from_str(ISSUER_ASSET_ID).ok(), output.asset_id);
+        assert_eq!(100, output.asset_quantity);
+        drop(colorer);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
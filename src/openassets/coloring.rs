@@ -0,0 +1,1099 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use tapyrus::network::constants::Network;
+use tapyrus::{OutPoint, Script, Transaction, TxOut, Txid};
+
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::cache::{self, ColoringCache};
+use crate::openassets::marker_output::{MarkerVersions, Payload, TxOutExt, VersionedPayload};
+use crate::openassets::network::NetworkParams;
+
+/// The maximum asset quantity allowed by the Open Assets Protocol (2^63 - 1).
+pub const MAX_ASSET_QUANTITY: u64 = (1 << 63) - 1;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum OutputType {
+    Uncolored,
+    MarkerOutput,
+    Issuance,
+    Transfer,
+}
+
+impl fmt::Display for OutputType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            OutputType::Uncolored => "uncolored",
+            OutputType::MarkerOutput => "marker_output",
+            OutputType::Issuance => "issuance",
+            OutputType::Transfer => "transfer",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A transaction output together with its Open Assets coloring.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ColoredOutput {
+    pub value: u64,
+    pub script_pubkey: Script,
+    pub asset_id: Option<AssetId>,
+    pub asset_quantity: u64,
+    pub output_type: OutputType,
+}
+
+impl ColoredOutput {
+    pub fn uncolored(txout: &TxOut) -> Self {
+        ColoredOutput {
+            value: txout.value,
+            script_pubkey: txout.script_pubkey.clone(),
+            asset_id: None,
+            asset_quantity: 0,
+            output_type: OutputType::Uncolored,
+        }
+    }
+
+    pub fn is_colored(&self) -> bool {
+        self.asset_id.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The transaction source does not know the transaction.
+    TransactionNotFound(Txid),
+    /// The transaction exists but has no output at the index.
+    OutputNotFound(OutPoint),
+    /// The transaction source failed to provide the transaction.
+    Source(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::TransactionNotFound(ref txid) => write!(f, "transaction {} not found", txid),
+            Error::OutputNotFound(ref outpoint) => write!(f, "output {} not found", outpoint),
+            Error::Source(ref e) => write!(f, "transaction source error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Provides previous transactions to the colorer.
+pub trait TxSource {
+    fn get_transaction(&self, txid: &Txid) -> Result<Transaction, Error>;
+}
+
+impl<T: TxSource + ?Sized> TxSource for &T {
+    fn get_transaction(&self, txid: &Txid) -> Result<Transaction, Error> {
+        (**self).get_transaction(txid)
+    }
+}
+
+impl TxSource for HashMap<Txid, Transaction> {
+    fn get_transaction(&self, txid: &Txid) -> Result<Transaction, Error> {
+        self.get(txid)
+            .cloned()
+            .ok_or(Error::TransactionNotFound(*txid))
+    }
+}
+
+/// Returns the index and payload of the marker output, which is the first output
+/// carrying a valid Open Assets payload.
+pub fn find_marker(tx: &Transaction) -> Option<(usize, Payload)> {
+    tx.output
+        .iter()
+        .enumerate()
+        .find_map(|(i, o)| o.get_oa_payload().ok().map(|p| (i, p)))
+}
+
+/// Like `find_marker`, accepting the marker versions of `versions`.
+pub fn find_marker_with(
+    tx: &Transaction,
+    versions: &MarkerVersions,
+) -> Option<(usize, VersionedPayload)> {
+    tx.output
+        .iter()
+        .enumerate()
+        .find_map(|(i, o)| o.get_versioned_payload(versions).ok().map(|p| (i, p)))
+}
+
+/// The role and asset quantity the marker output assigns to an output.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct OutputRole {
+    pub output_type: OutputType,
+    pub quantity: u64,
+}
+
+/// Returns the index and payload of the marker output with the role of each
+/// output, as far as it can be told without the inputs.
+///
+/// As in `compute_asset_ids`, outputs before the marker are issuance outputs
+/// and outputs after it transfer outputs, even when their quantity is zero.
+pub fn output_roles(tx: &Transaction) -> Option<(usize, Payload, Vec<OutputRole>)> {
+    output_roles_with(tx, &MarkerVersions::default())
+}
+
+/// Like `output_roles`, accepting the marker versions of `versions`.
+pub fn output_roles_with(
+    tx: &Transaction,
+    versions: &MarkerVersions,
+) -> Option<(usize, Payload, Vec<OutputRole>)> {
+    let (marker, payload) = find_marker_with(tx, versions).map(|(i, p)| (i, p.payload))?;
+    let roles = (0..tx.output.len())
+        .map(|i| {
+            let quantity = match i {
+                i if i < marker => payload.quantities.get(i),
+                i if i > marker => payload.quantities.get(i - 1),
+                _ => None,
+            }
+            .copied()
+            .unwrap_or(0);
+            let output_type = if i == marker {
+                OutputType::MarkerOutput
+            } else if i < marker {
+                OutputType::Issuance
+            } else {
+                OutputType::Transfer
+            };
+            OutputRole {
+                output_type,
+                quantity,
+            }
+        })
+        .collect();
+    Some((marker, payload, roles))
+}
+
+/// The reason a transaction is not a valid Open Assets transaction.
+///
+/// Output indices refer to `tx.output` and input indices to `tx.input`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Diagnosis {
+    /// No output carries a valid marker payload.
+    MarkerMissing,
+    /// Coinbase transactions can not issue or transfer assets.
+    Coinbase,
+    /// The transaction has no inputs to derive the issued asset id from.
+    NoInputs,
+    /// The marker output lists more quantities than there are other outputs.
+    TooManyQuantities { quantities: usize, outputs: usize },
+    /// The quantity of an output exceeds `MAX_ASSET_QUANTITY`.
+    QuantityOverflow { output: usize, quantity: u64 },
+    /// The inputs run out of asset units before `output` is filled.
+    InsufficientInputQuantity {
+        output: usize,
+        quantity: u64,
+        missing: u64,
+        inputs: Vec<usize>,
+    },
+    /// The inputs feeding `output` carry different asset ids.
+    AssetIdMismatch {
+        output: usize,
+        inputs: Vec<usize>,
+        asset_ids: (AssetId, AssetId),
+    },
+}
+
+impl Diagnosis {
+    /// A short machine readable name of the failed rule.
+    pub fn rule(&self) -> &'static str {
+        match *self {
+            Diagnosis::MarkerMissing => "marker_missing",
+            Diagnosis::Coinbase => "coinbase",
+            Diagnosis::NoInputs => "no_inputs",
+            Diagnosis::TooManyQuantities { .. } => "too_many_quantities",
+            Diagnosis::QuantityOverflow { .. } => "quantity_overflow",
+            Diagnosis::InsufficientInputQuantity { .. } => "insufficient_input_quantity",
+            Diagnosis::AssetIdMismatch { .. } => "asset_id_mismatch",
+        }
+    }
+}
+
+fn join_indices(indices: &[usize]) -> String {
+    indices
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnosis::MarkerMissing => write!(f, "the transaction has no valid marker output"),
+            Diagnosis::Coinbase => write!(f, "coinbase transactions can not carry assets"),
+            Diagnosis::NoInputs => write!(f, "the transaction has no inputs"),
+            Diagnosis::TooManyQuantities {
+                quantities,
+                outputs,
+            } => write!(
+                f,
+                "the marker output lists {} asset quantities but there are only {} other outputs",
+                quantities, outputs
+            ),
+            Diagnosis::QuantityOverflow { output, quantity } => write!(
+                f,
+                "asset quantity {} of output {} exceeds the maximum of {}",
+                quantity, output, MAX_ASSET_QUANTITY
+            ),
+            Diagnosis::InsufficientInputQuantity {
+                output,
+                quantity,
+                missing,
+                ref inputs,
+            } => write!(
+                f,
+                "output {} requires {} asset units but inputs [{}] lack {} of them",
+                output,
+                quantity,
+                join_indices(inputs),
+                missing
+            ),
+            Diagnosis::AssetIdMismatch {
+                output,
+                ref inputs,
+                ref asset_ids,
+            } => write!(
+                f,
+                "inputs [{}] feeding output {} carry different asset ids {} and {}",
+                join_indices(inputs),
+                output,
+                asset_ids.0,
+                asset_ids.1
+            ),
+        }
+    }
+}
+
+impl Serialize for Diagnosis {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Diagnosis", 6)?;
+        state.serialize_field("rule", self.rule())?;
+        match *self {
+            Diagnosis::MarkerMissing | Diagnosis::Coinbase | Diagnosis::NoInputs => {}
+            Diagnosis::TooManyQuantities {
+                quantities,
+                outputs,
+            } => {
+                state.serialize_field("quantities", &quantities)?;
+                state.serialize_field("outputs", &outputs)?;
+            }
+            Diagnosis::QuantityOverflow { output, quantity } => {
+                state.serialize_field("output", &output)?;
+                state.serialize_field("quantity", &quantity)?;
+            }
+            Diagnosis::InsufficientInputQuantity {
+                output,
+                quantity,
+                missing,
+                ref inputs,
+            } => {
+                state.serialize_field("output", &output)?;
+                state.serialize_field("quantity", &quantity)?;
+                state.serialize_field("missing", &missing)?;
+                state.serialize_field("inputs", inputs)?;
+            }
+            Diagnosis::AssetIdMismatch {
+                output,
+                ref inputs,
+                ref asset_ids,
+            } => {
+                state.serialize_field("output", &output)?;
+                state.serialize_field("inputs", inputs)?;
+                let ids = [asset_ids.0.to_string(), asset_ids.1.to_string()];
+                state.serialize_field("asset_ids", &ids)?;
+            }
+        }
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// Applies the Open Assets coloring rules to `tx`.
+///
+/// `inputs` are the colored outputs spent by `tx`, in input order.
+/// Returns the rule `tx` violates if it is not a valid Open Assets transaction.
+pub fn compute_asset_ids(
+    tx: &Transaction,
+    inputs: &[ColoredOutput],
+    network: Network,
+) -> Result<Vec<ColoredOutput>, Diagnosis> {
+    compute_from_marker(tx, find_marker(tx), inputs, NetworkParams::tapyrus(network))
+}
+
+/// Like `compute_asset_ids`, issuing the asset ids of `params` and accepting
+/// the marker versions of `versions`.
+///
+/// Extended versions are colored from their quantities; their extension
+/// data is left to the caller.
+pub fn compute_asset_ids_with(
+    tx: &Transaction,
+    inputs: &[ColoredOutput],
+    params: &'static NetworkParams,
+    versions: &MarkerVersions,
+) -> Result<Vec<ColoredOutput>, Diagnosis> {
+    let marker = find_marker_with(tx, versions).map(|(i, p)| (i, p.payload));
+    compute_from_marker(tx, marker, inputs, params)
+}
+
+fn compute_from_marker(
+    tx: &Transaction,
+    marker: Option<(usize, Payload)>,
+    inputs: &[ColoredOutput],
+    params: &'static NetworkParams,
+) -> Result<Vec<ColoredOutput>, Diagnosis> {
+    let (marker_index, payload) = marker.ok_or(Diagnosis::MarkerMissing)?;
+    let quantities = &payload.quantities;
+    if quantities.len() > tx.output.len() - 1 {
+        return Err(Diagnosis::TooManyQuantities {
+            quantities: quantities.len(),
+            outputs: tx.output.len() - 1,
+        });
+    }
+    if inputs.is_empty() {
+        return Err(Diagnosis::NoInputs);
+    }
+    if let Some((i, &quantity)) = quantities
+        .iter()
+        .enumerate()
+        .find(|&(_, &q)| q > MAX_ASSET_QUANTITY)
+    {
+        return Err(Diagnosis::QuantityOverflow {
+            output: if i < marker_index { i } else { i + 1 },
+            quantity,
+        });
+    }
+
+    let mut result = Vec::with_capacity(tx.output.len());
+
+    // issuance outputs
+    let issuance_asset_id = AssetId::with_params(&inputs[0].script_pubkey, params);
+    for (i, output) in tx.output[..marker_index].iter().enumerate() {
+        let quantity = quantities.get(i).cloned().unwrap_or(0);
+        result.push(ColoredOutput {
+            value: output.value,
+            script_pubkey: output.script_pubkey.clone(),
+            asset_id: if quantity > 0 {
+                Some(issuance_asset_id.clone())
+            } else {
+                None
+            },
+            asset_quantity: quantity,
+            output_type: OutputType::Issuance,
+        });
+    }
+
+    // marker output
+    let marker_output = &tx.output[marker_index];
+    result.push(ColoredOutput {
+        output_type: OutputType::MarkerOutput,
+        ..ColoredOutput::uncolored(marker_output)
+    });
+
+    // transfer outputs
+    let mut input_iter = inputs.iter().enumerate();
+    let mut current_input: Option<(usize, &ColoredOutput)> = None;
+    let mut input_units_left: u64 = 0;
+    for i in (marker_index + 1)..tx.output.len() {
+        let quantity = quantities.get(i - 1).cloned().unwrap_or(0);
+        let mut output_units_left = quantity;
+        let mut asset_id: Option<AssetId> = None;
+        // inputs the units of this output are taken from
+        let mut feeding: Vec<usize> = vec![];
+        while output_units_left > 0 {
+            if input_units_left == 0 {
+                let (index, input) =
+                    input_iter
+                        .next()
+                        .ok_or_else(|| Diagnosis::InsufficientInputQuantity {
+                            output: i,
+                            quantity,
+                            missing: output_units_left,
+                            inputs: feeding.clone(),
+                        })?;
+                input_units_left = input.asset_quantity;
+                current_input = Some((index, input));
+            }
+            let (index, input) = current_input.unwrap();
+            if let Some(ref input_asset_id) = input.asset_id {
+                if feeding.last() != Some(&index) {
+                    feeding.push(index);
+                }
+                let progress = std::cmp::min(input_units_left, output_units_left);
+                output_units_left -= progress;
+                input_units_left -= progress;
+                match asset_id {
+                    None => asset_id = Some(input_asset_id.clone()),
+                    Some(ref id) if id != input_asset_id => {
+                        return Err(Diagnosis::AssetIdMismatch {
+                            output: i,
+                            inputs: feeding,
+                            asset_ids: (id.clone(), input_asset_id.clone()),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let output = &tx.output[i];
+        result.push(ColoredOutput {
+            value: output.value,
+            script_pubkey: output.script_pubkey.clone(),
+            asset_id,
+            asset_quantity: quantity,
+            output_type: OutputType::Transfer,
+        });
+    }
+    Ok(result)
+}
+
+/// Colors the outputs of `tx`, leaving every output uncolored if `tx` is not a
+/// valid Open Assets transaction.
+pub fn color_outputs(
+    tx: &Transaction,
+    inputs: &[ColoredOutput],
+    network: Network,
+) -> Vec<ColoredOutput> {
+    compute_asset_ids(tx, inputs, network)
+        .unwrap_or_else(|_| tx.output.iter().map(ColoredOutput::uncolored).collect())
+}
+
+/// Like `color_outputs`, with the asset ids of `params` and the marker
+/// versions of `versions`.
+pub fn color_outputs_with(
+    tx: &Transaction,
+    inputs: &[ColoredOutput],
+    params: &'static NetworkParams,
+    versions: &MarkerVersions,
+) -> Vec<ColoredOutput> {
+    compute_asset_ids_with(tx, inputs, params, versions)
+        .unwrap_or_else(|_| tx.output.iter().map(ColoredOutput::uncolored).collect())
+}
+
+/// Returns why `tx` is not a valid Open Assets transaction, or `None` if it is.
+pub fn diagnose(tx: &Transaction, inputs: &[ColoredOutput], network: Network) -> Option<Diagnosis> {
+    if tx.is_coin_base() {
+        return Some(Diagnosis::Coinbase);
+    }
+    compute_asset_ids(tx, inputs, network).err()
+}
+
+/// Like `diagnose`, with the asset ids of `params` and the marker versions of
+/// `versions`.
+pub fn diagnose_with(
+    tx: &Transaction,
+    inputs: &[ColoredOutput],
+    params: &'static NetworkParams,
+    versions: &MarkerVersions,
+) -> Option<Diagnosis> {
+    if tx.is_coin_base() {
+        return Some(Diagnosis::Coinbase);
+    }
+    compute_asset_ids_with(tx, inputs, params, versions).err()
+}
+
+/// Returns true if coloring `tx` requires the colored outputs it spends.
+///
+/// Outputs of a transaction without a marker output are always uncolored, so
+/// its ancestry never has to be looked up.
+pub fn needs_inputs(tx: &Transaction) -> bool {
+    needs_inputs_with(tx, &MarkerVersions::default())
+}
+
+/// Like `needs_inputs`, accepting the marker versions of `versions`.
+pub fn needs_inputs_with(tx: &Transaction, versions: &MarkerVersions) -> bool {
+    !tx.is_coin_base() && find_marker_with(tx, versions).is_some()
+}
+
+/// Colors transactions by recursively fetching their ancestry from a `TxSource`.
+pub struct Colorer<S: TxSource> {
+    source: S,
+    params: &'static NetworkParams,
+    versions: MarkerVersions,
+    colored: HashMap<Txid, Vec<ColoredOutput>>,
+    cache: Option<Box<dyn ColoringCache + Send>>,
+}
+
+impl<S: TxSource> Colorer<S> {
+    pub fn new(source: S, network: Network) -> Self {
+        Colorer::with_params(source, NetworkParams::tapyrus(network))
+    }
+
+    /// Colors with the asset ids of `params`, e.g. of a Bitcoin network.
+    pub fn with_params(source: S, params: &'static NetworkParams) -> Self {
+        Colorer {
+            source,
+            params,
+            versions: MarkerVersions::default(),
+            colored: HashMap::new(),
+            cache: None,
+        }
+    }
+
+    /// Finds marker outputs of the versions in `versions`; version 0x0100 only
+    /// by default.
+    pub fn with_marker_versions(mut self, versions: MarkerVersions) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Uses `cache` to look up and record the coloring of outputs, so that the
+    /// ancestry of cached outputs is not walked again.
+    pub fn with_cache(mut self, cache: Box<dyn ColoringCache + Send>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Persists the coloring results recorded in the cache.
+    pub fn flush_cache(&mut self) -> Result<(), cache::Error> {
+        match self.cache {
+            Some(ref mut cache) => cache.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    pub fn network(&self) -> Network {
+        self.params.network
+    }
+
+    pub fn params(&self) -> &'static NetworkParams {
+        self.params
+    }
+
+    pub fn marker_versions(&self) -> &MarkerVersions {
+        &self.versions
+    }
+
+    /// Returns the colored output referenced by `outpoint`.
+    pub fn get_output(&mut self, outpoint: &OutPoint) -> Result<ColoredOutput, Error> {
+        if !self.colored.contains_key(&outpoint.txid) {
+            if let Some(output) = self.get_cached_output(outpoint)? {
+                return Ok(output);
+            }
+            let tx = self.source.get_transaction(&outpoint.txid)?;
+            self.color_transaction(&tx)?;
+        }
+        self.colored[&outpoint.txid]
+            .get(outpoint.vout as usize)
+            .cloned()
+            .ok_or(Error::OutputNotFound(*outpoint))
+    }
+
+    /// Returns true if the coloring of `outpoint` is known without walking its ancestry.
+    fn is_known(&mut self, outpoint: &OutPoint) -> bool {
+        self.colored.contains_key(&outpoint.txid)
+            || self
+                .cache
+                .as_mut()
+                .is_some_and(|cache| cache.get(outpoint).is_some())
+    }
+
+    /// Looks up `outpoint` in the cache, fetching only its own transaction.
+    fn get_cached_output(&mut self, outpoint: &OutPoint) -> Result<Option<ColoredOutput>, Error> {
+        let color = match self.cache.as_mut().and_then(|cache| cache.get(outpoint)) {
+            Some(color) => color,
+            None => return Ok(None),
+        };
+        let tx = self.source.get_transaction(&outpoint.txid)?;
+        let txout = tx
+            .output
+            .get(outpoint.vout as usize)
+            .ok_or(Error::OutputNotFound(*outpoint))?;
+        Ok(Some(color.to_colored_output(txout)))
+    }
+
+    /// Colors all outputs of `tx`.
+    ///
+    /// Ancestors are walked with an explicit stack so that long transfer chains
+    /// do not exhaust the call stack.
+    pub fn color_transaction(&mut self, tx: &Transaction) -> Result<Vec<ColoredOutput>, Error> {
+        let txid = tx.txid();
+        if let Some(outputs) = self.colored.get(&txid) {
+            return Ok(outputs.clone());
+        }
+
+        let mut stack: Vec<Transaction> = vec![tx.clone()];
+        while let Some(top) = stack.last() {
+            let mut pending = None;
+            if needs_inputs_with(top, &self.versions) {
+                for input in top.input.iter() {
+                    if !self.is_known(&input.previous_output) {
+                        pending = Some(input.previous_output.txid);
+                        break;
+                    }
+                }
+            }
+            match pending {
+                Some(prev_txid) => {
+                    let prev_tx = self.source.get_transaction(&prev_txid)?;
+                    stack.push(prev_tx);
+                }
+                None => {
+                    let top = stack.pop().unwrap();
+                    let outputs = self.color_with_known_inputs(&top)?;
+                    let top_txid = top.txid();
+                    if let Some(ref mut cache) = self.cache {
+                        for (vout, output) in outputs.iter().enumerate() {
+                            cache.put(OutPoint::new(top_txid, vout as u32), output.into());
+                        }
+                    }
+                    self.colored.insert(top_txid, outputs);
+                }
+            }
+        }
+        Ok(self.colored[&txid].clone())
+    }
+
+    /// Returns why `tx` is not a valid Open Assets transaction, or `None` if
+    /// it is, coloring its ancestry as needed.
+    pub fn diagnose_transaction(&mut self, tx: &Transaction) -> Result<Option<Diagnosis>, Error> {
+        if !needs_inputs_with(tx, &self.versions) {
+            return Ok(diagnose_with(tx, &[], self.params, &self.versions));
+        }
+        self.color_transaction(tx)?;
+        let inputs = self.known_inputs(tx)?;
+        Ok(diagnose_with(tx, &inputs, self.params, &self.versions))
+    }
+
+    fn color_with_known_inputs(&mut self, tx: &Transaction) -> Result<Vec<ColoredOutput>, Error> {
+        if !needs_inputs_with(tx, &self.versions) {
+            return Ok(tx.output.iter().map(ColoredOutput::uncolored).collect());
+        }
+        let inputs = self.known_inputs(tx)?;
+        Ok(color_outputs_with(tx, &inputs, self.params, &self.versions))
+    }
+
+    /// Collects the colored outputs spent by `tx`, whose coloring must be known.
+    fn known_inputs(&mut self, tx: &Transaction) -> Result<Vec<ColoredOutput>, Error> {
+        let mut inputs = Vec::with_capacity(tx.input.len());
+        for input in tx.input.iter() {
+            let outpoint = input.previous_output;
+            let output = match self.colored.get(&outpoint.txid) {
+                Some(outputs) => outputs.get(outpoint.vout as usize).cloned(),
+                None => self.get_cached_output(&outpoint)?,
+            };
+            inputs.push(output.ok_or(Error::OutputNotFound(outpoint))?);
+        }
+        Ok(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::coloring::{
+        compute_asset_ids, compute_asset_ids_with, find_marker_with, needs_inputs,
+        needs_inputs_with, output_roles, output_roles_with, ColoredOutput, Colorer, Diagnosis,
+        Error, OutputType, MAX_ASSET_QUANTITY,
+    };
+    use crate::openassets::marker_output::{MarkerVersions, V1Decoder};
+    use crate::openassets::network::{BITCOIN, TAPYRUS_PROD};
+    use crate::openassets::test_helpers::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use tapyrus::network::constants::Network;
+    use tapyrus::{OutPoint, Transaction, Txid};
+
+    fn uncolored() -> ColoredOutput {
+        ColoredOutput::uncolored(&txout(1000, p2pkh(8)))
+    }
+
+    #[test]
+    fn test_issuance_and_transfer() {
+        let tx = spend(
+            vec![
+                txout(600, p2pkh(1)),
+                txout(600, p2pkh(2)),
+                marker(vec![100, 0, 30, 10], b""),
+                txout(600, p2pkh(3)),
+                txout(600, p2pkh(4)),
+                txout(600, p2pkh(5)),
+            ],
+            2,
+        );
+        let mut issuer = uncolored();
+        issuer.script_pubkey = script(ISSUER_SCRIPT);
+        let inputs = vec![issuer, colored(40, &asset(7))];
+        let outputs = compute_asset_ids(&tx, &inputs, Network::Prod).unwrap();
+
+        let issued = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        assert_eq!(Some(issued), outputs[0].asset_id);
+        assert_eq!(100, outputs[0].asset_quantity);
+        assert_eq!(OutputType::Issuance, outputs[0].output_type);
+        assert_eq!(None, outputs[1].asset_id);
+        assert_eq!(OutputType::Issuance, outputs[1].output_type);
+        assert_eq!(OutputType::MarkerOutput, outputs[2].output_type);
+        assert_eq!(Some(asset(7)), outputs[3].asset_id);
+        assert_eq!(30, outputs[3].asset_quantity);
+        assert_eq!(Some(asset(7)), outputs[4].asset_id);
+        assert_eq!(10, outputs[4].asset_quantity);
+        assert_eq!(None, outputs[5].asset_id);
+        assert_eq!(OutputType::Transfer, outputs[5].output_type);
+    }
+
+    #[test]
+    fn test_output_roles() {
+        let tx = spend(
+            vec![
+                txout(600, p2pkh(1)),
+                marker(vec![100, 0, 30], b"u=x"),
+                txout(600, p2pkh(2)),
+                txout(600, p2pkh(3)),
+                txout(600, p2pkh(4)),
+            ],
+            1,
+        );
+        let (marker, payload, roles) = output_roles(&tx).unwrap();
+        assert_eq!(1, marker);
+        assert_eq!(vec![100, 0, 30], payload.quantities);
+        let roles: Vec<(OutputType, u64)> =
+            roles.iter().map(|r| (r.output_type, r.quantity)).collect();
+        assert_eq!(
+            vec![
+                (OutputType::Issuance, 100),
+                (OutputType::MarkerOutput, 0),
+                (OutputType::Transfer, 0),
+                (OutputType::Transfer, 30),
+                (OutputType::Transfer, 0),
+            ],
+            roles
+        );
+        assert!(output_roles(&spend(vec![txout(600, p2pkh(1))], 1)).is_none());
+    }
+
+    #[test]
+    fn test_marker_versions() {
+        // version 0x0200 with the layout of 0x0100: 100 units and no metadata
+        let data = hex::decode("4f410200016400").unwrap();
+        let extended = txout(
+            0,
+            tapyrus::blockdata::script::Builder::new()
+                .push_opcode(tapyrus::blockdata::opcodes::all::OP_RETURN)
+                .push_slice(&data)
+                .into_script(),
+        );
+        let tx = spend(vec![txout(600, p2pkh(1)), extended], 1);
+        let inputs = [uncolored()];
+        assert_eq!(
+            Err(Diagnosis::MarkerMissing),
+            compute_asset_ids(&tx, &inputs, Network::Prod)
+        );
+
+        let versions = MarkerVersions::new().with_version(0x0200, V1Decoder);
+        let (index, payload) = find_marker_with(&tx, &versions).unwrap();
+        assert_eq!((1, 0x0200), (index, payload.version));
+        let outputs = compute_asset_ids_with(&tx, &inputs, &TAPYRUS_PROD, &versions).unwrap();
+        assert_eq!(Some(asset(8)), outputs[0].asset_id);
+        assert_eq!(100, outputs[0].asset_quantity);
+        assert_eq!(OutputType::MarkerOutput, outputs[1].output_type);
+    }
+
+    #[test]
+    fn test_colorer_marker_versions() {
+        let data = hex::decode("4f410200016400").unwrap();
+        let extended = txout(
+            0,
+            tapyrus::blockdata::script::Builder::new()
+                .push_opcode(tapyrus::blockdata::opcodes::all::OP_RETURN)
+                .push_slice(&data)
+                .into_script(),
+        );
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![txout(600, p2pkh(1)), extended],
+        );
+        let source: HashMap<Txid, Transaction> = [funding, issuance.clone()]
+            .into_iter()
+            .map(|t| (t.txid(), t))
+            .collect();
+        let versions = MarkerVersions::new().with_version(0x0200, V1Decoder);
+        assert!(!needs_inputs(&issuance));
+        assert!(needs_inputs_with(&issuance, &versions));
+        assert!(output_roles(&issuance).is_none());
+        assert_eq!(1, output_roles_with(&issuance, &versions).unwrap().0);
+
+        let mut colorer = Colorer::new(source.clone(), Network::Prod);
+        assert_eq!(
+            None,
+            colorer.color_transaction(&issuance).unwrap()[0].asset_id
+        );
+        assert_eq!(
+            Some(Diagnosis::MarkerMissing),
+            colorer.diagnose_transaction(&issuance).unwrap()
+        );
+
+        let mut colorer = Colorer::new(source, Network::Prod).with_marker_versions(versions);
+        let outputs = colorer.color_transaction(&issuance).unwrap();
+        assert_eq!(AssetId::from_str(ISSUER_ASSET_ID).ok(), outputs[0].asset_id);
+        assert_eq!(100, outputs[0].asset_quantity);
+        assert_eq!(None, colorer.diagnose_transaction(&issuance).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_transactions() {
+        // too many quantities
+        let tx = spend(vec![marker(vec![1, 2], b""), txout(600, p2pkh(1))], 1);
+        assert_eq!(
+            Err(Diagnosis::TooManyQuantities {
+                quantities: 2,
+                outputs: 1
+            }),
+            compute_asset_ids(&tx, &[colored(3, &asset(1))], Network::Prod)
+        );
+
+        // insufficient input quantity
+        let tx = spend(
+            vec![
+                marker(vec![2, 5], b""),
+                txout(600, p2pkh(1)),
+                txout(600, p2pkh(2)),
+            ],
+            1,
+        );
+        assert_eq!(
+            Err(Diagnosis::InsufficientInputQuantity {
+                output: 2,
+                quantity: 5,
+                missing: 3,
+                inputs: vec![0]
+            }),
+            compute_asset_ids(&tx, &[colored(4, &asset(1))], Network::Prod)
+        );
+
+        // mixed asset ids for one output
+        let tx = spend(vec![marker(vec![5], b""), txout(600, p2pkh(1))], 3);
+        let inputs = vec![colored(3, &asset(1)), uncolored(), colored(2, &asset(2))];
+        assert_eq!(
+            Err(Diagnosis::AssetIdMismatch {
+                output: 1,
+                inputs: vec![0, 2],
+                asset_ids: (asset(1), asset(2))
+            }),
+            compute_asset_ids(&tx, &inputs, Network::Prod)
+        );
+
+        // quantity above the maximum
+        let tx = spend(
+            vec![
+                txout(600, p2pkh(1)),
+                marker(vec![1, MAX_ASSET_QUANTITY + 1], b""),
+                txout(600, p2pkh(2)),
+            ],
+            1,
+        );
+        assert_eq!(
+            Err(Diagnosis::QuantityOverflow {
+                output: 2,
+                quantity: MAX_ASSET_QUANTITY + 1
+            }),
+            compute_asset_ids(&tx, &[uncolored()], Network::Prod)
+        );
+
+        // no inputs, which the consensus encoding cannot express
+        let mut tx = spend(vec![txout(600, p2pkh(1)), marker(vec![1], b"")], 1);
+        tx.input.clear();
+        assert_eq!(
+            Err(Diagnosis::NoInputs),
+            compute_asset_ids(&tx, &[], Network::Prod)
+        );
+
+        // no marker
+        let tx = spend(vec![txout(600, p2pkh(1))], 1);
+        assert_eq!(
+            Err(Diagnosis::MarkerMissing),
+            compute_asset_ids(&tx, &[colored(3, &asset(1))], Network::Prod)
+        );
+    }
+
+    #[test]
+    fn test_diagnosis_rendering() {
+        let diagnosis = Diagnosis::InsufficientInputQuantity {
+            output: 2,
+            quantity: 5,
+            missing: 3,
+            inputs: vec![0, 1],
+        };
+        assert_eq!(
+            "output 2 requires 5 asset units but inputs [0, 1] lack 3 of them",
+            diagnosis.to_string()
+        );
+        assert_eq!(
+            json!({
+                "rule": "insufficient_input_quantity",
+                "output": 2,
+                "quantity": 5,
+                "missing": 3,
+                "inputs": [0, 1],
+                "message": "output 2 requires 5 asset units but inputs [0, 1] lack 3 of them"
+            }),
+            json!(diagnosis)
+        );
+
+        let diagnosis = Diagnosis::AssetIdMismatch {
+            output: 1,
+            inputs: vec![0, 2],
+            asset_ids: (asset(1), asset(2)),
+        };
+        let value = json!(diagnosis);
+        assert_eq!("asset_id_mismatch", value["rule"]);
+        assert_eq!(
+            json!([asset(1).to_string(), asset(2).to_string()]),
+            value["asset_ids"]
+        );
+        assert_eq!(
+            json!({"rule": "marker_missing", "message": "the transaction has no valid marker output"}),
+            json!(Diagnosis::MarkerMissing)
+        );
+    }
+
+    #[test]
+    fn test_transfer_skips_uncolored_inputs() {
+        let tx = spend(vec![marker(vec![5], b""), txout(600, p2pkh(1))], 3);
+        let inputs = vec![colored(3, &asset(1)), uncolored(), colored(2, &asset(1))];
+        let outputs = compute_asset_ids(&tx, &inputs, Network::Prod).unwrap();
+        assert_eq!(Some(asset(1)), outputs[1].asset_id);
+        assert_eq!(5, outputs[1].asset_quantity);
+    }
+
+    #[test]
+    fn test_colorer() {
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![
+                txout(600, p2pkh(1)),
+                marker(vec![100], b"u=https://cpr.sm/5YgSU1Pg-q"),
+            ],
+        );
+        let transfer = tx(
+            &[OutPoint::new(issuance.txid(), 0)],
+            vec![
+                marker(vec![60, 40], b""),
+                txout(600, p2pkh(2)),
+                txout(600, p2pkh(3)),
+            ],
+        );
+        // spending colored outputs without a marker destroys the assets
+        let burn = tx(
+            &[OutPoint::new(transfer.txid(), 1)],
+            vec![txout(500, p2pkh(4))],
+        );
+
+        let mut source: HashMap<Txid, Transaction> = HashMap::new();
+        for t in [&funding, &issuance, &transfer, &burn] {
+            source.insert(t.txid(), t.clone());
+        }
+        let mut colorer = Colorer::new(&source, Network::Prod);
+
+        let issued = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        let output = colorer
+            .get_output(&OutPoint::new(transfer.txid(), 2))
+            .unwrap();
+        assert_eq!(Some(issued.clone()), output.asset_id);
+        assert_eq!(40, output.asset_quantity);
+        assert_eq!(OutputType::Transfer, output.output_type);
+
+        let outputs = colorer.color_transaction(&burn).unwrap();
+        assert!(!outputs[0].is_colored());
+        assert_eq!(OutputType::Uncolored, outputs[0].output_type);
+        assert_eq!(None, colorer.diagnose_transaction(&transfer).unwrap());
+        assert_eq!(
+            Some(Diagnosis::MarkerMissing),
+            colorer.diagnose_transaction(&burn).unwrap()
+        );
+
+        // transferring more than the inputs hold
+        let overdraw = tx(
+            &[OutPoint::new(transfer.txid(), 2)],
+            vec![marker(vec![41], b""), txout(600, p2pkh(5))],
+        );
+        assert!(!colorer.color_transaction(&overdraw).unwrap()[1].is_colored());
+        assert_eq!(
+            Some(Diagnosis::InsufficientInputQuantity {
+                output: 1,
+                quantity: 41,
+                missing: 1,
+                inputs: vec![0]
+            }),
+            colorer.diagnose_transaction(&overdraw).unwrap()
+        );
+
+        match colorer.get_output(&OutPoint::new(transfer.txid(), 5)) {
+            Err(Error::OutputNotFound(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        // missing ancestry
+        drop(colorer);
+        source.remove(&funding.txid());
+        let mut colorer = Colorer::new(&source, Network::Prod);
+        match colorer.get_output(&OutPoint::new(transfer.txid(), 1)) {
+            Err(Error::TransactionNotFound(txid)) => assert_eq!(funding.txid(), txid),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_long_chain() {
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let mut source: HashMap<Txid, Transaction> = HashMap::new();
+        source.insert(funding.txid(), funding.clone());
+        let mut prev = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![txout(600, p2pkh(1)), marker(vec![100], b"")],
+        );
+        for _ in 0..5_000 {
+            source.insert(prev.txid(), prev.clone());
+            prev = tx(
+                &[OutPoint::new(
+                    prev.txid(),
+                    if prev.output[0].value == 0 { 1 } else { 0 },
+                )],
+                vec![marker(vec![100], b""), txout(600, p2pkh(1))],
+            );
+        }
+        let mut colorer = Colorer::new(&source, Network::Prod);
+        let outputs = colorer.color_transaction(&prev).unwrap();
+        assert_eq!(100, outputs[1].asset_quantity);
+        assert_eq!(AssetId::from_str(ISSUER_ASSET_ID).ok(), outputs[1].asset_id);
+    }
+
+    #[test]
+    fn test_bitcoin_params() {
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![txout(600, p2pkh(1)), marker(vec![100], b"")],
+        );
+        let source: HashMap<Txid, Transaction> =
+            [funding].into_iter().map(|t| (t.txid(), t)).collect();
+        let mut colorer = Colorer::with_params(&source, &BITCOIN);
+        assert_eq!(&BITCOIN, colorer.params());
+        let outputs = colorer.color_transaction(&issuance).unwrap();
+        let asset_id = outputs[0].asset_id.clone().unwrap();
+        assert_eq!(
+            AssetId::with_params(&script(ISSUER_SCRIPT), &BITCOIN),
+            asset_id
+        );
+        // the same string as on Tapyrus, but a different asset
+        assert_eq!(ISSUER_ASSET_ID, asset_id.to_string());
+        assert_ne!(AssetId::from_str(ISSUER_ASSET_ID).unwrap(), asset_id);
+    }
+}
@@ -0,0 +1,114 @@
+use tapyrus::util::address::Payload;
+use tapyrus::{Script, TxOut, VarInt};
+
+use crate::openassets::address::Address;
+
+/// The default dust relay fee rate of Tapyrus Core, in tapyrus per 1000 bytes.
+pub const DEFAULT_DUST_RELAY_FEE: u64 = 3000;
+
+/// The size of an input spending a non-witness output (outpoint, script and
+/// sequence), assumed when pricing the spend of an output.
+pub const SPEND_INPUT_SIZE: u64 = 32 + 4 + 1 + 107 + 4;
+
+/// Computes the value below which outputs are dust, i.e. cost more to spend
+/// than they are worth at the dust relay fee rate.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct DustPolicy {
+    fee_rate: u64,
+}
+
+impl Default for DustPolicy {
+    fn default() -> Self {
+        DustPolicy::new(DEFAULT_DUST_RELAY_FEE)
+    }
+}
+
+impl DustPolicy {
+    /// `fee_rate` is in tapyrus per 1000 bytes.
+    pub fn new(fee_rate: u64) -> Self {
+        DustPolicy { fee_rate }
+    }
+
+    pub fn fee_rate(&self) -> u64 {
+        self.fee_rate
+    }
+
+    /// Returns the smallest value of a non-dust output locked by `script`.
+    ///
+    /// Unspendable (OP_RETURN) outputs are never dust.
+    pub fn threshold(&self, script: &Script) -> u64 {
+        if script.is_op_return() {
+            return 0;
+        }
+        let len = script.len() as u64;
+        let output_size = 8 + VarInt(len).len() as u64 + len;
+        (output_size + SPEND_INPUT_SIZE) * self.fee_rate / 1000
+    }
+
+    /// Returns the dust threshold of outputs paying to `payload`, including the
+    /// colored P2PKH and P2SH payloads.
+    pub fn threshold_for_payload(&self, payload: &Payload) -> u64 {
+        self.threshold(&payload.script_pubkey())
+    }
+
+    /// Returns the dust threshold of outputs paying to the Open Assets `address`.
+    pub fn threshold_for_address(&self, address: &Address) -> u64 {
+        self.threshold_for_payload(&address.payload)
+    }
+
+    pub fn is_dust(&self, output: &TxOut) -> bool {
+        output.value < self.threshold(&output.script_pubkey)
+    }
+
+    /// Returns `value` raised to the dust threshold of `script` if needed.
+    pub fn min_value(&self, script: &Script, value: u64) -> u64 {
+        std::cmp::max(value, self.threshold(script))
+    }
+}
+
+impl Address {
+    /// Returns the dust threshold of outputs paying to this address at the
+    /// dust relay fee rate of its network.
+    pub fn dust_value(&self) -> u64 {
+        self.params().dust_policy().threshold_for_address(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::address::OAAddressConverter;
+    use crate::openassets::dust::DustPolicy;
+    use crate::openassets::test_helpers::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_threshold() {
+        let policy = DustPolicy::default();
+        let p2pkh = tapyrus::Address::from_str("1F2AQr6oqNtcJQ6p9SiCLQTrHuM9en44H8").unwrap();
+        let p2sh = tapyrus::Address::from_str("3EktnHQD7RiAE6uzMj2ZifT9YgRrkSgzQX").unwrap();
+        let cp2sh = tapyrus::Address::from_str(
+            "4Zxhb33iSoydtcKzWc7hpoRjtJh2W9otwDeqP5PbZHGoq7x6JndvyoFpAn5vzLCtLA5hyYTuJsH4gNP",
+        )
+        .unwrap();
+        assert_eq!(546, policy.threshold_for_payload(&p2pkh.payload));
+        assert_eq!(540, policy.threshold_for_payload(&p2sh.payload));
+        assert_eq!(645, policy.threshold_for_payload(&cp2sh.payload));
+        assert_eq!(546, p2pkh.to_oa_address().unwrap().dust_value());
+        assert_eq!(645, cp2sh.to_oa_address().unwrap().dust_value());
+
+        // twice the fee rate
+        let policy = DustPolicy::new(6000);
+        assert_eq!(1092, policy.threshold(&p2pkh.script_pubkey()));
+        assert_eq!(0, policy.threshold(&marker(vec![1], b"").script_pubkey));
+    }
+
+    #[test]
+    fn test_is_dust() {
+        let policy = DustPolicy::default();
+        assert!(policy.is_dust(&txout(545, p2pkh(1))));
+        assert!(!policy.is_dust(&txout(546, p2pkh(1))));
+        assert!(!policy.is_dust(&marker(vec![1], b"")));
+        assert_eq!(546, policy.min_value(&p2pkh(1), 100));
+        assert_eq!(600, policy.min_value(&p2pkh(1), 600));
+    }
+}
@@ -0,0 +1,429 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tapyrus::consensus::{deserialize, encode};
+use tapyrus::hashes::hex::FromHex;
+use tapyrus::hashes::{sha256, Hash};
+use tapyrus::{OutPoint, Script, Transaction, Txid};
+
+use crate::openassets::address::Address;
+use crate::openassets::coloring::{self, ColoredOutput, Colorer, TxSource};
+
+/// The default time `connect` and `connect_tls` wait for the connection and for
+/// each read or write.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Encode(encode::Error),
+    /// The server answered with an error object.
+    Server(Value),
+    /// The server answer does not follow the Electrum protocol.
+    Protocol(String),
+    Coloring(coloring::Error),
+    #[cfg(feature = "tls")]
+    Tls(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Json(ref e) => write!(f, "JSON error: {}", e),
+            Error::Encode(ref e) => write!(f, "decode error: {}", e),
+            Error::Server(ref e) => write!(f, "server error: {}", e),
+            Error::Protocol(ref s) => write!(f, "protocol error: {}", s),
+            Error::Coloring(ref e) => write!(f, "coloring error: {}", e),
+            #[cfg(feature = "tls")]
+            Error::Tls(ref s) => write!(f, "TLS error: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+impl From<coloring::Error> for Error {
+    fn from(e: coloring::Error) -> Self {
+        Error::Coloring(e)
+    }
+}
+
+/// An entry of `blockchain.scripthash.listunspent`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Unspent {
+    pub outpoint: OutPoint,
+    /// Confirmation height, 0 (or negative) for mempool transactions.
+    pub height: i64,
+    pub value: u64,
+}
+
+/// An unspent output of an Open Assets address with its coloring.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ColoredUnspent {
+    pub outpoint: OutPoint,
+    pub height: i64,
+    pub output: ColoredOutput,
+}
+
+/// A notification pushed by the server, e.g. a script hash status change.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Notification {
+    pub method: String,
+    pub params: Value,
+}
+
+/// Computes the Electrum script hash, the reversed SHA256 of the script in hex.
+pub fn script_hash(script: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).into_inner();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+/// A JSON-RPC client for the Electrum protocol over a line based stream.
+pub struct ElectrumClient<S: Read + Write> {
+    stream: RefCell<BufReader<S>>,
+    next_id: Cell<u64>,
+    notifications: RefCell<VecDeque<Notification>>,
+}
+
+/// Connects to the first address of `addr` that accepts within `timeout`, and
+/// gives up reads and writes which wait longer than `timeout`.
+fn connect_tcp<A: ToSocketAddrs>(addr: A, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+impl ElectrumClient<TcpStream> {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, Error> {
+        ElectrumClient::connect_with_timeout(addr, DEFAULT_TIMEOUT)
+    }
+
+    /// Connects with `timeout` instead of `DEFAULT_TIMEOUT`. A zero timeout,
+    /// which sockets reject, is raised to one millisecond.
+    pub fn connect_with_timeout<A: ToSocketAddrs>(
+        addr: A,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let timeout = std::cmp::max(timeout, Duration::from_millis(1));
+        Ok(ElectrumClient::new(connect_tcp(addr, timeout)?))
+    }
+}
+
+#[cfg(feature = "tls")]
+impl ElectrumClient<native_tls::TlsStream<TcpStream>> {
+    pub fn connect_tls(host: &str, port: u16) -> Result<Self, Error> {
+        let connector = native_tls::TlsConnector::new().map_err(|e| Error::Tls(e.to_string()))?;
+        let tcp = connect_tcp((host, port), DEFAULT_TIMEOUT)?;
+        let stream = connector
+            .connect(host, tcp)
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        Ok(ElectrumClient::new(stream))
+    }
+}
+
+impl<S: Read + Write> ElectrumClient<S> {
+    pub fn new(stream: S) -> Self {
+        ElectrumClient {
+            stream: RefCell::new(BufReader::new(stream)),
+            next_id: Cell::new(0),
+            notifications: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Sends a request and waits for its response. Notifications received in
+    /// the meantime are queued and can be read with `pop_notification`.
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+
+        let mut stream = self.stream.borrow_mut();
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        stream.get_mut().write_all(&line)?;
+        stream.get_mut().flush()?;
+
+        loop {
+            let mut buf = String::new();
+            if stream.read_line(&mut buf)? == 0 {
+                return Err(Error::Protocol("connection closed".to_string()));
+            }
+            let mut response: Value = serde_json::from_str(&buf)?;
+            if let Some(method) = response
+                .get("method")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+            {
+                self.notifications.borrow_mut().push_back(Notification {
+                    method,
+                    params: response["params"].take(),
+                });
+                continue;
+            }
+            if response["id"].as_u64() != Some(id) {
+                return Err(Error::Protocol(format!(
+                    "unexpected response id: {}",
+                    response["id"]
+                )));
+            }
+            if !response["error"].is_null() {
+                return Err(Error::Server(response["error"].take()));
+            }
+            return Ok(response["result"].take());
+        }
+    }
+
+    pub fn pop_notification(&self) -> Option<Notification> {
+        self.notifications.borrow_mut().pop_front()
+    }
+
+    /// `blockchain.transaction.get`
+    pub fn transaction_get(&self, txid: &Txid) -> Result<Transaction, Error> {
+        let result = self.call("blockchain.transaction.get", json!([txid.to_string()]))?;
+        let raw = result
+            .as_str()
+            .ok_or_else(|| Error::Protocol("transaction must be a hex string".to_string()))?;
+        let bytes = hex::decode(raw).map_err(|e| Error::Protocol(e.to_string()))?;
+        Ok(deserialize(&bytes)?)
+    }
+
+    /// `blockchain.scripthash.listunspent`
+    pub fn scripthash_listunspent(&self, script: &Script) -> Result<Vec<Unspent>, Error> {
+        let result = self.call(
+            "blockchain.scripthash.listunspent",
+            json!([script_hash(script)]),
+        )?;
+        let entries = result
+            .as_array()
+            .ok_or_else(|| Error::Protocol("listunspent must return an array".to_string()))?;
+        entries.iter().map(parse_unspent).collect()
+    }
+
+    /// `blockchain.scripthash.subscribe`, returns the current status of the script hash.
+    pub fn scripthash_subscribe(&self, script: &Script) -> Result<Option<String>, Error> {
+        let result = self.call(
+            "blockchain.scripthash.subscribe",
+            json!([script_hash(script)]),
+        )?;
+        Ok(result.as_str().map(|s| s.to_string()))
+    }
+
+    /// Lists the unspent outputs of `address` and colors them by fetching
+    /// their ancestry from the server.
+    pub fn list_colored_unspent(&self, address: &Address) -> Result<Vec<ColoredUnspent>, Error> {
+        let script = address.to_btc_addr()?.script_pubkey();
+        let unspents = self.scripthash_listunspent(&script)?;
+        let mut colorer = Colorer::with_params(self, address.params());
+        unspents
+            .into_iter()
+            .map(|u| {
+                Ok(ColoredUnspent {
+                    output: colorer.get_output(&u.outpoint)?,
+                    outpoint: u.outpoint,
+                    height: u.height,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<S: Read + Write> TxSource for ElectrumClient<S> {
+    fn get_transaction(&self, txid: &Txid) -> Result<Transaction, coloring::Error> {
+        self.transaction_get(txid)
+            .map_err(|e| coloring::Error::Source(Box::new(e)))
+    }
+}
+
+fn parse_unspent(entry: &Value) -> Result<Unspent, Error> {
+    let invalid = || Error::Protocol(format!("invalid listunspent entry: {}", entry));
+    let txid = entry["tx_hash"]
+        .as_str()
+        .and_then(|s| Txid::from_hex(s).ok())
+        .ok_or_else(invalid)?;
+    let vout = entry["tx_pos"].as_u64().ok_or_else(invalid)?;
+    Ok(Unspent {
+        outpoint: OutPoint::new(txid, vout as u32),
+        height: entry["height"].as_i64().ok_or_else(invalid)?,
+        value: entry["value"].as_u64().ok_or_else(invalid)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::address::OAAddressConverter;
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::coloring::OutputType;
+    use crate::openassets::electrum::{script_hash, ElectrumClient, Error, Notification};
+    use crate::openassets::test_helpers::*;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::Duration;
+    use tapyrus::consensus::serialize;
+    use tapyrus::OutPoint;
+
+    /// Starts a local Electrum stand-in answering each request with `handler`.
+    /// A `None` answer makes the stand-in send a notification before the result.
+    fn serve<F>(handler: F) -> std::net::SocketAddr
+    where
+        F: Fn(&str, &Value) -> (Option<Value>, Value) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let request: Value = serde_json::from_str(&line.unwrap()).unwrap();
+                let method = request["method"].as_str().unwrap();
+                let (notification, result) = handler(method, &request["params"]);
+                if let Some(n) = notification {
+                    writeln!(writer, "{}", n).unwrap();
+                }
+                let response = match result {
+                    Value::Object(ref o) if o.contains_key("code") => {
+                        json!({"jsonrpc": "2.0", "id": request["id"], "error": result})
+                    }
+                    _ => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                };
+                writeln!(writer, "{}", response).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_script_hash() {
+        // https://electrumx.readthedocs.io/en/latest/protocol-basics.html#script-hashes
+        let script = script("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac");
+        assert_eq!(
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161",
+            script_hash(&script)
+        );
+    }
+
+    #[test]
+    fn test_list_colored_unspent() {
+        let receiver = tapyrus::Address::from_str("1F2AQr6oqNtcJQ6p9SiCLQTrHuM9en44H8").unwrap();
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![txout(600, receiver.script_pubkey()), marker(vec![100], b"")],
+        );
+        let mut txs = HashMap::new();
+        for t in [&funding, &issuance] {
+            txs.insert(t.txid().to_string(), hex::encode(serialize(t)));
+        }
+        let expected_hash = script_hash(&receiver.script_pubkey());
+        let issuance_txid = issuance.txid().to_string();
+
+        let addr = serve(move |method, params| match method {
+            "blockchain.scripthash.listunspent" => {
+                assert_eq!(expected_hash, params[0].as_str().unwrap());
+                let result =
+                    json!([{"tx_hash": issuance_txid, "tx_pos": 0, "height": 100, "value": 600}]);
+                (None, result)
+            }
+            "blockchain.transaction.get" => {
+                let txid = params[0].as_str().unwrap();
+                match txs.get(txid) {
+                    Some(raw) => (None, json!(raw)),
+                    None => (None, json!({"code": 2, "message": "missing transaction"})),
+                }
+            }
+            "blockchain.scripthash.subscribe" => {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "blockchain.headers.subscribe",
+                    "params": [{"height": 101}]
+                });
+                (Some(notification), json!("status-hash"))
+            }
+            _ => (None, json!({"code": -32601, "message": "unknown method"})),
+        });
+
+        let client = ElectrumClient::connect(addr).unwrap();
+        let oa_address = receiver.to_oa_address().unwrap();
+        let unspents = client.list_colored_unspent(&oa_address).unwrap();
+        assert_eq!(1, unspents.len());
+        assert_eq!(OutPoint::new(issuance.txid(), 0), unspents[0].outpoint);
+        assert_eq!(100, unspents[0].height);
+        assert_eq!(
+            AssetId::from_str(ISSUER_ASSET_ID).ok(),
+            unspents[0].output.asset_id
+        );
+        assert_eq!(100, unspents[0].output.asset_quantity);
+        assert_eq!(OutputType::Issuance, unspents[0].output.output_type);
+
+        let status = client
+            .scripthash_subscribe(&receiver.script_pubkey())
+            .unwrap();
+        assert_eq!(Some("status-hash".to_string()), status);
+        assert_eq!(
+            Some(Notification {
+                method: "blockchain.headers.subscribe".to_string(),
+                params: json!([{"height": 101}]),
+            }),
+            client.pop_notification()
+        );
+        assert_eq!(None, client.pop_notification());
+
+        assert_eq!(funding, client.transaction_get(&funding.txid()).unwrap());
+        let unknown = funding_tx(2, vec![]);
+        match client.transaction_get(&unknown.txid()) {
+            Err(Error::Server(e)) => assert_eq!(2, e["code"]),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        // the connection is queued by the listener but never answered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client =
+            ElectrumClient::connect_with_timeout(addr, Duration::from_millis(100)).unwrap();
+        match client.call("server.version", json!([])) {
+            Err(Error::Io(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
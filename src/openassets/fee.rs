@@ -0,0 +1,317 @@
+use std::fmt;
+
+use tapyrus::consensus::encode::serialize;
+use tapyrus::{Script, TxOut, VarInt};
+
+use crate::openassets::marker_output::Payload;
+
+/// The number of times `converge_change` re-estimates the fee before giving up.
+pub const MAX_CHANGE_ITERATIONS: usize = 10;
+
+/// The largest low-S DER signature with its sighash type.
+const SIGNATURE_SIZE: usize = 72;
+
+/// The largest scriptSig of a P2PKH input: a signature and a compressed public
+/// key, each with its push opcode.
+const P2PKH_SCRIPT_SIG_SIZE: usize = 1 + SIGNATURE_SIZE + 1 + 33;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The inputs do not cover the outputs and the fee.
+    InsufficientFunds { missing: u64 },
+    /// The change did not settle within `MAX_CHANGE_ITERATIONS` estimations.
+    NotConverged,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InsufficientFunds { missing } => {
+                write!(f, "insufficient funds: {} tapyrus missing", missing)
+            }
+            Error::NotConverged => write!(f, "change amount did not converge"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The kind of output an input spends, which determines the size of its scriptSig.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum InputType {
+    P2pkh,
+    /// A colored P2PKH output, spent with the same scriptSig as P2PKH.
+    ColoredP2pkh,
+    /// A P2SH output locked by a bare `required`-of-`total` multisig script.
+    P2shMultisig {
+        required: usize,
+        total: usize,
+    },
+    /// An input whose scriptSig size is known.
+    ScriptSig(usize),
+}
+
+impl InputType {
+    /// The input type of an output locked by `script`, or `None` when the
+    /// script does not tell the size of its scriptSig, as for P2SH.
+    pub fn from_script(script: &Script) -> Option<InputType> {
+        if script.is_p2pkh() {
+            Some(InputType::P2pkh)
+        } else if script.is_cp2pkh() {
+            Some(InputType::ColoredP2pkh)
+        } else {
+            None
+        }
+    }
+
+    pub fn script_sig_size(&self) -> usize {
+        match *self {
+            InputType::P2pkh | InputType::ColoredP2pkh => P2PKH_SCRIPT_SIG_SIZE,
+            InputType::P2shMultisig { required, total } => {
+                let redeem_script = 1 + total * (1 + 33) + 1 + 1;
+                1 + required * (1 + SIGNATURE_SIZE) + push_size(redeem_script)
+            }
+            InputType::ScriptSig(size) => size,
+        }
+    }
+
+    /// The serialized size of the input: outpoint, scriptSig and sequence.
+    pub fn size(&self) -> usize {
+        let script_sig = self.script_sig_size();
+        32 + 4 + VarInt(script_sig as u64).len() + script_sig + 4
+    }
+}
+
+/// The size of `len` bytes pushed on the stack, including the push opcode.
+fn push_size(len: usize) -> usize {
+    let opcode = match len {
+        0..=75 => 1,
+        76..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    };
+    opcode + len
+}
+
+/// The serialized size of an output locked by a script of `script_len` bytes.
+pub fn output_size(script_len: usize) -> usize {
+    8 + VarInt(script_len as u64).len() + script_len
+}
+
+/// The size of the OP_RETURN script of a marker output carrying `payload`.
+///
+/// The payload grows with the LEB128 encoding of each quantity and with the metadata.
+pub fn marker_script_size(payload: &Payload) -> usize {
+    1 + push_size(serialize(payload).len())
+}
+
+/// Estimates the serialized size of a transaction spending `inputs` into
+/// `outputs`, plus a marker output carrying `payload` if given.
+pub fn estimate_size(inputs: &[InputType], outputs: &[TxOut], payload: Option<&Payload>) -> usize {
+    let output_count = outputs.len() + payload.map_or(0, |_| 1);
+    let mut size = 4 + VarInt(inputs.len() as u64).len() + VarInt(output_count as u64).len() + 4;
+    size += inputs.iter().map(InputType::size).sum::<usize>();
+    size += outputs
+        .iter()
+        .map(|o| output_size(o.script_pubkey.len()))
+        .sum::<usize>();
+    if let Some(payload) = payload {
+        size += output_size(marker_script_size(payload));
+    }
+    size
+}
+
+/// Estimates the weight of the transaction. Tapyrus transactions carry no
+/// witness, so every byte weighs 4 units.
+pub fn estimate_weight(
+    inputs: &[InputType],
+    outputs: &[TxOut],
+    payload: Option<&Payload>,
+) -> usize {
+    estimate_size(inputs, outputs, payload) * 4
+}
+
+/// Returns the fee of a transaction of `size` bytes at `fee_rate` tapyrus per
+/// 1000 bytes, rounded up.
+pub fn fee(size: usize, fee_rate: u64) -> u64 {
+    (size as u64 * fee_rate).div_ceil(1000)
+}
+
+pub fn estimate_fee(
+    inputs: &[InputType],
+    outputs: &[TxOut],
+    payload: Option<&Payload>,
+    fee_rate: u64,
+) -> u64 {
+    fee(estimate_size(inputs, outputs, payload), fee_rate)
+}
+
+/// The change output settled by `converge_change`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Change {
+    /// The value of the change output, or `None` if the transaction has none.
+    pub value: Option<u64>,
+    pub fee: u64,
+}
+
+/// Finds a change value which leaves exactly the fee of the resulting transaction.
+///
+/// `available` is the value of the inputs minus the value of the other
+/// outputs. `size_of` estimates the transaction size with the given change
+/// value (`None` for no change output). Change below `dust_threshold`, or
+/// worth less than the fee of its own output, is left to the fee instead.
+pub fn converge_change<F>(
+    available: u64,
+    fee_rate: u64,
+    dust_threshold: u64,
+    mut size_of: F,
+) -> Result<Change, Error>
+where
+    F: FnMut(Option<u64>) -> usize,
+{
+    let mut change = None;
+    for _ in 0..MAX_CHANGE_ITERATIONS {
+        let required = fee(size_of(change), fee_rate);
+        let left = match available.checked_sub(required) {
+            Some(left) => left,
+            // the change output costs more than it holds, but the
+            // transaction without it is paid for
+            None if change.is_some() => {
+                return Ok(Change {
+                    value: None,
+                    fee: available,
+                });
+            }
+            None => {
+                return Err(Error::InsufficientFunds {
+                    missing: required - available,
+                });
+            }
+        };
+        let next = if left >= dust_threshold {
+            Some(left)
+        } else {
+            None
+        };
+        // adding the change output made it dust: pay the rest as fee
+        if next == change || (change.is_some() && next.is_none()) {
+            return Ok(Change {
+                value: next,
+                fee: available - next.unwrap_or(0),
+            });
+        }
+        change = next;
+    }
+    Err(Error::NotConverged)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::fee::{
+        converge_change, estimate_size, fee, marker_script_size, Change, Error, InputType,
+    };
+    use crate::openassets::marker_output::{Metadata, Payload};
+    use crate::openassets::test_helpers::*;
+    use tapyrus::consensus::encode::serialize;
+    use tapyrus::OutPoint;
+
+    #[test]
+    fn test_estimate_size() {
+        let dummy = funding_tx(0, vec![]);
+        let outputs = vec![txout(600, p2pkh(1)), txout(600, p2pkh(2))];
+        for quantities in [vec![1], vec![300, 1 << 40], vec![u64::MAX; 20]] {
+            for metadata in [&b""[..], &[b'u'; 70][..], &[b'u'; 300][..]] {
+                let payload = Payload {
+                    quantities: quantities.clone(),
+                    metadata: Metadata::new(metadata.to_vec()),
+                };
+                let marker = marker(quantities.clone(), metadata);
+                assert_eq!(marker.script_pubkey.len(), marker_script_size(&payload));
+
+                let mut all = outputs.clone();
+                all.insert(1, marker);
+                let tx = tx(
+                    &[
+                        OutPoint::new(dummy.txid(), 0),
+                        OutPoint::new(dummy.txid(), 1),
+                    ],
+                    all,
+                );
+                let inputs = [InputType::ScriptSig(0), InputType::ScriptSig(0)];
+                assert_eq!(
+                    serialize(&tx).len(),
+                    estimate_size(&inputs, &outputs, Some(&payload))
+                );
+            }
+        }
+
+        assert_eq!(148, InputType::P2pkh.size());
+        assert_eq!(148, InputType::ColoredP2pkh.size());
+        // OP_0, 2 signatures and a push of the 71 byte 2-of-2 redeem script
+        let multisig = InputType::P2shMultisig {
+            required: 2,
+            total: 2,
+        };
+        assert_eq!(1 + 2 * 73 + 1 + 71, multisig.script_sig_size());
+        let outputs = vec![txout(600, p2pkh(1))];
+        assert_eq!(
+            10 + 148 + 34,
+            estimate_size(&[InputType::P2pkh], &outputs, None)
+        );
+    }
+
+    #[test]
+    fn test_fee() {
+        assert_eq!(0, fee(250, 0));
+        assert_eq!(250, fee(250, 1000));
+        assert_eq!(1, fee(250, 1));
+        assert_eq!(2500, fee(250, 10_000));
+    }
+
+    #[test]
+    fn test_converge_change() {
+        // 200 bytes without change, 234 bytes with it
+        let size_of = |change: Option<u64>| if change.is_some() { 234 } else { 200 };
+        assert_eq!(
+            Ok(Change {
+                value: Some(7660),
+                fee: 2340
+            }),
+            converge_change(10_000, 10_000, 546, size_of)
+        );
+
+        // the change would be dust once its own output is paid for
+        assert_eq!(
+            Ok(Change {
+                value: None,
+                fee: 2800
+            }),
+            converge_change(2800, 10_000, 546, size_of)
+        );
+        // the change is dust even without its output
+        assert_eq!(
+            Ok(Change {
+                value: None,
+                fee: 2100
+            }),
+            converge_change(2100, 10_000, 546, size_of)
+        );
+        assert_eq!(
+            Err(Error::InsufficientFunds { missing: 100 }),
+            converge_change(1900, 10_000, 546, size_of)
+        );
+
+        // at 20_000 per kB the 600 left do not pay for their own output
+        assert_eq!(
+            Ok(Change {
+                value: None,
+                fee: 4600
+            }),
+            converge_change(4600, 20_000, 546, size_of)
+        );
+        assert_eq!(
+            Err(Error::InsufficientFunds { missing: 100 }),
+            converge_change(3900, 20_000, 546, size_of)
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use tapyrus::consensus::encode;
+use tapyrus::network::constants::Network;
+use tapyrus::secp256k1::{All, Secp256k1};
+use tapyrus::util::bip32::{self, ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey};
+use tapyrus::{OutPoint, PrivateKey, PublicKey, Script};
+
+use crate::openassets::address::{Address, OAAddressConverter};
+use crate::openassets::coloring::ColoredOutput;
+
+/// The number of consecutive unused addresses after which discovery stops.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// The BIP44 purpose field.
+pub const BIP44_PURPOSE: u32 = 44;
+
+#[derive(Debug)]
+pub enum Error {
+    Bip32(bip32::Error),
+    Encode(encode::Error),
+    /// Handing out another address would leave more than the gap limit of
+    /// unused addresses on the chain.
+    GapLimitExceeded(Chain),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Bip32(ref e) => write!(f, "BIP32 error: {}", e),
+            Error::Encode(ref e) => write!(f, "encode error: {}", e),
+            Error::GapLimitExceeded(chain) => {
+                write!(f, "gap limit exceeded on the {} chain", chain)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<bip32::Error> for Error {
+    fn from(e: bip32::Error) -> Self {
+        Error::Bip32(e)
+    }
+}
+
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+/// The external (receive) and internal (change) chains of a BIP44 account.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Chain {
+    Receive,
+    Change,
+}
+
+impl Chain {
+    pub fn index(&self) -> u32 {
+        match *self {
+            Chain::Receive => 0,
+            Chain::Change => 1,
+        }
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Chain::Receive => "receive",
+            Chain::Change => "change",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The position of a key in the account.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct KeyIndex {
+    pub chain: Chain,
+    pub index: u32,
+}
+
+/// A colored output paying to a key of the wallet.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct WalletOutput {
+    pub outpoint: OutPoint,
+    pub output: ColoredOutput,
+    pub key: KeyIndex,
+    /// The full derivation path of the key from the master key.
+    pub path: DerivationPath,
+}
+
+/// Derives the P2PKH addresses of a BIP44 account and recognizes the outputs
+/// paying to them.
+pub struct HdWallet {
+    secp: Secp256k1<All>,
+    network: Network,
+    account: ExtendedPrivKey,
+    account_path: DerivationPath,
+    gap_limit: u32,
+    /// The next index handed out on each chain.
+    next: [u32; 2],
+    /// The highest index seen in use on each chain.
+    last_used: [Option<u32>; 2],
+    /// The scripts of the derived keys up to the gap limit.
+    scripts: HashMap<Script, KeyIndex>,
+    derived: [u32; 2],
+}
+
+impl HdWallet {
+    /// Uses the account `m/44'/coin_type'/account'` of the master key derived from `seed`.
+    pub fn from_seed(
+        network: Network,
+        seed: &[u8],
+        coin_type: u32,
+        account: u32,
+    ) -> Result<Self, Error> {
+        let master = ExtendedPrivKey::new_master(network, seed)?;
+        let path = DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(BIP44_PURPOSE)?,
+            ChildNumber::from_hardened_idx(coin_type)?,
+            ChildNumber::from_hardened_idx(account)?,
+        ]);
+        HdWallet::new(network, &master, path)
+    }
+
+    /// Uses the account at `account_path` below `master`.
+    pub fn new(
+        network: Network,
+        master: &ExtendedPrivKey,
+        account_path: DerivationPath,
+    ) -> Result<Self, Error> {
+        let secp = Secp256k1::new();
+        let account = master.derive_priv(&secp, &account_path)?;
+        let mut wallet = HdWallet {
+            secp,
+            network,
+            account,
+            account_path,
+            gap_limit: DEFAULT_GAP_LIMIT,
+            next: [0; 2],
+            last_used: [None; 2],
+            scripts: HashMap::new(),
+            derived: [0; 2],
+        };
+        wallet.extend_lookahead()?;
+        Ok(wallet)
+    }
+
+    pub fn with_gap_limit(mut self, gap_limit: u32) -> Result<Self, Error> {
+        self.gap_limit = gap_limit;
+        // forget the keys past a smaller lookahead
+        self.derived = [0; 2];
+        self.scripts.clear();
+        self.extend_lookahead()?;
+        Ok(self)
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// The extended public key of the account, for watch-only use.
+    pub fn account_xpub(&self) -> ExtendedPubKey {
+        ExtendedPubKey::from_private(&self.secp, &self.account)
+    }
+
+    /// Returns the full derivation path of `key` from the master key.
+    pub fn path(&self, key: KeyIndex) -> DerivationPath {
+        let mut path: Vec<ChildNumber> = self.account_path.as_ref().to_vec();
+        path.push(ChildNumber::Normal {
+            index: key.chain.index(),
+        });
+        path.push(ChildNumber::Normal { index: key.index });
+        DerivationPath::from(path)
+    }
+
+    pub fn private_key(&self, key: KeyIndex) -> Result<PrivateKey, Error> {
+        let path = [
+            ChildNumber::from_normal_idx(key.chain.index())?,
+            ChildNumber::from_normal_idx(key.index)?,
+        ];
+        Ok(self.account.derive_priv(&self.secp, &path)?.private_key)
+    }
+
+    pub fn public_key(&self, key: KeyIndex) -> Result<PublicKey, Error> {
+        Ok(self.private_key(key)?.public_key(&self.secp))
+    }
+
+    pub fn address(&self, key: KeyIndex) -> Result<tapyrus::Address, Error> {
+        Ok(tapyrus::Address::p2pkh(
+            &self.public_key(key)?,
+            self.network,
+        ))
+    }
+
+    pub fn oa_address(&self, key: KeyIndex) -> Result<Address, Error> {
+        Ok(self.address(key)?.to_oa_address()?)
+    }
+
+    /// Hands out the next address of `chain`, refusing to leave more than the
+    /// gap limit of unused addresses.
+    pub fn next_address(&mut self, chain: Chain) -> Result<(KeyIndex, tapyrus::Address), Error> {
+        let c = chain.index() as usize;
+        let limit = self.last_used[c].map_or(0, |i| i + 1) + self.gap_limit;
+        if self.next[c] >= limit {
+            return Err(Error::GapLimitExceeded(chain));
+        }
+        let key = KeyIndex {
+            chain,
+            index: self.next[c],
+        };
+        self.next[c] += 1;
+        Ok((key, self.address(key)?))
+    }
+
+    pub fn next_receive_address(&mut self) -> Result<Address, Error> {
+        let (_, address) = self.next_address(Chain::Receive)?;
+        Ok(address.to_oa_address()?)
+    }
+
+    pub fn next_change_address(&mut self) -> Result<Address, Error> {
+        let (_, address) = self.next_address(Chain::Change)?;
+        Ok(address.to_oa_address()?)
+    }
+
+    /// Returns the key paying to `script`, among the keys up to the gap limit.
+    pub fn find_key(&self, script: &Script) -> Option<KeyIndex> {
+        self.scripts.get(script).cloned()
+    }
+
+    /// Records that `key` received funds, extending the lookahead past it.
+    ///
+    /// Fails without changing the wallet when the key or its lookahead is
+    /// not a normal child index.
+    pub fn mark_used(&mut self, key: KeyIndex) -> Result<(), Error> {
+        ChildNumber::from_normal_idx(key.index)?;
+        ChildNumber::from_normal_idx(key.index.saturating_add(self.gap_limit))?;
+        let c = key.chain.index() as usize;
+        if self.last_used[c].is_none_or(|i| i < key.index) {
+            self.last_used[c] = Some(key.index);
+        }
+        self.next[c] = std::cmp::max(self.next[c], key.index + 1);
+        self.extend_lookahead()
+    }
+
+    /// Picks the colored outputs paying to the wallet and maps them to the
+    /// derivation path of their key, marking those keys as used.
+    pub fn discover<'a, I>(&mut self, outputs: I) -> Result<Vec<WalletOutput>, Error>
+    where
+        I: IntoIterator<Item = (&'a OutPoint, &'a ColoredOutput)>,
+    {
+        let mut found = vec![];
+        for (outpoint, output) in outputs {
+            if let Some(key) = self.find_key(&output.script_pubkey) {
+                self.mark_used(key)?;
+                found.push(WalletOutput {
+                    outpoint: *outpoint,
+                    output: output.clone(),
+                    key,
+                    path: self.path(key),
+                });
+            }
+        }
+        Ok(found)
+    }
+
+    /// Derives the scripts of every key up to the gap limit past the last used one.
+    fn extend_lookahead(&mut self) -> Result<(), Error> {
+        for chain in [Chain::Receive, Chain::Change] {
+            let c = chain.index() as usize;
+            let limit = self.last_used[c].map_or(0, |i| i + 1) + self.gap_limit;
+            while self.derived[c] < limit {
+                let key = KeyIndex {
+                    chain,
+                    index: self.derived[c],
+                };
+                let script = self.address(key)?.script_pubkey();
+                self.scripts.insert(script, key);
+                self.derived[c] += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::coloring::{ColoredOutput, OutputType};
+    use crate::openassets::hd_wallet::{Chain, Error, HdWallet, KeyIndex};
+    use crate::openassets::test_helpers::*;
+    use std::str::FromStr;
+    use tapyrus::network::constants::Network;
+    use tapyrus::secp256k1::Secp256k1;
+    use tapyrus::util::bip32::DerivationPath;
+    use tapyrus::OutPoint;
+
+    const SEED: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+
+    fn colored(script: tapyrus::Script, quantity: u64) -> ColoredOutput {
+        ColoredOutput {
+            value: 600,
+            script_pubkey: script,
+            asset_id: Some(AssetId::new(&p2pkh(7), Network::Prod)),
+            asset_quantity: quantity,
+            output_type: OutputType::Transfer,
+        }
+    }
+
+    #[test]
+    fn test_derivation() {
+        let wallet = HdWallet::from_seed(Network::Prod, &SEED, 1165, 0).unwrap();
+        let key = KeyIndex {
+            chain: Chain::Change,
+            index: 5,
+        };
+        let path = wallet.path(key);
+        assert_eq!(
+            DerivationPath::from_str("m/44'/1165'/0'/1/5").unwrap(),
+            path
+        );
+
+        // the account xpub derives the same public keys
+        let secp = Secp256k1::new();
+        let xpub = wallet.account_xpub();
+        let child = xpub
+            .derive_pub(&secp, &DerivationPath::from_str("m/1/5").unwrap())
+            .unwrap();
+        assert_eq!(child.public_key, wallet.public_key(key).unwrap());
+
+        let address = wallet.address(key).unwrap();
+        let oa_address = wallet.oa_address(key).unwrap();
+        assert_eq!(address, oa_address.to_btc_addr().unwrap());
+        assert!(oa_address.to_string().starts_with('a'));
+
+        // another account derives other keys
+        let other = HdWallet::from_seed(Network::Prod, &SEED, 1165, 1).unwrap();
+        assert_ne!(address, other.address(key).unwrap());
+    }
+
+    #[test]
+    fn test_gap_limit() {
+        let mut wallet = HdWallet::from_seed(Network::Dev, &SEED, 1, 0)
+            .unwrap()
+            .with_gap_limit(3)
+            .unwrap();
+        for _ in 0..3 {
+            wallet.next_receive_address().unwrap();
+        }
+        match wallet.next_receive_address() {
+            Err(Error::GapLimitExceeded(Chain::Receive)) => {}
+            r => panic!("unexpected result {:?}", r.map(|a| a.to_string())),
+        }
+        // the change chain is independent
+        wallet.next_change_address().unwrap();
+
+        wallet
+            .mark_used(KeyIndex {
+                chain: Chain::Receive,
+                index: 1,
+            })
+            .unwrap();
+        let (key, _) = wallet.next_address(Chain::Receive).unwrap();
+        assert_eq!(3, key.index);
+        wallet.next_address(Chain::Receive).unwrap();
+        assert!(wallet.next_address(Chain::Receive).is_err());
+
+        // hardened and overflowing indexes leave the wallet unchanged
+        for index in [1 << 31, u32::MAX, (1 << 31) - 2] {
+            match wallet.mark_used(KeyIndex {
+                chain: Chain::Receive,
+                index,
+            }) {
+                Err(Error::Bip32(_)) => {}
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+        assert!(wallet.next_address(Chain::Receive).is_err());
+        wallet.next_change_address().unwrap();
+    }
+
+    #[test]
+    fn test_discover() {
+        let mut wallet = HdWallet::from_seed(Network::Prod, &SEED, 1165, 0)
+            .unwrap()
+            .with_gap_limit(5)
+            .unwrap();
+        let receive = |index| KeyIndex {
+            chain: Chain::Receive,
+            index,
+        };
+        // the address at index 7 is beyond the lookahead until index 4 is used
+        let near = wallet.address(receive(4)).unwrap().script_pubkey();
+        let far = wallet.address(receive(7)).unwrap().script_pubkey();
+        assert_eq!(None, wallet.find_key(&far));
+
+        let funding = funding_tx(0, vec![]);
+        let outputs = [
+            (OutPoint::new(funding.txid(), 0), colored(near, 10)),
+            (OutPoint::new(funding.txid(), 1), colored(p2pkh(1), 20)),
+        ];
+        let found = wallet
+            .discover(outputs.iter().map(|(o, c)| (o, c)))
+            .unwrap();
+        assert_eq!(1, found.len());
+        assert_eq!(receive(4), found[0].key);
+        assert_eq!(
+            DerivationPath::from_str("m/44'/1165'/0'/0/4").unwrap(),
+            found[0].path
+        );
+        assert_eq!(Some(receive(7)), wallet.find_key(&far));
+
+        // the key found signs for the output
+        let private_key = wallet.private_key(found[0].key).unwrap();
+        assert_eq!(
+            wallet.public_key(receive(4)).unwrap(),
+            private_key.public_key(&Secp256k1::new())
+        );
+    }
+}
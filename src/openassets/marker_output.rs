@@ -1,16 +1,21 @@
 use serde::ser::SerializeStruct;
 use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io::{Read, Write};
+use std::sync::Arc;
 
 use tapyrus::blockdata::script::Instruction;
 use tapyrus::consensus::encode::Error;
-use tapyrus::consensus::{deserialize, Decodable, Encodable};
+use tapyrus::consensus::{Decodable, Encodable};
 use tapyrus::{TxOut, VarInt};
 
 pub const MARKER: u16 = 0x4f41;
 pub const VERSION: u16 = 0x0100;
 
+/// The first bytes of every marker payload: the `OA` tag followed by the version.
+pub const MARKER_PREFIX: [u8; 4] = [0x4f, 0x41, 0x01, 0x00];
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Payload {
     pub quantities: Vec<u64>,
@@ -58,27 +63,263 @@ impl Decodable for Payload {
         let mut quantities: Vec<u64> = Vec::with_capacity(count as usize);
 
         for _ in 0..count {
-            let mut value: u64 = 0;
-            let mut offset: u64 = 0;
-            loop {
-                let b: u8 = Decodable::consensus_decode(&mut d)?;
-                value |= ((b as u64) & 0x7f) << offset;
-                if (b as u64) & 0x80 == 0 {
-                    break;
-                }
-                offset += 7;
-            }
-            quantities.push(value);
+            quantities.push(read_leb128(&mut d)?);
         }
 
         let payload = Payload {
             quantities,
             metadata: Decodable::consensus_decode(d)?,
         };
-        return Ok(payload);
+        Ok(payload)
+    }
+}
+
+/// Reads an asset quantity encoded in unsigned LEB128.
+fn read_leb128<D: Read>(mut d: D) -> Result<u64, Error> {
+    let mut value: u64 = 0;
+    let mut offset: u64 = 0;
+    loop {
+        let b: u8 = Decodable::consensus_decode(&mut d)?;
+        // only the lowest bit of the 10th byte still fits in 64 bits
+        if offset == 63 && b > 1 {
+            return Err(Error::ParseFailed("LEB128 quantity overflow."));
+        }
+        value |= ((b as u64) & 0x7f) << offset;
+        if (b as u64) & 0x80 == 0 {
+            return Ok(value);
+        }
+        offset += 7;
     }
 }
 
+/// Reads the quantity count and the LEB128 asset quantities at the start of
+/// `data`, leaving it at the bytes that follow.
+///
+/// Extended payload versions keep this layout, so their decoders can use it.
+pub fn read_quantities(data: &mut &[u8]) -> Result<Vec<u64>, Error> {
+    let VarInt(count) = Decodable::consensus_decode(&mut *data)?;
+    let mut quantities = vec![];
+    for _ in 0..count {
+        quantities.push(read_leb128(&mut *data)?);
+    }
+    Ok(quantities)
+}
+
+/// A marker payload together with the version it was encoded with.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct VersionedPayload {
+    pub version: u16,
+    pub payload: Payload,
+    /// Data of extended versions with no place in `Payload`, e.g. per-output
+    /// asset flags. Empty for version 0x0100.
+    pub extension: Vec<u8>,
+}
+
+/// Decodes the payloads of one marker version.
+pub trait VersionDecoder: Send + Sync {
+    /// Decodes `body`, the payload data following the tag and the version,
+    /// into the payload and the extension data.
+    fn decode(&self, body: &[u8]) -> Result<(Payload, Vec<u8>), Error>;
+}
+
+/// The decoder of version 0x0100: the asset quantities followed by the metadata.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct V1Decoder;
+
+impl VersionDecoder for V1Decoder {
+    fn decode(&self, body: &[u8]) -> Result<(Payload, Vec<u8>), Error> {
+        let mut data = MARKER_PREFIX.to_vec();
+        data.extend_from_slice(body);
+        Ok((PayloadRef::parse(&data)?.to_payload(), vec![]))
+    }
+}
+
+/// The marker versions a reader accepts, by version number.
+///
+/// The default only accepts version 0x0100.
+#[derive(Clone)]
+pub struct MarkerVersions {
+    decoders: BTreeMap<u16, Arc<dyn VersionDecoder>>,
+}
+
+impl Default for MarkerVersions {
+    fn default() -> Self {
+        MarkerVersions::empty().with_version(VERSION, V1Decoder)
+    }
+}
+
+impl fmt::Debug for MarkerVersions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MarkerVersions")
+            .field("versions", &self.decoders.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl MarkerVersions {
+    pub fn new() -> Self {
+        MarkerVersions::default()
+    }
+
+    /// Accepts no version at all.
+    pub fn empty() -> Self {
+        MarkerVersions {
+            decoders: BTreeMap::new(),
+        }
+    }
+
+    /// Decodes `version` with `decoder`, replacing any previous decoder.
+    pub fn with_version<D: VersionDecoder + 'static>(mut self, version: u16, decoder: D) -> Self {
+        self.decoders.insert(version, Arc::new(decoder));
+        self
+    }
+
+    pub fn without_version(mut self, version: u16) -> Self {
+        self.decoders.remove(&version);
+        self
+    }
+
+    /// The accepted versions in ascending order.
+    pub fn versions(&self) -> Vec<u16> {
+        self.decoders.keys().copied().collect()
+    }
+
+    pub fn supports(&self, version: u16) -> bool {
+        self.decoders.contains_key(&version)
+    }
+
+    /// Returns the highest version accepted both here and by `other`, i.e.
+    /// the highest version both readers decode.
+    pub fn negotiate(&self, other: &[u16]) -> Option<u16> {
+        self.decoders
+            .keys()
+            .rev()
+            .find(|v| other.contains(v))
+            .copied()
+    }
+
+    /// Decodes a marker payload of any accepted version.
+    pub fn decode(&self, data: &[u8]) -> Result<VersionedPayload, Error> {
+        if data.len() < 2 || data[..2] != MARKER_PREFIX[..2] {
+            return Err(Error::ParseFailed("Invalid marker."));
+        }
+        if data.len() < 4 {
+            return Err(Error::ParseFailed("Invalid version."));
+        }
+        let version = u16::from_be_bytes([data[2], data[3]]);
+        let decoder = self
+            .decoders
+            .get(&version)
+            .ok_or(Error::ParseFailed("Invalid version."))?;
+        let (payload, extension) = decoder.decode(&data[4..])?;
+        Ok(VersionedPayload {
+            version,
+            payload,
+            extension,
+        })
+    }
+}
+
+/// A marker payload borrowing its data from the script of the marker output.
+///
+/// The structure is validated by `parse`, but the quantities are only decoded
+/// while iterating over them.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct PayloadRef<'a> {
+    count: usize,
+    quantities: &'a [u8],
+    metadata: &'a [u8],
+}
+
+impl<'a> PayloadRef<'a> {
+    /// Parses a payload with the same rules as the `Payload` decoder.
+    pub fn parse(data: &'a [u8]) -> Result<PayloadRef<'a>, Error> {
+        if data.len() < 2 || data[..2] != MARKER_PREFIX[..2] {
+            return Err(Error::ParseFailed("Invalid marker."));
+        }
+        if data.len() < 4 || data[2..4] != MARKER_PREFIX[2..] {
+            return Err(Error::ParseFailed("Invalid version."));
+        }
+        let mut rest = &data[4..];
+        let VarInt(count) = Decodable::consensus_decode(&mut rest)?;
+
+        let quantities = rest;
+        for _ in 0..count {
+            read_leb128(&mut rest)?;
+        }
+        let quantities = &quantities[..quantities.len() - rest.len()];
+
+        let VarInt(len) = Decodable::consensus_decode(&mut rest)?;
+        if (rest.len() as u64) < len {
+            return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        if rest.len() as u64 > len {
+            return Err(Error::ParseFailed(
+                "data not consumed entirely when explicitly deserializing",
+            ));
+        }
+        Ok(PayloadRef {
+            count: count as usize,
+            quantities,
+            metadata: rest,
+        })
+    }
+
+    pub fn quantity_count(&self) -> usize {
+        self.count
+    }
+
+    pub fn quantities(&self) -> Quantities<'a> {
+        Quantities {
+            data: self.quantities,
+            remaining: self.count,
+        }
+    }
+
+    pub fn metadata(&self) -> &'a [u8] {
+        self.metadata
+    }
+
+    pub fn to_payload(&self) -> Payload {
+        Payload {
+            quantities: self.quantities().collect(),
+            metadata: Metadata(self.metadata.to_vec()),
+        }
+    }
+}
+
+impl From<PayloadRef<'_>> for Payload {
+    fn from(payload: PayloadRef<'_>) -> Self {
+        payload.to_payload()
+    }
+}
+
+/// Iterates over the asset quantities of a `PayloadRef`.
+#[derive(Debug, Clone)]
+pub struct Quantities<'a> {
+    data: &'a [u8],
+    remaining: usize,
+}
+
+impl Iterator for Quantities<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // the encoding was validated by PayloadRef::parse
+        read_leb128(&mut self.data).ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Quantities<'_> {}
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Metadata(Vec<u8>);
 
@@ -116,9 +357,8 @@ impl Serialize for Metadata {
         let mut state = serializer.serialize_struct("Metadata", 2)?;
         let hex = hex::encode(self.0.clone());
         state.serialize_field("hex", &hex)?;
-        match String::from_utf8(self.0.clone()) {
-            Ok(s) => state.serialize_field("utf8", &s)?,
-            _ => {}
+        if let Ok(s) = String::from_utf8(self.0.clone()) {
+            state.serialize_field("utf8", &s)?;
         }
         state.end()
     }
@@ -127,53 +367,71 @@ impl Serialize for Metadata {
 pub trait TxOutExt {
     fn get_op_return_data(&self) -> Vec<u8>;
 
+    /// Returns the data pushed after OP_RETURN without copying it.
+    fn op_return_data(&self) -> Option<&[u8]>;
+
     fn is_openassets_marker(&self) -> bool;
 
     fn get_oa_payload(&self) -> Result<Payload, Error>;
+
+    fn get_oa_payload_ref(&self) -> Result<PayloadRef<'_>, Error>;
+
+    /// Decodes the payload of a marker of any version accepted by `versions`.
+    fn get_versioned_payload(&self, versions: &MarkerVersions) -> Result<VersionedPayload, Error>;
 }
 
 impl TxOutExt for TxOut {
     fn get_op_return_data(&self) -> Vec<u8> {
-        if self.script_pubkey.is_op_return() {
-            let mut script_iter = self.script_pubkey.instructions();
-            script_iter.next(); // OP_RETURN
-            let item = script_iter.next();
-            if item.is_some() {
-                return match item.unwrap().ok() {
-                    Some(Instruction::PushBytes(value)) => value.to_vec(),
-                    _ => vec![],
-                };
-            } else {
-                return vec![];
-            }
-        } else {
-            return vec![];
+        match self.op_return_data() {
+            Some(data) => data.to_vec(),
+            None => vec![],
+        }
+    }
+
+    fn op_return_data(&self) -> Option<&[u8]> {
+        if !self.script_pubkey.is_op_return() {
+            return None;
+        }
+        let mut script_iter = self.script_pubkey.instructions();
+        script_iter.next(); // OP_RETURN
+        match script_iter.next() {
+            Some(Ok(Instruction::PushBytes(value))) => Some(value),
+            _ => None,
         }
     }
 
     fn is_openassets_marker(&self) -> bool {
-        if self.script_pubkey.is_op_return() {
-            let payload: Result<Payload, _> = self.get_oa_payload();
-            return payload.is_ok();
-        } else {
-            return false;
+        match self.op_return_data() {
+            Some(data) => data.starts_with(&MARKER_PREFIX) && PayloadRef::parse(data).is_ok(),
+            None => false,
         }
     }
 
     fn get_oa_payload(&self) -> Result<Payload, Error> {
-        let op_return_data: Vec<u8> = self.get_op_return_data();
-        let payload: Result<Payload, _> = deserialize(&op_return_data);
-        return payload;
+        self.get_oa_payload_ref().map(Payload::from)
+    }
+
+    fn get_oa_payload_ref(&self) -> Result<PayloadRef<'_>, Error> {
+        PayloadRef::parse(self.op_return_data().unwrap_or(&[]))
+    }
+
+    fn get_versioned_payload(&self, versions: &MarkerVersions) -> Result<VersionedPayload, Error> {
+        versions.decode(self.op_return_data().unwrap_or(&[]))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::openassets::marker_output::{
+        read_quantities, MarkerVersions, Metadata, Payload, PayloadRef, TxOutExt, VersionDecoder,
+        VersionedPayload,
+    };
     use hex::decode as hex_decode;
-    use crate::openassets::marker_output::{Metadata, Payload, TxOutExt};
     use serde_json::json;
     use tapyrus::blockdata::script::Builder;
-    use tapyrus::consensus::serialize;
+    use tapyrus::consensus::encode::Error;
+    use tapyrus::consensus::Decodable;
+    use tapyrus::consensus::{deserialize, serialize};
     use tapyrus::hashes::hex::FromHex;
     use tapyrus::{Script, TxOut};
 
@@ -355,6 +613,14 @@ mod tests {
         };
         let payload: Payload = marker_output.get_oa_payload().unwrap();
         assert_eq!(vec![127, 128, 12857], payload.quantities);
+
+        // the largest quantity fits in 10 bytes, a larger 10th byte overflows
+        let data = hex_decode("4f41010001ffffffffffffffffff0100").unwrap();
+        let payload: Payload = deserialize(&data).unwrap();
+        assert_eq!(vec![u64::MAX], payload.quantities);
+        assert_eq!(data, serialize(&payload));
+        let data = hex_decode("4f41010001ffffffffffffffffff0200").unwrap();
+        assert!(deserialize::<Payload>(&data).is_err());
     }
 
     #[test]
@@ -418,4 +684,171 @@ mod tests {
         let metadata = Metadata(vec![0x01, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc]);
         assert_eq!(json!(metadata), json!({"hex": "01020304fffefdfc"}));
     }
+
+    #[test]
+    fn test_payload_ref() {
+        let marker_output = TxOut {
+            value: 0,
+            script_pubkey: Builder::from(
+                hex_decode(
+                    "6a244f4101000364007b1b753d68747470733a2f2f6370722e736d2f35596753553150672d71",
+                )
+                .unwrap(),
+            )
+            .into_script(),
+        };
+        let payload: PayloadRef = marker_output.get_oa_payload_ref().unwrap();
+        assert_eq!(3, payload.quantity_count());
+        assert_eq!(3, payload.quantities().len());
+        assert_eq!(
+            vec![100, 0, 123],
+            payload.quantities().collect::<Vec<u64>>()
+        );
+        assert_eq!(b"u=https://cpr.sm/5YgSU1Pg-q", payload.metadata());
+
+        // metadata points into the script itself
+        let script = marker_output.script_pubkey.as_bytes();
+        let range = script.as_ptr_range();
+        assert!(range.contains(&payload.metadata().as_ptr()));
+        assert_eq!(
+            marker_output.get_oa_payload().unwrap(),
+            payload.to_payload()
+        );
+        assert_eq!(
+            marker_output.get_oa_payload().unwrap(),
+            Payload::from(payload)
+        );
+
+        // leb128
+        let data = hex_decode("4f410100037f8001b96400").unwrap();
+        let payload = PayloadRef::parse(&data).unwrap();
+        assert_eq!(
+            vec![127, 128, 12857],
+            payload.quantities().collect::<Vec<u64>>()
+        );
+        assert!(payload.metadata().is_empty());
+
+        // same results as the owned decoder
+        for data in [
+            "",
+            "4f",
+            "4f42010003",
+            "4f4102000364007b00",
+            "4f410100ff",
+            "4f410100028f7f",
+            "4f410100018f7f",
+            "4f4101000201440801020304fffefd",
+            "4f4101000201440801020304fffefdfc00",
+            "4f4101000201440801020304fffefdfc",
+        ] {
+            let data = hex_decode(data).unwrap();
+            let owned: Result<Payload, _> = tapyrus::consensus::deserialize(&data);
+            let borrowed = PayloadRef::parse(&data).map(Payload::from);
+            assert_eq!(owned.is_ok(), borrowed.is_ok(), "{}", hex::encode(&data));
+            if let Ok(owned) = owned {
+                assert_eq!(owned, borrowed.unwrap());
+            }
+        }
+
+        // quantities longer than 64 bits are rejected
+        let data = hex_decode("4f41010001ffffffffffffffffffff0100").unwrap();
+        assert!(PayloadRef::parse(&data).is_err());
+        let owned: Result<Payload, _> = tapyrus::consensus::deserialize(&data);
+        assert!(owned.is_err());
+        let data = hex_decode("4f41010001ffffffffffffffffff0100").unwrap();
+        assert_eq!(
+            vec![u64::MAX],
+            PayloadRef::parse(&data)
+                .unwrap()
+                .quantities()
+                .collect::<Vec<u64>>()
+        );
+    }
+
+    /// A version with one flag byte per quantity between the quantities and
+    /// the metadata.
+    struct FlagsDecoder;
+
+    impl VersionDecoder for FlagsDecoder {
+        fn decode(&self, mut body: &[u8]) -> Result<(Payload, Vec<u8>), Error> {
+            let quantities = read_quantities(&mut body)?;
+            if body.len() < quantities.len() {
+                return Err(Error::ParseFailed("missing flags"));
+            }
+            let (flags, mut rest) = body.split_at(quantities.len());
+            let metadata = Metadata::consensus_decode(&mut rest)?;
+            if !rest.is_empty() {
+                return Err(Error::ParseFailed("trailing data"));
+            }
+            Ok((
+                Payload {
+                    quantities,
+                    metadata,
+                },
+                flags.to_vec(),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_marker_versions() {
+        let v1 = hex_decode("4f41010002014400").unwrap();
+        let flagged = hex_decode("4f4102000201440100020102").unwrap();
+        let versions = MarkerVersions::default();
+        assert_eq!(vec![0x0100], versions.versions());
+        assert_eq!(
+            VersionedPayload {
+                version: 0x0100,
+                payload: tapyrus::consensus::deserialize(&v1).unwrap(),
+                extension: vec![],
+            },
+            versions.decode(&v1).unwrap()
+        );
+        assert!(versions.decode(&flagged).is_err());
+        assert!(MarkerVersions::empty().decode(&v1).is_err());
+
+        let versions = versions.with_version(0x0200, FlagsDecoder);
+        assert!(versions.supports(0x0200));
+        assert_eq!(
+            VersionedPayload {
+                version: 0x0200,
+                payload: Payload {
+                    quantities: vec![1, 68],
+                    metadata: Metadata(vec![0x01, 0x02]),
+                },
+                extension: vec![0x01, 0x00],
+            },
+            versions.decode(&flagged).unwrap()
+        );
+        // the flags of both quantities are required
+        assert!(versions
+            .decode(&hex_decode("4f41020002014401").unwrap())
+            .is_err());
+
+        assert_eq!(Some(0x0200), versions.negotiate(&[0x0100, 0x0200]));
+        assert_eq!(Some(0x0100), versions.negotiate(&[0x0100, 0x0300]));
+        assert_eq!(None, versions.negotiate(&[0x0300]));
+        assert_eq!(
+            Some(0x0100),
+            versions
+                .without_version(0x0200)
+                .negotiate(&[0x0100, 0x0200])
+        );
+
+        let txout = TxOut {
+            value: 0,
+            script_pubkey: Builder::new()
+                .push_opcode(tapyrus::blockdata::opcodes::all::OP_RETURN)
+                .push_slice(&flagged)
+                .into_script(),
+        };
+        assert!(!txout.is_openassets_marker());
+        assert_eq!(
+            0x0200,
+            txout
+                .get_versioned_payload(&MarkerVersions::new().with_version(0x0200, FlagsDecoder))
+                .unwrap()
+                .version
+        );
+    }
 }
@@ -1,3 +1,29 @@
 pub mod address;
 pub mod asset_id;
+#[cfg(feature = "async")]
+pub mod async_coloring;
+pub mod blockfile;
+pub mod builder;
+pub mod cache;
+pub mod coloring;
+pub mod dust;
+pub mod electrum;
+pub mod fee;
+pub mod hd_wallet;
 pub mod marker_output;
+pub mod network;
+pub mod pipeline;
+pub mod policy;
+pub mod psbt;
+#[cfg(feature = "server")]
+pub mod rpc;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sign;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod validator;
+pub mod watch_only;
+
+#[cfg(test)]
+pub(crate) mod test_helpers;
@@ -0,0 +1,406 @@
+use std::fmt;
+use std::sync::RwLock;
+
+use tapyrus::network::constants::Network;
+use tapyrus::util::address::Payload;
+
+use crate::openassets::dust::{DustPolicy, DEFAULT_DUST_RELAY_FEE};
+use crate::openassets::policy::{Policy, DEFAULT_MAX_MARKER_SIZE};
+
+/// The chain specific values Open Assets addresses, asset ids and relay
+/// policies are encoded with.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
+pub struct NetworkParams {
+    pub name: &'static str,
+    /// The Tapyrus network whose script and address rules the chain follows.
+    pub network: Network,
+    /// The Tapyrus network id, `None` on Bitcoin.
+    pub network_id: Option<u32>,
+    /// The message start bytes of the P2P protocol and block files, `None`
+    /// when they depend on the node configuration.
+    pub magic: Option<[u8; 4]>,
+    pub p2pkh_prefix: u8,
+    pub p2sh_prefix: u8,
+    /// The prefixes of colored addresses, `None` on chains without colored coins.
+    pub cp2pkh_prefix: Option<u8>,
+    pub cp2sh_prefix: Option<u8>,
+    pub asset_id_version: u8,
+    /// The byte prepended to addresses to make Open Assets addresses.
+    pub oa_namespace: u8,
+    /// The dust relay fee rate in the smallest unit per 1000 bytes.
+    pub dust_relay_fee: u64,
+    /// The largest marker output script relayed by default.
+    pub max_marker_size: usize,
+}
+
+pub const TAPYRUS_PROD: NetworkParams = NetworkParams {
+    name: "tapyrus",
+    network: Network::Prod,
+    network_id: Some(1),
+    magic: None,
+    p2pkh_prefix: 0,
+    p2sh_prefix: 5,
+    cp2pkh_prefix: Some(1),
+    cp2sh_prefix: Some(6),
+    asset_id_version: 0x17,
+    oa_namespace: 0x13,
+    dust_relay_fee: DEFAULT_DUST_RELAY_FEE,
+    max_marker_size: DEFAULT_MAX_MARKER_SIZE,
+};
+
+pub const TAPYRUS_DEV: NetworkParams = NetworkParams {
+    name: "tapyrus-dev",
+    network: Network::Dev,
+    network_id: Some(1905960821),
+    p2pkh_prefix: 111,
+    p2sh_prefix: 196,
+    cp2pkh_prefix: Some(112),
+    cp2sh_prefix: Some(197),
+    asset_id_version: 0x73,
+    ..TAPYRUS_PROD
+};
+
+pub const BITCOIN: NetworkParams = NetworkParams {
+    name: "bitcoin",
+    network_id: None,
+    magic: Some([0xf9, 0xbe, 0xb4, 0xd9]),
+    cp2pkh_prefix: None,
+    cp2sh_prefix: None,
+    ..TAPYRUS_PROD
+};
+
+pub const BITCOIN_TESTNET: NetworkParams = NetworkParams {
+    name: "bitcoin-testnet",
+    network_id: None,
+    magic: Some([0x0b, 0x11, 0x09, 0x07]),
+    cp2pkh_prefix: None,
+    cp2sh_prefix: None,
+    ..TAPYRUS_DEV
+};
+
+pub const BITCOIN_REGTEST: NetworkParams = NetworkParams {
+    name: "bitcoin-regtest",
+    magic: Some([0xfa, 0xbf, 0xb5, 0xda]),
+    ..BITCOIN_TESTNET
+};
+
+/// The built-in parameter sets. Tapyrus comes first, so strings valid on both
+/// chains decode as Tapyrus.
+pub static PRESETS: [&NetworkParams; 5] = [
+    &TAPYRUS_PROD,
+    &TAPYRUS_DEV,
+    &BITCOIN,
+    &BITCOIN_TESTNET,
+    &BITCOIN_REGTEST,
+];
+
+/// The longest name a registered parameter set may have, in bytes.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Parameter sets registered at runtime, for private Tapyrus chains.
+static REGISTRY: RwLock<Vec<&'static NetworkParams>> = RwLock::new(Vec::new());
+
+#[derive(Debug)]
+pub enum Error {
+    /// A preset or registered parameter set already uses the name.
+    DuplicateName(&'static str),
+    /// A preset or registered parameter set already uses the network id.
+    DuplicateNetworkId(u32),
+    /// A preset or registered parameter set already uses the asset id version.
+    DuplicateAssetIdVersion(u8),
+    /// A preset or registered parameter set with the same Open Assets
+    /// namespace already uses the address prefix.
+    DuplicateAddressPrefix(u8),
+    /// The name is longer than `MAX_NAME_LEN` bytes.
+    NameTooLong(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::DuplicateName(name) => write!(f, "network {} is already known", name),
+            Error::DuplicateNetworkId(id) => write!(f, "network id {} is already known", id),
+            Error::DuplicateAssetIdVersion(version) => {
+                write!(f, "asset id version {} is already known", version)
+            }
+            Error::DuplicateAddressPrefix(prefix) => {
+                write!(f, "address prefix {} is already known", prefix)
+            }
+            Error::NameTooLong(len) => write!(f, "network name of {} bytes is too long", len),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Registers a custom parameter set, which the `FromStr` implementations of
+/// `AssetId` and `Address` then try after the presets.
+///
+/// The version bytes must differ from those of every known parameter set, so
+/// that strings decode to exactly one network, and the name must be at most
+/// `MAX_NAME_LEN` bytes long. The parameters live for the rest of the process.
+pub fn register(params: NetworkParams) -> Result<&'static NetworkParams, Error> {
+    if params.name.len() > MAX_NAME_LEN {
+        return Err(Error::NameTooLong(params.name.len()));
+    }
+    let mut registry = REGISTRY.write().unwrap();
+    for known in PRESETS.iter().chain(registry.iter()) {
+        if known.name == params.name {
+            return Err(Error::DuplicateName(params.name));
+        }
+        if let Some(id) = params.network_id.filter(|id| known.network_id == Some(*id)) {
+            return Err(Error::DuplicateNetworkId(id));
+        }
+        if known.asset_id_version == params.asset_id_version {
+            return Err(Error::DuplicateAssetIdVersion(params.asset_id_version));
+        }
+        if known.oa_namespace == params.oa_namespace {
+            let prefixes = known.prefixes();
+            if let Some(prefix) = params
+                .prefixes()
+                .find(|p| prefixes.clone().any(|k| k == *p))
+            {
+                return Err(Error::DuplicateAddressPrefix(prefix));
+            }
+        }
+    }
+    let params: &'static NetworkParams = Box::leak(Box::new(params));
+    registry.push(params);
+    Ok(params)
+}
+
+/// Returns the presets followed by the registered parameter sets.
+pub fn all() -> Vec<&'static NetworkParams> {
+    let registry = REGISTRY.read().unwrap();
+    PRESETS.iter().chain(registry.iter()).copied().collect()
+}
+
+impl NetworkParams {
+    /// The address prefixes in use.
+    fn prefixes(&self) -> impl Iterator<Item = u8> + Clone {
+        [
+            Some(self.p2pkh_prefix),
+            Some(self.p2sh_prefix),
+            self.cp2pkh_prefix,
+            self.cp2sh_prefix,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Returns the parameters of the Tapyrus `network`.
+    pub fn tapyrus(network: Network) -> &'static NetworkParams {
+        match network {
+            Network::Prod => &TAPYRUS_PROD,
+            Network::Dev => &TAPYRUS_DEV,
+        }
+    }
+
+    /// Finds a preset or registered parameter set by name.
+    pub fn by_name(name: &str) -> Option<&'static NetworkParams> {
+        all().into_iter().find(|p| p.name == name)
+    }
+
+    pub fn by_network_id(network_id: u32) -> Option<&'static NetworkParams> {
+        all().into_iter().find(|p| p.network_id == Some(network_id))
+    }
+
+    pub fn by_magic(magic: [u8; 4]) -> Option<&'static NetworkParams> {
+        all().into_iter().find(|p| p.magic == Some(magic))
+    }
+
+    /// Returns the address prefix of `payload`, or `None` if the chain does
+    /// not support this kind of payload.
+    pub fn address_prefix(&self, payload: &Payload) -> Option<u8> {
+        match payload {
+            Payload::PubkeyHash(_) => Some(self.p2pkh_prefix),
+            Payload::ScriptHash(_) => Some(self.p2sh_prefix),
+            Payload::ColoredPubkeyHash(_, _) => self.cp2pkh_prefix,
+            Payload::ColoredScriptHash(_, _) => self.cp2sh_prefix,
+        }
+    }
+
+    /// Maps the address `prefix` of this chain to the prefix of the same kind of
+    /// payload on the Tapyrus network the chain follows.
+    pub(crate) fn tapyrus_prefix(&self, prefix: u8) -> Option<u8> {
+        let tapyrus = NetworkParams::tapyrus(self.network);
+        if prefix == self.p2pkh_prefix {
+            Some(tapyrus.p2pkh_prefix)
+        } else if prefix == self.p2sh_prefix {
+            Some(tapyrus.p2sh_prefix)
+        } else if Some(prefix) == self.cp2pkh_prefix {
+            tapyrus.cp2pkh_prefix
+        } else if Some(prefix) == self.cp2sh_prefix {
+            tapyrus.cp2sh_prefix
+        } else {
+            None
+        }
+    }
+
+    pub fn dust_policy(&self) -> DustPolicy {
+        DustPolicy::new(self.dust_relay_fee)
+    }
+
+    /// Returns the default policy with the relay limits of this chain.
+    pub fn policy(&self) -> Policy {
+        Policy::new()
+            .with_max_marker_size(self.max_marker_size)
+            .with_dust_policy(self.dust_policy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::address::Address;
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::network::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(&TAPYRUS_PROD, NetworkParams::tapyrus(Network::Prod));
+        assert_eq!(&TAPYRUS_DEV, NetworkParams::tapyrus(Network::Dev));
+        assert_eq!(
+            Some(&BITCOIN_REGTEST),
+            NetworkParams::by_name("bitcoin-regtest")
+        );
+        assert_eq!(None, NetworkParams::by_name("litecoin"));
+
+        let cp2sh = tapyrus::Address::from_str(
+            "4Zxhb33iSoydtcKzWc7hpoRjtJh2W9otwDeqP5PbZHGoq7x6JndvyoFpAn5vzLCtLA5hyYTuJsH4gNP",
+        )
+        .unwrap();
+        assert_eq!(Some(6), TAPYRUS_PROD.address_prefix(&cp2sh.payload));
+        assert_eq!(None, BITCOIN.address_prefix(&cp2sh.payload));
+        assert_eq!(Some(112), TAPYRUS_DEV.tapyrus_prefix(112));
+        assert_eq!(None, BITCOIN_TESTNET.tapyrus_prefix(112));
+        assert_eq!(Some(111), BITCOIN_REGTEST.tapyrus_prefix(111));
+    }
+
+    #[test]
+    fn test_register() {
+        let params = NetworkParams {
+            name: "test-register",
+            network_id: Some(9_000_001),
+            magic: Some([0x01, 0x02, 0x03, 0x04]),
+            p2pkh_prefix: 40,
+            p2sh_prefix: 41,
+            cp2pkh_prefix: Some(42),
+            cp2sh_prefix: Some(43),
+            asset_id_version: 44,
+            ..TAPYRUS_DEV
+        };
+        let registered = register(params).unwrap();
+        assert_eq!(&params, registered);
+        assert_eq!(Some(registered), NetworkParams::by_name("test-register"));
+        assert_eq!(Some(registered), NetworkParams::by_network_id(9_000_001));
+        assert_eq!(
+            Some(registered),
+            NetworkParams::by_magic([0x01, 0x02, 0x03, 0x04])
+        );
+        assert!(all().contains(&registered));
+
+        assert!(register(params).is_err());
+        let other = NetworkParams {
+            name: "test-register-other",
+            network_id: Some(9_000_005),
+            asset_id_version: 45,
+            p2pkh_prefix: 46,
+            p2sh_prefix: 47,
+            cp2pkh_prefix: None,
+            cp2sh_prefix: None,
+            ..params
+        };
+        assert!(matches!(
+            register(NetworkParams {
+                network_id: params.network_id,
+                ..other
+            }),
+            Err(Error::DuplicateNetworkId(9_000_001))
+        ));
+        assert!(matches!(
+            register(NetworkParams {
+                asset_id_version: TAPYRUS_DEV.asset_id_version,
+                ..other
+            }),
+            Err(Error::DuplicateAssetIdVersion(0x73))
+        ));
+        assert!(matches!(
+            register(NetworkParams {
+                cp2sh_prefix: Some(42),
+                ..other
+            }),
+            Err(Error::DuplicateAddressPrefix(42))
+        ));
+        assert!(matches!(
+            register(NetworkParams {
+                p2pkh_prefix: TAPYRUS_PROD.p2pkh_prefix,
+                ..other
+            }),
+            Err(Error::DuplicateAddressPrefix(0))
+        ));
+        let long = "n".repeat(256);
+        assert!(matches!(
+            register(NetworkParams {
+                name: Box::leak(long.into_boxed_str()),
+                ..other
+            }),
+            Err(Error::NameTooLong(256))
+        ));
+        let dev = NetworkParams {
+            name: "test-register-dev",
+            ..TAPYRUS_DEV
+        };
+        assert!(register(dev).is_err());
+
+        // the same prefixes are unambiguous under another namespace
+        let namespaced = register(NetworkParams {
+            oa_namespace: 0x14,
+            p2pkh_prefix: 40,
+            ..other
+        })
+        .unwrap();
+        assert_eq!(
+            Some(namespaced),
+            NetworkParams::by_name("test-register-other")
+        );
+    }
+
+    #[test]
+    fn test_custom_encodings() {
+        let custom = register(NetworkParams {
+            name: "test-custom-encodings",
+            network_id: Some(9_000_003),
+            p2pkh_prefix: 50,
+            p2sh_prefix: 51,
+            cp2pkh_prefix: Some(52),
+            cp2sh_prefix: Some(53),
+            asset_id_version: 54,
+            ..TAPYRUS_DEV
+        })
+        .unwrap();
+
+        let script = tapyrus::Address::from_str("mkgW6hNYBctmqDtTTsTJrsf2Gh2NPtoCU4")
+            .unwrap()
+            .script_pubkey();
+        let asset_id = AssetId::with_params(&script, custom).to_string();
+        assert_eq!(custom, AssetId::from_str(&asset_id).unwrap().params());
+        assert!(AssetId::from_str_with_params(&asset_id, &TAPYRUS_DEV).is_err());
+        let dev_asset_id = AssetId::new(&script, Network::Dev).to_string();
+        assert!(AssetId::from_str_with_params(&dev_asset_id, custom).is_err());
+
+        let payload = Address::from_str("bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE")
+            .unwrap()
+            .payload;
+        let address = Address::with_params(payload.clone(), custom)
+            .unwrap()
+            .to_string();
+        let parsed = Address::from_str(&address).unwrap();
+        assert_eq!(custom, parsed.params());
+        assert_eq!(payload, parsed.payload);
+        assert!(Address::from_str_with_params(&address, &TAPYRUS_DEV).is_err());
+        assert!(
+            Address::from_str_with_params("bWvePLsBsf6nThU3pWVZVWjZbcJCYQxHCpE", custom).is_err()
+        );
+    }
+}
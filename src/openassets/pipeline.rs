@@ -0,0 +1,592 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use tapyrus::blockdata::script::Instruction;
+#[cfg(feature = "parallel")]
+use tapyrus::consensus::{deserialize, encode};
+use tapyrus::network::constants::Network;
+use tapyrus::util::key::PublicKey;
+use tapyrus::{Address, Block, OutPoint, Script, Transaction, TxIn, TxOut, Txid};
+
+use crate::openassets::coloring::{color_outputs_with, find_marker_with, ColoredOutput, Error};
+use crate::openassets::marker_output::{MarkerVersions, Payload};
+use crate::openassets::network::NetworkParams;
+
+/// The default number of blocks parsed in parallel before being colored.
+pub const DEFAULT_CHUNK_SIZE: usize = 256;
+
+/// A transaction with its txid and marker output already extracted.
+#[derive(Debug, Clone)]
+pub struct ParsedTx {
+    pub tx: Transaction,
+    pub txid: Txid,
+    pub marker: Option<(usize, Payload)>,
+}
+
+impl ParsedTx {
+    pub fn new(tx: Transaction) -> Self {
+        ParsedTx::with_versions(tx, &MarkerVersions::default())
+    }
+
+    /// Finds the marker output among the versions in `versions`, which must be
+    /// those of the `ChainColorer` applying the transaction.
+    pub fn with_versions(tx: Transaction, versions: &MarkerVersions) -> Self {
+        ParsedTx {
+            txid: tx.txid(),
+            marker: find_marker_with(&tx, versions).map(|(i, p)| (i, p.payload)),
+            tx,
+        }
+    }
+
+    /// See `coloring::needs_inputs`.
+    pub fn needs_inputs(&self) -> bool {
+        self.marker.is_some() && !self.tx.is_coin_base()
+    }
+}
+
+/// The coloring of the outputs of one transaction.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TxColoring {
+    pub txid: Txid,
+    pub outputs: Vec<ColoredOutput>,
+}
+
+pub fn parse_block(block: &Block) -> Vec<ParsedTx> {
+    parse_block_with(block, &MarkerVersions::default())
+}
+
+pub fn parse_block_with(block: &Block, versions: &MarkerVersions) -> Vec<ParsedTx> {
+    block
+        .txdata
+        .iter()
+        .map(|tx| ParsedTx::with_versions(tx.clone(), versions))
+        .collect()
+}
+
+/// Colors the transactions of a chain in order.
+///
+/// The coloring of unspent outputs is kept in memory, so every transaction is
+/// colored from its inputs without looking up its ancestry.
+pub struct ChainColorer {
+    params: &'static NetworkParams,
+    versions: MarkerVersions,
+    unspent: HashMap<OutPoint, ColoredOutput>,
+    missing_uncolored: bool,
+}
+
+impl ChainColorer {
+    pub fn new(network: Network) -> Self {
+        ChainColorer::with_params(NetworkParams::tapyrus(network))
+    }
+
+    /// Colors with the asset ids of `params`, e.g. of a Bitcoin network.
+    pub fn with_params(params: &'static NetworkParams) -> Self {
+        ChainColorer {
+            params,
+            versions: MarkerVersions::default(),
+            unspent: HashMap::new(),
+            missing_uncolored: false,
+        }
+    }
+
+    /// Takes inputs missing from the unspent outputs for uncolored outputs,
+    /// so that the colorer can resume from the outputs of `colored_unspent`.
+    ///
+    /// The asset id of an issuance spending a missing output is derived from
+    /// the script recovered from the scriptSig of its first input, which is
+    /// only known for P2PKH and P2SH multisig spends; other issuances spending
+    /// a missing output fail with `Error::OutputNotFound`.
+    pub fn with_missing_as_uncolored(mut self) -> Self {
+        self.missing_uncolored = true;
+        self
+    }
+
+    /// See `Colorer::with_marker_versions`.
+    pub fn with_marker_versions(mut self, versions: MarkerVersions) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    pub fn network(&self) -> Network {
+        self.params.network
+    }
+
+    pub fn params(&self) -> &'static NetworkParams {
+        self.params
+    }
+
+    pub fn marker_versions(&self) -> &MarkerVersions {
+        &self.versions
+    }
+
+    /// Returns the coloring of an unspent output created by a colored transaction.
+    pub fn get_unspent(&self, outpoint: &OutPoint) -> Option<&ColoredOutput> {
+        self.unspent.get(outpoint)
+    }
+
+    /// Iterates over the unspent outputs seen so far.
+    pub fn unspent(&self) -> impl Iterator<Item = (&OutPoint, &ColoredOutput)> {
+        self.unspent.iter()
+    }
+
+    /// Iterates over the unspent outputs a colorer `with_missing_as_uncolored`
+    /// needs to resume from: the colored outputs, and the colored P2PKH and
+    /// P2SH outputs, whose scriptSig can not be told apart from that of the
+    /// uncolored ones.
+    pub fn colored_unspent(&self) -> impl Iterator<Item = (&OutPoint, &ColoredOutput)> {
+        self.unspent.iter().filter(|(_, o)| {
+            o.is_colored() || o.script_pubkey.is_cp2pkh() || o.script_pubkey.is_cp2sh()
+        })
+    }
+
+    /// Records the coloring of an unspent output, e.g. when resuming from a
+    /// state saved from `unspent`.
+    pub fn insert_unspent(&mut self, outpoint: OutPoint, output: ColoredOutput) {
+        self.unspent.insert(outpoint, output);
+    }
+
+    /// Colors `tx`, which must spend outputs of already colored transactions if
+    /// it has a marker output.
+    pub fn color_transaction(&mut self, tx: &Transaction) -> Result<Vec<ColoredOutput>, Error> {
+        self.apply(&ParsedTx::with_versions(tx.clone(), &self.versions))
+    }
+
+    pub fn color_block(&mut self, block: &Block) -> Result<Vec<TxColoring>, Error> {
+        self.apply_all(&parse_block_with(block, &self.versions))
+    }
+
+    /// Colors a transaction parsed by `ParsedTx::with_versions` with the
+    /// versions of this colorer.
+    pub fn apply(&mut self, parsed: &ParsedTx) -> Result<Vec<ColoredOutput>, Error> {
+        let outputs = if parsed.needs_inputs() {
+            // the colorer is left untouched if an input is missing
+            let first = &parsed.tx.input[0];
+            let first_script =
+                if self.missing_uncolored && !self.unspent.contains_key(&first.previous_output) {
+                    match spent_script(first, self.params.network) {
+                        Some(script) => script,
+                        None if issues(parsed) => {
+                            return Err(Error::OutputNotFound(first.previous_output));
+                        }
+                        None => Script::new(),
+                    }
+                } else {
+                    Script::new()
+                };
+            if let Some(input) =
+                parsed.tx.input.iter().find(|i| {
+                    !self.missing_uncolored && !self.unspent.contains_key(&i.previous_output)
+                })
+            {
+                return Err(Error::OutputNotFound(input.previous_output));
+            }
+            let inputs: Vec<ColoredOutput> = parsed
+                .tx
+                .input
+                .iter()
+                .enumerate()
+                .map(
+                    |(i, input)| match self.unspent.remove(&input.previous_output) {
+                        Some(output) => output,
+                        None => ColoredOutput::uncolored(&TxOut {
+                            value: 0,
+                            script_pubkey: if i == 0 {
+                                first_script.clone()
+                            } else {
+                                Script::new()
+                            },
+                        }),
+                    },
+                )
+                .collect();
+            color_outputs_with(&parsed.tx, &inputs, self.params, &self.versions)
+        } else {
+            for input in parsed.tx.input.iter() {
+                self.unspent.remove(&input.previous_output);
+            }
+            parsed
+                .tx
+                .output
+                .iter()
+                .map(ColoredOutput::uncolored)
+                .collect()
+        };
+        for (vout, output) in outputs.iter().enumerate() {
+            self.unspent
+                .insert(OutPoint::new(parsed.txid, vout as u32), output.clone());
+        }
+        Ok(outputs)
+    }
+
+    pub fn apply_all(&mut self, parsed: &[ParsedTx]) -> Result<Vec<TxColoring>, Error> {
+        parsed
+            .iter()
+            .map(|p| {
+                Ok(TxColoring {
+                    txid: p.txid,
+                    outputs: self.apply(p)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns true if the marker of `parsed` gives asset units to outputs before
+/// it, which are issued with the asset id of the script of the first input.
+fn issues(parsed: &ParsedTx) -> bool {
+    parsed
+        .marker
+        .as_ref()
+        .is_some_and(|(index, payload)| payload.quantities.iter().take(*index).any(|&q| q > 0))
+}
+
+/// Recovers the script of the output spent by `input` from its scriptSig: a
+/// signature and a public key spend a P2PKH output, and a multisig redeem
+/// script pushed last a P2SH output.
+fn spent_script(input: &TxIn, network: Network) -> Option<Script> {
+    let mut pushes = vec![];
+    for instruction in input.script_sig.instructions() {
+        match instruction.ok()? {
+            Instruction::PushBytes(data) => pushes.push(data),
+            Instruction::Op(_) => return None,
+        }
+    }
+    match pushes.as_slice() {
+        [_, key] if key.len() >= 33 => {
+            let key = PublicKey::from_slice(key).ok()?;
+            Some(Address::p2pkh(&key, network).script_pubkey())
+        }
+        [[], .., redeem_script] => {
+            let redeem_script = Script::from(redeem_script.to_vec());
+            if redeem_script.is_multisig() {
+                Some(Address::p2sh(&redeem_script, network).script_pubkey())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Colors `blocks`, given in chain order, on a single thread.
+pub fn color_blocks(
+    colorer: &mut ChainColorer,
+    blocks: &[Block],
+) -> Result<Vec<Vec<TxColoring>>, Error> {
+    blocks.iter().map(|b| colorer.color_block(b)).collect()
+}
+
+/// Colors `blocks`, given in chain order, parsing up to `chunk_size` blocks
+/// in parallel and then applying the coloring in chain order.
+///
+/// The result is identical to `color_blocks`.
+#[cfg(feature = "parallel")]
+pub fn color_blocks_parallel(
+    colorer: &mut ChainColorer,
+    blocks: &[Block],
+    chunk_size: usize,
+) -> Result<Vec<Vec<TxColoring>>, Error> {
+    let mut result = Vec::with_capacity(blocks.len());
+    for chunk in blocks.chunks(std::cmp::max(chunk_size, 1)) {
+        let versions = colorer.marker_versions();
+        let parsed: Vec<Vec<ParsedTx>> = chunk
+            .par_iter()
+            .map(|block| parse_block_with(block, versions))
+            .collect();
+        for block in parsed.iter() {
+            result.push(colorer.apply_all(block)?);
+        }
+    }
+    Ok(result)
+}
+
+/// Like `color_blocks_parallel`, but also deserializes the raw blocks in parallel.
+#[cfg(feature = "parallel")]
+pub fn color_raw_blocks_parallel(
+    colorer: &mut ChainColorer,
+    raw_blocks: &[Vec<u8>],
+    chunk_size: usize,
+) -> Result<Vec<Vec<TxColoring>>, PipelineError> {
+    let mut result = Vec::with_capacity(raw_blocks.len());
+    for chunk in raw_blocks.chunks(std::cmp::max(chunk_size, 1)) {
+        let parsed: Vec<Vec<ParsedTx>> = chunk
+            .par_iter()
+            .map(|raw| {
+                deserialize::<Block>(raw)
+                    .map(|block| parse_block_with(&block, colorer.marker_versions()))
+            })
+            .collect::<Result<_, encode::Error>>()?;
+        for block in parsed.iter() {
+            result.push(colorer.apply_all(block)?);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(feature = "parallel")]
+#[derive(Debug)]
+pub enum PipelineError {
+    Encode(encode::Error),
+    Coloring(Error),
+}
+
+#[cfg(feature = "parallel")]
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PipelineError::Encode(ref e) => write!(f, "block decode error: {}", e),
+            PipelineError::Coloring(ref e) => write!(f, "coloring error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl std::error::Error for PipelineError {}
+
+#[cfg(feature = "parallel")]
+impl From<encode::Error> for PipelineError {
+    fn from(e: encode::Error) -> Self {
+        PipelineError::Encode(e)
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl From<Error> for PipelineError {
+    fn from(e: Error) -> Self {
+        PipelineError::Coloring(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::coloring::{ColoredOutput, Colorer, Error};
+    use crate::openassets::marker_output::{MarkerVersions, V1Decoder};
+    use crate::openassets::pipeline::{color_blocks, ChainColorer};
+    use crate::openassets::test_helpers::*;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use tapyrus::blockdata::script::Builder;
+    use tapyrus::hashes::Hash;
+    use tapyrus::network::constants::Network;
+    use tapyrus::util::key::PublicKey;
+    use tapyrus::{Block, BlockHash, OutPoint, Script, Transaction, Txid};
+
+    /// A chain of `length` blocks, each transferring the asset issued in the first one.
+    fn chain(length: usize) -> Vec<Block> {
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![
+                txout(600, p2pkh(1)),
+                txout(600, p2pkh(2)),
+                marker(vec![100, 50], b""),
+            ],
+        );
+        let mut blocks = vec![block(
+            &BlockHash::from_slice(&[0; 32]).unwrap(),
+            0,
+            &[funding, issuance.clone()],
+        )];
+        let mut prev = (OutPoint::new(issuance.txid(), 0), 100u64);
+        for i in 1..length {
+            let (outpoint, quantity) = prev;
+            let transfer = tx(
+                &[outpoint],
+                vec![
+                    marker(vec![quantity - 1, 1], b""),
+                    txout(600, p2pkh(3)),
+                    txout(600, p2pkh(4)),
+                ],
+            );
+            let plain = tx(
+                &[OutPoint::new(transfer.txid(), 2)],
+                vec![txout(500, p2pkh(5))],
+            );
+            prev = (OutPoint::new(transfer.txid(), 1), quantity - 1);
+            let prev_hash = blocks.last().unwrap().block_hash();
+            blocks.push(block(&prev_hash, i as u32, &[transfer, plain]));
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_matches_recursive_colorer() {
+        let blocks = chain(20);
+        let mut chain_colorer = ChainColorer::new(Network::Prod);
+        let colorings = color_blocks(&mut chain_colorer, &blocks).unwrap();
+
+        let source: HashMap<Txid, Transaction> = blocks
+            .iter()
+            .flat_map(|b| b.txdata.iter())
+            .map(|tx| (tx.txid(), tx.clone()))
+            .collect();
+        let mut colorer = Colorer::new(&source, Network::Prod);
+        for (block, coloring) in blocks.iter().zip(colorings.iter()) {
+            for (tx, tx_coloring) in block.txdata.iter().zip(coloring.iter()) {
+                assert_eq!(tx.txid(), tx_coloring.txid);
+                assert_eq!(colorer.color_transaction(tx).unwrap(), tx_coloring.outputs);
+            }
+        }
+
+        let last = &colorings[19][0];
+        assert_eq!(
+            AssetId::from_str(ISSUER_ASSET_ID).ok(),
+            last.outputs[1].asset_id
+        );
+        assert_eq!(81, last.outputs[1].asset_quantity);
+        // the issued output that was never spent is still unspent
+        let issuance_txid = blocks[0].txdata[1].txid();
+        assert_eq!(
+            50,
+            chain_colorer
+                .get_unspent(&OutPoint::new(issuance_txid, 1))
+                .unwrap()
+                .asset_quantity
+        );
+        // spent outputs are forgotten
+        assert!(chain_colorer
+            .get_unspent(&OutPoint::new(issuance_txid, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_unknown_input() {
+        let blocks = chain(3);
+        let mut colorer = ChainColorer::new(Network::Prod);
+        match color_blocks(&mut colorer, &blocks[1..]) {
+            Err(Error::OutputNotFound(outpoint)) => {
+                assert_eq!(blocks[1].txdata[0].input[0].previous_output, outpoint)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_marker_versions() {
+        let data = hex::decode("4f410200016400").unwrap();
+        let extended = txout(
+            0,
+            tapyrus::blockdata::script::Builder::new()
+                .push_opcode(tapyrus::blockdata::opcodes::all::OP_RETURN)
+                .push_slice(&data)
+                .into_script(),
+        );
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![txout(600, p2pkh(1)), extended],
+        );
+        let block = block(
+            &BlockHash::from_slice(&[0; 32]).unwrap(),
+            0,
+            &[funding, issuance],
+        );
+        let colorings = ChainColorer::new(Network::Prod)
+            .color_block(&block)
+            .unwrap();
+        assert_eq!(None, colorings[1].outputs[0].asset_id);
+
+        let versions = MarkerVersions::new().with_version(0x0200, V1Decoder);
+        let mut colorer = ChainColorer::new(Network::Prod).with_marker_versions(versions);
+        let colorings = colorer.color_block(&block).unwrap();
+        assert_eq!(
+            AssetId::from_str(ISSUER_ASSET_ID).ok(),
+            colorings[1].outputs[0].asset_id
+        );
+    }
+
+    #[test]
+    fn test_missing_input_is_atomic() {
+        let blocks = chain(2);
+        let mut colorer = ChainColorer::new(Network::Prod);
+        colorer.color_block(&blocks[0]).unwrap();
+        let issuance_txid = blocks[0].txdata[1].txid();
+        let known = OutPoint::new(issuance_txid, 0);
+        let unknown = OutPoint::new(issuance_txid, 7);
+        let transfer = tx(
+            &[known, unknown],
+            vec![marker(vec![100], b""), txout(600, p2pkh(3))],
+        );
+        match colorer.color_transaction(&transfer) {
+            Err(Error::OutputNotFound(outpoint)) => assert_eq!(unknown, outpoint),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert_eq!(100, colorer.get_unspent(&known).unwrap().asset_quantity);
+    }
+
+    #[test]
+    fn test_missing_as_uncolored() {
+        let blocks = chain(4);
+        let mut colorer = ChainColorer::new(Network::Prod);
+        let colorings = color_blocks(&mut colorer, &blocks).unwrap();
+        let mut resumed = ChainColorer::new(Network::Prod);
+        color_blocks(&mut resumed, &blocks[..2]).unwrap();
+        let unspent: Vec<(OutPoint, ColoredOutput)> = resumed
+            .colored_unspent()
+            .map(|(outpoint, output)| (*outpoint, output.clone()))
+            .collect();
+        assert!(unspent.iter().all(|(_, output)| output.is_colored()));
+        let mut resumed = ChainColorer::new(Network::Prod).with_missing_as_uncolored();
+        for (outpoint, output) in unspent {
+            resumed.insert_unspent(outpoint, output);
+        }
+        assert_eq!(
+            colorings[2..].to_vec(),
+            color_blocks(&mut resumed, &blocks[2..]).unwrap()
+        );
+
+        // the asset id of an issuance spending a missing P2PKH output comes
+        // from the public key of its scriptSig
+        let key = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let mut issuance = tx(
+            &[OutPoint::new(funding_tx(7, vec![]).txid(), 0)],
+            vec![txout(600, p2pkh(1)), marker(vec![10], b"")],
+        );
+        issuance.input[0].script_sig = Builder::new()
+            .push_slice(&[0x30; 71])
+            .push_key(&key)
+            .into_script();
+        let outputs = resumed.color_transaction(&issuance).unwrap();
+        let script = tapyrus::Address::p2pkh(&key, Network::Prod).script_pubkey();
+        assert_eq!(
+            Some(AssetId::new(&script, Network::Prod)),
+            outputs[0].asset_id
+        );
+
+        issuance.input[0].script_sig = Script::new();
+        match resumed.color_transaction(&issuance) {
+            Err(Error::OutputNotFound(outpoint)) => {
+                assert_eq!(issuance.input[0].previous_output, outpoint)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_sequential() {
+        use crate::openassets::pipeline::{color_blocks_parallel, color_raw_blocks_parallel};
+        use tapyrus::consensus::serialize;
+
+        let blocks = chain(50);
+        let expected = color_blocks(&mut ChainColorer::new(Network::Prod), &blocks).unwrap();
+        for chunk_size in [1, 7, 64] {
+            let mut colorer = ChainColorer::new(Network::Prod);
+            assert_eq!(
+                expected,
+                color_blocks_parallel(&mut colorer, &blocks, chunk_size).unwrap()
+            );
+        }
+        let raw: Vec<Vec<u8>> = blocks.iter().map(serialize).collect();
+        let mut colorer = ChainColorer::new(Network::Prod);
+        assert_eq!(
+            expected,
+            color_raw_blocks_parallel(&mut colorer, &raw, 16).unwrap()
+        );
+    }
+}
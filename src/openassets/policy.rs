@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use tapyrus::Transaction;
+
+use crate::openassets::coloring::MAX_ASSET_QUANTITY;
+use crate::openassets::dust::DustPolicy;
+use crate::openassets::marker_output::TxOutExt;
+
+/// The largest OP_RETURN script relayed by default (MAX_OP_RETURN_RELAY).
+pub const DEFAULT_MAX_MARKER_SIZE: usize = 83;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Rule {
+    /// More than one output is an OP_RETURN output.
+    MultipleOpReturn,
+    /// The marker output script is larger than the relay limit.
+    OversizedMarker,
+    /// An asset quantity exceeds `MAX_ASSET_QUANTITY`.
+    QuantityOverflow,
+    /// An output receiving assets has a value below the dust threshold.
+    DustColoredOutput,
+    /// The marker lists more quantities than there are other outputs.
+    TooManyQuantities,
+    /// The marker metadata is not valid UTF-8.
+    NonUtf8Metadata,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::MultipleOpReturn,
+        Rule::OversizedMarker,
+        Rule::QuantityOverflow,
+        Rule::DustColoredOutput,
+        Rule::TooManyQuantities,
+        Rule::NonUtf8Metadata,
+    ];
+
+    pub fn default_severity(&self) -> Severity {
+        match *self {
+            Rule::NonUtf8Metadata => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Rule::MultipleOpReturn => "multiple_op_return",
+            Rule::OversizedMarker => "oversized_marker",
+            Rule::QuantityOverflow => "quantity_overflow",
+            Rule::DustColoredOutput => "dust_colored_output",
+            Rule::TooManyQuantities => "too_many_quantities",
+            Rule::NonUtf8Metadata => "non_utf8_metadata",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A rule broken by a transaction.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Violation {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// The output the violation is about, if any.
+    pub output: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}]: {}", self.severity, self.rule, self.message)
+    }
+}
+
+/// Flags Open Assets transactions which are valid but may be rejected or
+/// colored differently by other nodes and implementations.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    severities: HashMap<Rule, Severity>,
+    max_marker_size: usize,
+    dust: DustPolicy,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            severities: Rule::ALL
+                .iter()
+                .map(|rule| (*rule, rule.default_severity()))
+                .collect(),
+            max_marker_size: DEFAULT_MAX_MARKER_SIZE,
+            dust: DustPolicy::default(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Policy::default()
+    }
+
+    /// Reports violations of `rule` with `severity`, enabling it if needed.
+    pub fn with_severity(mut self, rule: Rule, severity: Severity) -> Self {
+        self.severities.insert(rule, severity);
+        self
+    }
+
+    /// Stops checking `rule`.
+    pub fn disable(mut self, rule: Rule) -> Self {
+        self.severities.remove(&rule);
+        self
+    }
+
+    pub fn with_max_marker_size(mut self, size: usize) -> Self {
+        self.max_marker_size = size;
+        self
+    }
+
+    pub fn with_dust_policy(mut self, dust: DustPolicy) -> Self {
+        self.dust = dust;
+        self
+    }
+
+    pub fn severity(&self, rule: Rule) -> Option<Severity> {
+        self.severities.get(&rule).cloned()
+    }
+
+    /// Returns the violations of the enabled rules by `tx`.
+    pub fn check(&self, tx: &Transaction) -> Vec<Violation> {
+        let mut violations = vec![];
+
+        let op_returns = tx
+            .output
+            .iter()
+            .filter(|o| o.script_pubkey.is_op_return())
+            .count();
+        if op_returns > 1 {
+            self.report(
+                &mut violations,
+                Rule::MultipleOpReturn,
+                None,
+                format!("the transaction has {} OP_RETURN outputs", op_returns),
+            );
+        }
+
+        let marker = tx
+            .output
+            .iter()
+            .enumerate()
+            .find_map(|(i, o)| o.get_oa_payload_ref().ok().map(|p| (i, p)));
+        let (marker_index, payload) = match marker {
+            Some(marker) => marker,
+            None => return violations,
+        };
+
+        let size = tx.output[marker_index].script_pubkey.len();
+        if size > self.max_marker_size {
+            self.report(
+                &mut violations,
+                Rule::OversizedMarker,
+                Some(marker_index),
+                format!(
+                    "the marker output script is {} bytes, above the relay limit of {} bytes",
+                    size, self.max_marker_size
+                ),
+            );
+        }
+
+        if payload.quantity_count() > tx.output.len() - 1 {
+            self.report(
+                &mut violations,
+                Rule::TooManyQuantities,
+                Some(marker_index),
+                format!(
+                    "the marker output lists {} asset quantities but there are only {} other outputs",
+                    payload.quantity_count(),
+                    tx.output.len() - 1
+                ),
+            );
+        }
+
+        for (i, quantity) in payload.quantities().enumerate() {
+            let index = if i < marker_index { i } else { i + 1 };
+            if quantity > MAX_ASSET_QUANTITY {
+                self.report(
+                    &mut violations,
+                    Rule::QuantityOverflow,
+                    Some(index),
+                    format!(
+                        "asset quantity {} of output {} exceeds the maximum of {}",
+                        quantity, index, MAX_ASSET_QUANTITY
+                    ),
+                );
+            }
+            if let Some(output) = tx.output.get(index)
+                && quantity > 0
+                && self.dust.is_dust(output)
+            {
+                self.report(
+                    &mut violations,
+                    Rule::DustColoredOutput,
+                    Some(index),
+                    format!(
+                        "output {} receives assets but its value {} is below the dust threshold of {}",
+                        index,
+                        output.value,
+                        self.dust.threshold(&output.script_pubkey)
+                    ),
+                );
+            }
+        }
+
+        if std::str::from_utf8(payload.metadata()).is_err() {
+            self.report(
+                &mut violations,
+                Rule::NonUtf8Metadata,
+                Some(marker_index),
+                "the marker metadata is not valid UTF-8".to_string(),
+            );
+        }
+        violations
+    }
+
+    fn report(
+        &self,
+        violations: &mut Vec<Violation>,
+        rule: Rule,
+        output: Option<usize>,
+        message: String,
+    ) {
+        if let Some(severity) = self.severity(rule) {
+            violations.push(Violation {
+                rule,
+                severity,
+                output,
+                message,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::coloring::MAX_ASSET_QUANTITY;
+    use crate::openassets::dust::DustPolicy;
+    use crate::openassets::policy::{Policy, Rule, Severity, Violation};
+    use crate::openassets::test_helpers::*;
+    use tapyrus::{OutPoint, Transaction};
+
+    fn spend(outputs: Vec<tapyrus::TxOut>) -> Transaction {
+        let dummy = funding_tx(0, vec![]);
+        tx(&[OutPoint::new(dummy.txid(), 0)], outputs)
+    }
+
+    fn rules(violations: &[Violation]) -> Vec<Rule> {
+        violations.iter().map(|v| v.rule).collect()
+    }
+
+    #[test]
+    fn test_standard_transaction() {
+        let tx = spend(vec![
+            txout(600, p2pkh(1)),
+            marker(vec![100, 20], b"u=https://cpr.sm/5YgSU1Pg-q"),
+            txout(600, p2pkh(2)),
+        ]);
+        assert!(Policy::new().check(&tx).is_empty());
+
+        // without marker
+        let tx = spend(vec![txout(600, p2pkh(1))]);
+        assert!(Policy::new().check(&tx).is_empty());
+    }
+
+    #[test]
+    fn test_violations() {
+        let tx = spend(vec![
+            txout(100, p2pkh(1)),
+            marker(vec![100, MAX_ASSET_QUANTITY + 1], &[0xff, 0xfe]),
+            txout(600, p2pkh(2)),
+            marker(vec![], b""),
+        ]);
+        let violations = Policy::new().check(&tx);
+        assert_eq!(
+            vec![
+                Rule::MultipleOpReturn,
+                Rule::DustColoredOutput,
+                Rule::QuantityOverflow,
+                Rule::NonUtf8Metadata
+            ],
+            rules(&violations)
+        );
+        assert_eq!(Some(0), violations[1].output);
+        assert_eq!(Some(2), violations[2].output);
+        assert_eq!(Severity::Warning, violations[3].severity);
+        assert_eq!(
+            "error [dust_colored_output]: output 0 receives assets but its value 100 is below the dust threshold of 546",
+            violations[1].to_string()
+        );
+
+        let tx = spend(vec![
+            marker(vec![1, 2, 3], &[b'a'; 80]),
+            txout(600, p2pkh(2)),
+        ]);
+        assert_eq!(
+            vec![Rule::OversizedMarker, Rule::TooManyQuantities],
+            rules(&Policy::new().check(&tx))
+        );
+    }
+
+    #[test]
+    fn test_configuration() {
+        let tx = spend(vec![txout(100, p2pkh(1)), marker(vec![100], &[0xff])]);
+        let policy = Policy::new()
+            .disable(Rule::NonUtf8Metadata)
+            .with_severity(Rule::DustColoredOutput, Severity::Info);
+        let violations = policy.check(&tx);
+        assert_eq!(vec![Rule::DustColoredOutput], rules(&violations));
+        assert_eq!(Severity::Info, violations[0].severity);
+
+        let policy = Policy::new().with_dust_policy(DustPolicy::new(500));
+        assert_eq!(vec![Rule::NonUtf8Metadata], rules(&policy.check(&tx)));
+    }
+}
@@ -0,0 +1,792 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+
+use tapyrus::blockdata::script::Builder;
+use tapyrus::consensus::encode::{self, deserialize, serialize};
+use tapyrus::consensus::{Decodable, Encodable};
+use tapyrus::network::constants::Network;
+use tapyrus::{Address, PublicKey, Script, SigHashType, Transaction, TxOut};
+
+use crate::openassets::cache::{self, OutputColor};
+use crate::openassets::coloring::{color_outputs_with, ColoredOutput};
+use crate::openassets::marker_output::MarkerVersions;
+use crate::openassets::network::NetworkParams;
+use crate::openassets::sign::{
+    multisig_script_sig, parse_multisig, sign_input, SignatureScheme, Signer,
+};
+use crate::openassets::validator::{validate_with, Discrepancy, ExpectedOutput};
+
+/// The magic bytes starting every serialized partially signed transaction.
+pub const MAGIC: [u8; 5] = *b"psbt\xff";
+
+/// The identifier of the proprietary keys carrying the Open Assets annotations.
+pub const PROPRIETARY_PREFIX: &[u8] = b"oa";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+const PSBT_PROPRIETARY: u8 = 0xfc;
+const OA_PREVOUT: u8 = 0x00;
+const OA_COLOR: u8 = 0x01;
+
+#[derive(Debug)]
+pub enum Error {
+    Encode(encode::Error),
+    Sign(crate::openassets::sign::Error),
+    /// The unsigned transaction already has a scriptSig.
+    NonEmptyScriptSig(usize),
+    /// The transaction has no input or output at the index.
+    IndexOutOfRange(usize),
+    /// The previous output or coloring of an input is not known.
+    MissingAnnotation(usize),
+    /// The transactions being combined are different.
+    TransactionMismatch,
+    /// The colorings given for the spent outputs do not match the inputs.
+    InputCountMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// The redeem script of an input does not hash to its previous output.
+    RedeemScriptMismatch(usize),
+    /// An input has fewer signatures than its script requires.
+    NotEnoughSignatures {
+        index: usize,
+        required: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Encode(ref e) => write!(f, "encode error: {}", e),
+            Error::Sign(ref e) => write!(f, "signing error: {}", e),
+            Error::NonEmptyScriptSig(index) => write!(f, "input {} is already signed", index),
+            Error::IndexOutOfRange(index) => write!(f, "index {} out of range", index),
+            Error::MissingAnnotation(index) => {
+                write!(f, "input {} lacks its previous output or coloring", index)
+            }
+            Error::TransactionMismatch => write!(f, "the unsigned transactions differ"),
+            Error::InputCountMismatch { expected, found } => write!(
+                f,
+                "the transaction has {} inputs but {} colorings were given",
+                expected, found
+            ),
+            Error::RedeemScriptMismatch(index) => {
+                write!(f, "the redeem script of input {} does not match", index)
+            }
+            Error::NotEnoughSignatures {
+                index,
+                required,
+                found,
+            } => write!(
+                f,
+                "input {} requires {} signatures but has {}",
+                index, required, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+impl From<crate::openassets::sign::Error> for Error {
+    fn from(e: crate::openassets::sign::Error) -> Self {
+        Error::Sign(e)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Input {
+    /// The output spent by the input.
+    pub prevout: Option<TxOut>,
+    /// The coloring of the spent output.
+    pub color: Option<OutputColor>,
+    pub redeem_script: Option<Script>,
+    /// Signatures with their sighash type, by public key.
+    pub partial_sigs: BTreeMap<PublicKey, Vec<u8>>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Output {
+    /// The coloring the output is intended to receive.
+    pub color: Option<OutputColor>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// An unsigned transaction with the coloring of its inputs and outputs and
+/// the signatures collected so far, passed between signers.
+///
+/// The layout follows BIP 174, with the Open Assets annotations stored under
+/// proprietary keys.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PartiallySignedTransaction {
+    pub unsigned_tx: Transaction,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+}
+
+fn proprietary_key(subtype: u8) -> Vec<u8> {
+    let mut key = vec![PSBT_PROPRIETARY, PROPRIETARY_PREFIX.len() as u8];
+    key.extend_from_slice(PROPRIETARY_PREFIX);
+    key.push(subtype);
+    key
+}
+
+fn encode_color(color: &OutputColor) -> Vec<u8> {
+    let mut data = vec![];
+    color.write_to(&mut data).unwrap();
+    data
+}
+
+fn decode_color(mut data: &[u8]) -> Result<OutputColor, encode::Error> {
+    let color = OutputColor::read_from(&mut data).map_err(|e| match e {
+        cache::Error::Corrupted(s) => encode::Error::ParseFailed(s),
+        _ => encode::Error::ParseFailed("truncated asset annotation"),
+    })?;
+    if !data.is_empty() {
+        return Err(encode::Error::ParseFailed("invalid asset id"));
+    }
+    Ok(color)
+}
+
+/// Returns true if `script` is a P2PKH or colored P2PKH script paying to `key`.
+fn pays_to_key(script: &Script, key: &PublicKey) -> bool {
+    // a colored P2PKH script is the color id and OP_COLOR followed by P2PKH
+    let p2pkh = Address::p2pkh(key, Network::Prod).script_pubkey();
+    script.as_bytes().ends_with(p2pkh.as_bytes())
+}
+
+/// Returns true if `script` is a P2SH or colored P2SH script of `redeem_script`.
+fn pays_to_script(script: &Script, redeem_script: &Script) -> bool {
+    let p2sh = Address::p2sh(redeem_script, Network::Prod).script_pubkey();
+    script.as_bytes().ends_with(p2sh.as_bytes())
+}
+
+impl PartiallySignedTransaction {
+    /// Wraps an unsigned transaction without annotations.
+    pub fn new(unsigned_tx: Transaction) -> Result<Self, Error> {
+        if let Some(index) = unsigned_tx
+            .input
+            .iter()
+            .position(|input| !input.script_sig.is_empty())
+        {
+            return Err(Error::NonEmptyScriptSig(index));
+        }
+        Ok(PartiallySignedTransaction {
+            inputs: vec![Input::default(); unsigned_tx.input.len()],
+            outputs: vec![Output::default(); unsigned_tx.output.len()],
+            unsigned_tx,
+        })
+    }
+
+    /// Records the output spent by input `index` and its coloring.
+    pub fn annotate_input(&mut self, index: usize, prevout: &ColoredOutput) -> Result<(), Error> {
+        let input = self
+            .inputs
+            .get_mut(index)
+            .ok_or(Error::IndexOutOfRange(index))?;
+        input.prevout = Some(TxOut {
+            value: prevout.value,
+            script_pubkey: prevout.script_pubkey.clone(),
+        });
+        input.color = Some(prevout.into());
+        Ok(())
+    }
+
+    pub fn set_redeem_script(&mut self, index: usize, redeem_script: Script) -> Result<(), Error> {
+        let input = self
+            .inputs
+            .get_mut(index)
+            .ok_or(Error::IndexOutOfRange(index))?;
+        input.redeem_script = Some(redeem_script);
+        Ok(())
+    }
+
+    /// Returns the colored outputs spent by the transaction.
+    pub fn colored_inputs(&self) -> Result<Vec<ColoredOutput>, Error> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| match (&input.prevout, &input.color) {
+                (Some(prevout), Some(color)) => Ok(color.to_colored_output(prevout)),
+                _ => Err(Error::MissingAnnotation(index)),
+            })
+            .collect()
+    }
+
+    /// Annotates every output with the coloring computed from the annotated inputs.
+    pub fn annotate_outputs(&mut self, network: Network) -> Result<(), Error> {
+        self.annotate_outputs_with(NetworkParams::tapyrus(network), &MarkerVersions::default())
+    }
+
+    /// Like `annotate_outputs`, with the asset ids of `params` and the marker
+    /// versions of `versions`.
+    pub fn annotate_outputs_with(
+        &mut self,
+        params: &'static NetworkParams,
+        versions: &MarkerVersions,
+    ) -> Result<(), Error> {
+        let inputs = self.colored_inputs()?;
+        let outputs = color_outputs_with(&self.unsigned_tx, &inputs, params, versions);
+        for (output, colored) in self.outputs.iter_mut().zip(outputs.iter()) {
+            output.color = Some(colored.into());
+        }
+        Ok(())
+    }
+
+    /// Checks that the transaction colors its outputs as annotated when it
+    /// spends `inputs`, so that a signer does not have to trust the output
+    /// annotations of the creator.
+    ///
+    /// `inputs` must come from the signer's own view of the chain, not from
+    /// `colored_inputs`: a creator could annotate a colored input as
+    /// uncolored and have the signer burn its assets. Outputs without
+    /// annotation are expected to be uncolored.
+    pub fn verify_coloring(
+        &self,
+        inputs: &[ColoredOutput],
+        network: Network,
+    ) -> Result<Vec<Discrepancy>, Error> {
+        self.verify_coloring_with(
+            inputs,
+            NetworkParams::tapyrus(network),
+            &MarkerVersions::default(),
+        )
+    }
+
+    /// Like `verify_coloring`, with the asset ids of `params` and the marker
+    /// versions of `versions`.
+    pub fn verify_coloring_with(
+        &self,
+        inputs: &[ColoredOutput],
+        params: &'static NetworkParams,
+        versions: &MarkerVersions,
+    ) -> Result<Vec<Discrepancy>, Error> {
+        if inputs.len() != self.inputs.len() {
+            return Err(Error::InputCountMismatch {
+                expected: self.inputs.len(),
+                found: inputs.len(),
+            });
+        }
+        let expected: Vec<ExpectedOutput> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, output)| {
+                let color = output.color.as_ref()?;
+                color
+                    .asset_id
+                    .clone()
+                    .map(|asset_id| ExpectedOutput::new(index, asset_id, color.asset_quantity))
+            })
+            .collect();
+        Ok(validate_with(
+            &self.unsigned_tx,
+            inputs,
+            &expected,
+            params,
+            versions,
+        ))
+    }
+
+    /// Adds the signatures of `signer` to the inputs locked by its key.
+    ///
+    /// Inputs with a redeem script are only signed when their previous output
+    /// pays to it. Returns the number of inputs signed.
+    pub fn sign<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        scheme: SignatureScheme,
+    ) -> Result<usize, Error> {
+        let key = signer.public_key();
+        let mut signed = 0;
+        for index in 0..self.inputs.len() {
+            let input = &self.inputs[index];
+            let script_code = match (&input.redeem_script, &input.prevout) {
+                (Some(redeem_script), prevout) => {
+                    let (_, keys) = parse_multisig(redeem_script)?;
+                    if !keys.contains(&key) {
+                        continue;
+                    }
+                    let prevout = prevout.as_ref().ok_or(Error::MissingAnnotation(index))?;
+                    if !pays_to_script(&prevout.script_pubkey, redeem_script) {
+                        return Err(Error::RedeemScriptMismatch(index));
+                    }
+                    redeem_script.clone()
+                }
+                (None, Some(prevout)) if pays_to_key(&prevout.script_pubkey, &key) => {
+                    prevout.script_pubkey.clone()
+                }
+                _ => continue,
+            };
+            let sig = sign_input(
+                &self.unsigned_tx,
+                index,
+                &script_code,
+                signer,
+                scheme,
+                SigHashType::All,
+            )?;
+            self.inputs[index].partial_sigs.insert(key, sig);
+            signed += 1;
+        }
+        Ok(signed)
+    }
+
+    /// Merges the annotations and signatures of `other`, which must wrap the
+    /// same unsigned transaction.
+    pub fn combine(&mut self, other: PartiallySignedTransaction) -> Result<(), Error> {
+        if self.unsigned_tx != other.unsigned_tx {
+            return Err(Error::TransactionMismatch);
+        }
+        for (input, theirs) in self.inputs.iter_mut().zip(other.inputs) {
+            input.prevout = input.prevout.take().or(theirs.prevout);
+            input.color = input.color.take().or(theirs.color);
+            input.redeem_script = input.redeem_script.take().or(theirs.redeem_script);
+            input.partial_sigs.extend(theirs.partial_sigs);
+            input.unknown.extend(theirs.unknown);
+        }
+        for (output, theirs) in self.outputs.iter_mut().zip(other.outputs) {
+            output.color = output.color.take().or(theirs.color);
+            output.unknown.extend(theirs.unknown);
+        }
+        Ok(())
+    }
+
+    /// Builds the signed transaction from the collected signatures.
+    pub fn finalize(&self) -> Result<Transaction, Error> {
+        let mut tx = self.unsigned_tx.clone();
+        for (index, input) in self.inputs.iter().enumerate() {
+            tx.input[index].script_sig = match input.redeem_script {
+                Some(ref redeem_script) => {
+                    let (required, keys) = parse_multisig(redeem_script)?;
+                    let sigs: Vec<Vec<u8>> = keys
+                        .iter()
+                        .filter_map(|key| input.partial_sigs.get(key).cloned())
+                        .take(required)
+                        .collect();
+                    if sigs.len() < required {
+                        return Err(Error::NotEnoughSignatures {
+                            index,
+                            required,
+                            found: sigs.len(),
+                        });
+                    }
+                    multisig_script_sig(&sigs, redeem_script)
+                }
+                None => match input.partial_sigs.iter().next() {
+                    Some((key, sig)) => Builder::new().push_slice(sig).push_key(key).into_script(),
+                    None => {
+                        return Err(Error::NotEnoughSignatures {
+                            index,
+                            required: 1,
+                            found: 0,
+                        })
+                    }
+                },
+            };
+        }
+        Ok(tx)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialize(self)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        Ok(deserialize(data)?)
+    }
+}
+
+fn encode_pair<S: Write>(mut s: S, key: &[u8], value: &[u8]) -> Result<usize, encode::Error> {
+    let len = key.to_vec().consensus_encode(&mut s)?;
+    Ok(len + value.to_vec().consensus_encode(&mut s)?)
+}
+
+fn encode_unknown<S: Write>(
+    mut s: S,
+    unknown: &BTreeMap<Vec<u8>, Vec<u8>>,
+) -> Result<usize, encode::Error> {
+    let mut len = 0;
+    for (key, value) in unknown.iter() {
+        len += encode_pair(&mut s, key, value)?;
+    }
+    Ok(len)
+}
+
+/// The key-value pairs of a map, in the order they were read.
+type Pairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Reads the key-value pairs of a map up to its separator.
+fn decode_map<D: Read>(mut d: D) -> Result<Pairs, encode::Error> {
+    let mut pairs: Pairs = vec![];
+    loop {
+        let key: Vec<u8> = Decodable::consensus_decode(&mut d)?;
+        if key.is_empty() {
+            return Ok(pairs);
+        }
+        if pairs.iter().any(|(k, _)| *k == key) {
+            return Err(encode::Error::ParseFailed("duplicate key"));
+        }
+        let value: Vec<u8> = Decodable::consensus_decode(&mut d)?;
+        pairs.push((key, value));
+    }
+}
+
+impl Encodable for PartiallySignedTransaction {
+    fn consensus_encode<S: Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        s.write_all(&MAGIC).map_err(encode::Error::Io)?;
+        let mut len = MAGIC.len();
+        len += encode_pair(
+            &mut s,
+            &[PSBT_GLOBAL_UNSIGNED_TX],
+            &serialize(&self.unsigned_tx),
+        )?;
+        len += 0u8.consensus_encode(&mut s)?;
+
+        for input in self.inputs.iter() {
+            for (key, sig) in input.partial_sigs.iter() {
+                let mut k = vec![PSBT_IN_PARTIAL_SIG];
+                k.extend_from_slice(&key.to_bytes());
+                len += encode_pair(&mut s, &k, sig)?;
+            }
+            if let Some(ref redeem_script) = input.redeem_script {
+                len += encode_pair(&mut s, &[PSBT_IN_REDEEM_SCRIPT], redeem_script.as_bytes())?;
+            }
+            if let Some(ref prevout) = input.prevout {
+                len += encode_pair(&mut s, &proprietary_key(OA_PREVOUT), &serialize(prevout))?;
+            }
+            if let Some(ref color) = input.color {
+                len += encode_pair(&mut s, &proprietary_key(OA_COLOR), &encode_color(color))?;
+            }
+            len += encode_unknown(&mut s, &input.unknown)?;
+            len += 0u8.consensus_encode(&mut s)?;
+        }
+
+        for output in self.outputs.iter() {
+            if let Some(ref color) = output.color {
+                len += encode_pair(&mut s, &proprietary_key(OA_COLOR), &encode_color(color))?;
+            }
+            len += encode_unknown(&mut s, &output.unknown)?;
+            len += 0u8.consensus_encode(&mut s)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for PartiallySignedTransaction {
+    fn consensus_decode<D: Read>(mut d: D) -> Result<Self, encode::Error> {
+        let mut magic = [0u8; 5];
+        d.read_exact(&mut magic).map_err(encode::Error::Io)?;
+        if magic != MAGIC {
+            return Err(encode::Error::ParseFailed("invalid magic"));
+        }
+
+        let mut unsigned_tx = None;
+        for (key, value) in decode_map(&mut d)? {
+            if key == [PSBT_GLOBAL_UNSIGNED_TX] {
+                unsigned_tx = Some(deserialize::<Transaction>(&value)?);
+            }
+        }
+        let unsigned_tx =
+            unsigned_tx.ok_or(encode::Error::ParseFailed("missing unsigned transaction"))?;
+        let mut psbt = PartiallySignedTransaction::new(unsigned_tx)
+            .map_err(|_| encode::Error::ParseFailed("signed transaction"))?;
+
+        for input in psbt.inputs.iter_mut() {
+            for (key, value) in decode_map(&mut d)? {
+                if key.first() == Some(&PSBT_IN_PARTIAL_SIG) {
+                    let pubkey = PublicKey::from_slice(&key[1..])
+                        .map_err(|_| encode::Error::ParseFailed("invalid public key"))?;
+                    input.partial_sigs.insert(pubkey, value);
+                } else if key == [PSBT_IN_REDEEM_SCRIPT] {
+                    input.redeem_script = Some(Script::from(value));
+                } else if key == proprietary_key(OA_PREVOUT) {
+                    input.prevout = Some(deserialize(&value)?);
+                } else if key == proprietary_key(OA_COLOR) {
+                    input.color = Some(decode_color(&value)?);
+                } else {
+                    input.unknown.insert(key, value);
+                }
+            }
+        }
+
+        for output in psbt.outputs.iter_mut() {
+            for (key, value) in decode_map(&mut d)? {
+                if key == proprietary_key(OA_COLOR) {
+                    output.color = Some(decode_color(&value)?);
+                } else {
+                    output.unknown.insert(key, value);
+                }
+            }
+        }
+        Ok(psbt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::cache::OutputColor;
+    use crate::openassets::coloring::{ColoredOutput, OutputType};
+    use crate::openassets::network::{self, NetworkParams};
+    use crate::openassets::psbt::{decode_color, encode_color, Error, PartiallySignedTransaction};
+    use crate::openassets::sign::{
+        multisig_redeem_script, verify_signature, SignatureScheme, Signer,
+    };
+    use crate::openassets::test_helpers::*;
+    use crate::openassets::validator::Discrepancy;
+    use tapyrus::network::constants::Network;
+    use tapyrus::secp256k1::SecretKey;
+    use tapyrus::{Address, OutPoint, PrivateKey, PublicKey, Script};
+
+    fn key(n: u8) -> PrivateKey {
+        PrivateKey {
+            compressed: true,
+            network: Network::Prod,
+            key: SecretKey::from_slice(&[n; 32]).unwrap(),
+        }
+    }
+
+    fn pubkey(key: &PrivateKey) -> PublicKey {
+        Signer::public_key(key)
+    }
+
+    fn asset(n: u8) -> AssetId {
+        AssetId::new(&p2pkh(n), Network::Prod)
+    }
+
+    /// Transfers 30 units held by a P2PKH key and 20 units held by a 2-of-2 multisig.
+    fn fixture() -> (PartiallySignedTransaction, [PrivateKey; 3], Script) {
+        let keys = [key(1), key(2), key(3)];
+        let owner_script = Address::p2pkh(&pubkey(&keys[0]), Network::Prod).script_pubkey();
+        let redeem_script = multisig_redeem_script(2, &[pubkey(&keys[1]), pubkey(&keys[2])]);
+        let multisig_script = Address::p2sh(&redeem_script, Network::Prod).script_pubkey();
+
+        let funding = funding_tx(0, vec![]);
+        let unsigned = tx(
+            &[
+                OutPoint::new(funding.txid(), 0),
+                OutPoint::new(funding.txid(), 1),
+            ],
+            vec![
+                marker(vec![50], b""),
+                txout(600, p2pkh(9)),
+                txout(1000, p2pkh(8)),
+            ],
+        );
+        let mut psbt = PartiallySignedTransaction::new(unsigned).unwrap();
+        for (index, (script, quantity)) in [(owner_script, 30), (multisig_script, 20)]
+            .into_iter()
+            .enumerate()
+        {
+            psbt.annotate_input(
+                index,
+                &ColoredOutput {
+                    value: 600,
+                    script_pubkey: script,
+                    asset_id: Some(asset(7)),
+                    asset_quantity: quantity,
+                    output_type: OutputType::Transfer,
+                },
+            )
+            .unwrap();
+        }
+        psbt.set_redeem_script(1, redeem_script.clone()).unwrap();
+        psbt.annotate_outputs(Network::Prod).unwrap();
+        (psbt, keys, redeem_script)
+    }
+
+    #[test]
+    fn test_color_encoding() {
+        let custom = network::register(NetworkParams {
+            name: "test-psbt-color",
+            network_id: Some(9_000_004),
+            p2pkh_prefix: 70,
+            p2sh_prefix: 71,
+            cp2pkh_prefix: Some(72),
+            cp2sh_prefix: Some(73),
+            asset_id_version: 74,
+            ..network::TAPYRUS_DEV
+        })
+        .unwrap();
+        for params in [&network::TAPYRUS_PROD, &network::BITCOIN, custom] {
+            let color = OutputColor {
+                asset_id: Some(AssetId::with_params(&p2pkh(1), params)),
+                asset_quantity: 10,
+                output_type: OutputType::Transfer,
+            };
+            let decoded = decode_color(&encode_color(&color)).unwrap();
+            assert_eq!(color, decoded);
+            assert_eq!(params, decoded.asset_id.unwrap().params());
+        }
+        let uncolored = OutputColor {
+            asset_id: None,
+            asset_quantity: 0,
+            output_type: OutputType::Uncolored,
+        };
+        assert_eq!(uncolored, decode_color(&encode_color(&uncolored)).unwrap());
+
+        let mut data = encode_color(&OutputColor {
+            asset_id: Some(AssetId::with_params(&p2pkh(1), custom)),
+            ..uncolored.clone()
+        });
+        assert!(decode_color(&data[..data.len() - 1]).is_err());
+        // an unknown network name
+        data[11] = b'x';
+        assert!(decode_color(&data).is_err());
+    }
+
+    #[test]
+    fn test_offline_signing() {
+        let (psbt, keys, redeem_script) = fixture();
+        assert_eq!(
+            Some(50),
+            psbt.outputs[1].color.as_ref().map(|c| c.asset_quantity)
+        );
+        assert_eq!(
+            Some(asset(7)),
+            psbt.outputs[1].color.clone().unwrap().asset_id
+        );
+
+        // each party signs its own copy after checking the coloring
+        let mut copies = vec![];
+        for (signer, scheme, expected) in [
+            (&keys[0], SignatureScheme::Ecdsa, 1),
+            (&keys[1], SignatureScheme::Schnorr, 1),
+            (&keys[2], SignatureScheme::Schnorr, 1),
+        ] {
+            let mut copy = PartiallySignedTransaction::from_bytes(&psbt.to_bytes()).unwrap();
+            assert_eq!(psbt, copy);
+            let inputs = psbt.colored_inputs().unwrap();
+            assert!(copy
+                .verify_coloring(&inputs, Network::Prod)
+                .unwrap()
+                .is_empty());
+            assert_eq!(expected, copy.sign(signer, scheme).unwrap());
+            copies.push(copy);
+        }
+        // a single signature does not satisfy the multisig input
+        match copies[0].finalize() {
+            Err(Error::NotEnoughSignatures {
+                index: 1,
+                required: 2,
+                found: 0,
+            }) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        let mut combined = psbt.clone();
+        for copy in copies {
+            combined.combine(copy).unwrap();
+        }
+        let combined = PartiallySignedTransaction::from_bytes(&combined.to_bytes()).unwrap();
+        assert_eq!(2, combined.inputs[1].partial_sigs.len());
+        let tx = combined.finalize().unwrap();
+        assert_eq!(psbt.unsigned_tx.txid(), {
+            let mut stripped = tx.clone();
+            stripped
+                .input
+                .iter_mut()
+                .for_each(|i| i.script_sig = Script::new());
+            stripped.txid()
+        });
+        for (index, script_code, key) in [
+            (
+                0,
+                combined.inputs[0].prevout.clone().unwrap().script_pubkey,
+                pubkey(&keys[0]),
+            ),
+            (1, redeem_script.clone(), pubkey(&keys[1])),
+            (1, redeem_script.clone(), pubkey(&keys[2])),
+        ] {
+            let sig = &combined.inputs[index].partial_sigs[&key];
+            assert!(verify_signature(&tx, index, &script_code, sig, &key).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_tampered_annotations() {
+        let (mut psbt, keys, _) = fixture();
+        // the creator claims the output receives more than the inputs hold
+        psbt.outputs[1].color.as_mut().unwrap().asset_quantity = 60;
+        let inputs = psbt.colored_inputs().unwrap();
+        let discrepancies = psbt.verify_coloring(&inputs, Network::Prod).unwrap();
+        assert_eq!(
+            vec![Discrepancy::QuantityMismatch {
+                index: 1,
+                expected: 60,
+                actual: 50
+            }],
+            discrepancies
+        );
+
+        let mut other = fixture().0;
+        other.unsigned_tx.lock_time = 1;
+        match psbt.combine(other) {
+            Err(Error::TransactionMismatch) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        match psbt.verify_coloring(&inputs[1..], Network::Prod) {
+            Err(Error::InputCountMismatch {
+                expected: 2,
+                found: 1,
+            }) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        psbt.inputs[0].color = None;
+        match psbt.colored_inputs() {
+            Err(Error::MissingAnnotation(0)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        // signing does not depend on the coloring
+        assert_eq!(1, psbt.sign(&keys[0], SignatureScheme::Ecdsa).unwrap());
+
+        // a redeem script the previous output does not pay to is not signed
+        psbt.set_redeem_script(0, psbt.inputs[1].redeem_script.clone().unwrap())
+            .unwrap();
+        match psbt.sign(&keys[1], SignatureScheme::Ecdsa) {
+            Err(Error::RedeemScriptMismatch(0)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_uncolored_input_annotation() {
+        let (psbt, _, _) = fixture();
+        let spent = psbt.colored_inputs().unwrap()[0].clone();
+        let unsigned = tx(
+            &[psbt.unsigned_tx.input[0].previous_output],
+            vec![txout(600, p2pkh(9))],
+        );
+        let mut tampered = PartiallySignedTransaction::new(unsigned).unwrap();
+        // the creator claims the colored input is uncolored
+        let prevout = txout(spent.value, spent.script_pubkey.clone());
+        tampered
+            .annotate_input(0, &ColoredOutput::uncolored(&prevout))
+            .unwrap();
+        tampered.annotate_outputs(Network::Prod).unwrap();
+        let claimed = tampered.colored_inputs().unwrap();
+        assert!(tampered
+            .verify_coloring(&claimed, Network::Prod)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec![Discrepancy::Burn {
+                asset_id: asset(7),
+                quantity: 30
+            }],
+            tampered.verify_coloring(&[spent], Network::Prod).unwrap()
+        );
+    }
+}
@@ -0,0 +1,582 @@
+//! A JSON-RPC interface with the methods and response shapes of openassets-ruby.
+//!
+//! The server holds no keys and is not connected to a node, so the methods
+//! building transactions only support the `unsigned` mode and return the hex
+//! of the unsigned transaction.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use tapyrus::consensus::serialize;
+use tapyrus::util::base58;
+
+use crate::openassets::address::Address;
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::builder::{self, TransactionBuilder, UnsignedTransaction, Utxo};
+use crate::openassets::server::Index;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The code of errors raised by the methods themselves.
+pub const APPLICATION_ERROR: i64 = -1;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: &str) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn invalid_params(message: &str) -> Self {
+        RpcError::new(INVALID_PARAMS, message)
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<builder::Error> for RpcError {
+    fn from(e: builder::Error) -> Self {
+        RpcError::new(APPLICATION_ERROR, &e.to_string())
+    }
+}
+
+/// Answers a JSON-RPC 1.0 or 2.0 request.
+pub fn handle(index: &Index, body: &[u8]) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(_) => {
+            return envelope(
+                false,
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, "parse error")),
+            )
+        }
+    };
+    let result = match request["method"].as_str() {
+        Some(method) => call(index, method, Params(&request["params"])),
+        None => Err(RpcError::new(INVALID_REQUEST, "invalid request")),
+    };
+    envelope(request["jsonrpc"] == "2.0", request["id"].clone(), result)
+}
+
+fn envelope(version2: bool, id: Value, result: Result<Value, RpcError>) -> Value {
+    let error = |e: RpcError| json!({ "code": e.code, "message": e.message });
+    match (version2, result) {
+        (true, Ok(result)) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        (true, Err(e)) => json!({ "jsonrpc": "2.0", "error": error(e), "id": id }),
+        (false, Ok(result)) => json!({ "result": result, "error": null, "id": id }),
+        (false, Err(e)) => json!({ "result": null, "error": error(e), "id": id }),
+    }
+}
+
+pub fn call(index: &Index, method: &str, params: Params) -> Result<Value, RpcError> {
+    match method {
+        "list_unspent" => list_unspent(index, params),
+        "get_balance" => get_balance(index, params),
+        "issue_asset" => issue_asset(index, params),
+        "send_asset" => send_asset(index, params),
+        "send_assets" => send_assets(index, params),
+        "burn_asset" => burn_asset(index, params),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, "method not found")),
+    }
+}
+
+/// The parameters of a call, given by position or by name.
+#[derive(Debug, Clone, Copy)]
+pub struct Params<'a>(&'a Value);
+
+impl<'a> Params<'a> {
+    fn get(&self, position: usize, name: &str) -> Option<&'a Value> {
+        let value = match self.0 {
+            Value::Array(values) => values.get(position),
+            Value::Object(values) => values.get(name),
+            _ => None,
+        };
+        value.filter(|v| !v.is_null())
+    }
+
+    fn required(&self, position: usize, name: &str) -> Result<&'a Value, RpcError> {
+        self.get(position, name)
+            .ok_or_else(|| RpcError::invalid_params(&format!("missing parameter {}", name)))
+    }
+
+    fn string(&self, position: usize, name: &str) -> Result<&'a str, RpcError> {
+        self.required(position, name)?
+            .as_str()
+            .ok_or_else(|| RpcError::invalid_params(&format!("{} must be a string", name)))
+    }
+
+    /// An amount given as a number or a decimal string, as openassets-ruby accepts both.
+    fn quantity(value: &Value, name: &str) -> Result<u64, RpcError> {
+        match value {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => u64::from_str(s).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params(&format!("invalid {}", name)))
+    }
+
+    fn address(&self, index: &Index, position: usize, name: &str) -> Result<Address, RpcError> {
+        parse_address(index, self.string(position, name)?)
+    }
+}
+
+/// Parses an Open Assets or plain address of the network of `index`.
+fn parse_address(index: &Index, s: &str) -> Result<Address, RpcError> {
+    let params = index.params();
+    let address = Address::from_str_with_params(s, params).or_else(|_| {
+        // a plain address is an Open Assets address without the namespace
+        let mut data = vec![params.oa_namespace];
+        data.extend(base58::from_check(s)?);
+        Address::from_str_with_params(&base58::check_encode_slice(&data), params)
+    });
+    address.map_err(|_| RpcError::invalid_params(&format!("invalid address {}", s)))
+}
+
+/// Parses an asset id of the network of `index`.
+fn parse_asset_id(index: &Index, s: &str) -> Result<AssetId, RpcError> {
+    AssetId::from_str_with_params(s, index.params())
+        .map_err(|_| RpcError::invalid_params(&format!("invalid asset id {}", s)))
+}
+
+/// Formats tapyrus as a decimal amount of TPC, like the BTC amounts of openassets-ruby.
+pub fn format_amount(value: u64) -> String {
+    format!("{}.{:08}", value / 100_000_000, value % 100_000_000)
+}
+
+fn unspent_of(index: &Index, address: &Address) -> Vec<Utxo> {
+    match address.to_btc_addr() {
+        Ok(address) => index.unspent_outputs(&address.script_pubkey()),
+        Err(_) => vec![],
+    }
+}
+
+fn list_unspent(index: &Index, params: Params) -> Result<Value, RpcError> {
+    let addresses = params
+        .required(0, "oa_address_list")?
+        .as_array()
+        .filter(|a| !a.is_empty())
+        .ok_or_else(|| RpcError::invalid_params("oa_address_list must be a non-empty array"))?;
+    let mut result = vec![];
+    for address in addresses {
+        let address = parse_address(index, address.as_str().unwrap_or_default())?;
+        let btc_address = address
+            .to_btc_addr()
+            .map_err(|e| RpcError::invalid_params(&e.to_string()))?;
+        for utxo in unspent_of(index, &address) {
+            let output = &utxo.output;
+            let quantity = output.asset_quantity.to_string();
+            result.push(json!({
+                "txid": utxo.outpoint.txid.to_string(),
+                "vout": utxo.outpoint.vout,
+                "address": btc_address.to_string(),
+                "oa_address": address.to_string(),
+                "script": hex::encode(output.script_pubkey.as_bytes()),
+                "amount": format_amount(output.value),
+                "confirmations": index.confirmations(&utxo.outpoint.txid).unwrap_or(0),
+                "asset_id": output.asset_id.as_ref().map(|id| id.to_string()),
+                "account": null,
+                "asset_quantity": quantity,
+                "asset_amount": quantity,
+                "asset_definition_url": output
+                    .asset_id
+                    .as_ref()
+                    .map(|id| index.asset_definition_url(id)),
+                "proof_of_authenticity": false,
+            }));
+        }
+    }
+    Ok(Value::Array(result))
+}
+
+fn get_balance(index: &Index, params: Params) -> Result<Value, RpcError> {
+    let address = params.address(index, 0, "address")?;
+    let btc_address = address
+        .to_btc_addr()
+        .map_err(|e| RpcError::invalid_params(&e.to_string()))?;
+    let unspent = unspent_of(index, &address);
+    let mut assets: Vec<(AssetId, u64)> = vec![];
+    for utxo in unspent.iter() {
+        if let Some(ref asset_id) = utxo.output.asset_id {
+            match assets.iter_mut().find(|(id, _)| id == asset_id) {
+                Some((_, quantity)) => *quantity += utxo.output.asset_quantity,
+                None => assets.push((asset_id.clone(), utxo.output.asset_quantity)),
+            }
+        }
+    }
+    let value: u64 = unspent.iter().map(|u| u.output.value).sum();
+    Ok(json!([{
+        "address": btc_address.to_string(),
+        "oa_address": address.to_string(),
+        "value": format_amount(value),
+        "assets": assets.iter().map(|(asset_id, quantity)| json!({
+            "asset_id": asset_id.to_string(),
+            "quantity": quantity.to_string(),
+            "amount": quantity.to_string(),
+            "asset_definition_url": index.asset_definition_url(asset_id),
+            "proof_of_authenticity": false,
+        })).collect::<Vec<Value>>(),
+        "account": null,
+    }]))
+}
+
+/// Returns the builder for a call with the given `fees`, `mode` and
+/// `output_qty` parameters.
+///
+/// An omitted mode means `broadcast` in openassets-ruby, so it is rejected
+/// like an explicit `broadcast`.
+fn transaction_builder(
+    index: &Index,
+    fees: Option<&Value>,
+    mode: Option<&Value>,
+    output_qty: Option<&Value>,
+) -> Result<TransactionBuilder, RpcError> {
+    match mode.map_or(Some("broadcast"), Value::as_str) {
+        Some("unsigned") => {}
+        Some("broadcast") | Some("signed") => {
+            return Err(RpcError::new(
+                APPLICATION_ERROR,
+                "the server holds no keys, only the unsigned mode is supported",
+            ))
+        }
+        _ => return Err(RpcError::invalid_params("invalid mode")),
+    }
+    if let Some(output_qty) = output_qty
+        && Params::quantity(output_qty, "output_qty")? != 1
+    {
+        return Err(RpcError::invalid_params("output_qty must be 1"));
+    }
+    let builder = TransactionBuilder::with_params(index.params());
+    Ok(match fees {
+        Some(fees) => builder.with_fixed_fee(Params::quantity(fees, "fees")?),
+        None => builder,
+    })
+}
+
+fn script_of(address: &Address) -> Result<tapyrus::Script, RpcError> {
+    address
+        .to_btc_addr()
+        .map(|a| a.script_pubkey())
+        .map_err(|e| RpcError::invalid_params(&e.to_string()))
+}
+
+fn to_hex(built: UnsignedTransaction) -> Value {
+    json!(hex::encode(serialize(&built.tx)))
+}
+
+/// `issue_asset(from, amount, metadata = nil, to = nil, fees = nil, mode = 'broadcast', output_qty = 1)`
+fn issue_asset(index: &Index, params: Params) -> Result<Value, RpcError> {
+    let from = params.address(index, 0, "from")?;
+    let amount = Params::quantity(params.required(1, "amount")?, "amount")?;
+    let metadata = match params.get(2, "metadata") {
+        Some(metadata) => metadata
+            .as_str()
+            .ok_or_else(|| RpcError::invalid_params("metadata must be a string"))?,
+        None => "",
+    };
+    let to = match params.get(3, "to") {
+        Some(_) => params.address(index, 3, "to")?,
+        None => from.clone(),
+    };
+    let builder = transaction_builder(
+        index,
+        params.get(4, "fees"),
+        params.get(5, "mode"),
+        params.get(6, "output_qty"),
+    )?;
+    let built = builder.issue(
+        &unspent_of(index, &from),
+        &script_of(&to)?,
+        amount,
+        metadata.as_bytes().to_vec(),
+        &script_of(&from)?,
+    )?;
+    Ok(to_hex(built))
+}
+
+/// `send_asset(from, asset_id, amount, to, fees = nil, mode = 'broadcast', output_qty = 1)`
+fn send_asset(index: &Index, params: Params) -> Result<Value, RpcError> {
+    let from = params.address(index, 0, "from")?;
+    let asset_id = parse_asset_id(index, params.string(1, "asset_id")?)?;
+    let amount = Params::quantity(params.required(2, "amount")?, "amount")?;
+    let to = params.address(index, 3, "to")?;
+    let builder = transaction_builder(
+        index,
+        params.get(4, "fees"),
+        params.get(5, "mode"),
+        params.get(6, "output_qty"),
+    )?;
+    let built = builder.transfer(
+        &unspent_of(index, &from),
+        &asset_id,
+        &[(script_of(&to)?, amount)],
+        &script_of(&from)?,
+    )?;
+    Ok(to_hex(built))
+}
+
+/// `send_assets(from, send_asset_params, fees = nil, mode = 'broadcast')`, where
+/// each parameter is an object with `asset_id`, `amount` and `to`, or an array
+/// of them in that order.
+fn send_assets(index: &Index, params: Params) -> Result<Value, RpcError> {
+    let from = params.address(index, 0, "from")?;
+    let mut transfers = vec![];
+    for param in params
+        .required(1, "send_asset_params")?
+        .as_array()
+        .ok_or_else(|| RpcError::invalid_params("send_asset_params must be an array"))?
+    {
+        let param = Params(param);
+        transfers.push((
+            parse_asset_id(index, param.string(0, "asset_id")?)?,
+            script_of(&param.address(index, 2, "to")?)?,
+            Params::quantity(param.required(1, "amount")?, "amount")?,
+        ));
+    }
+    let builder = transaction_builder(index, params.get(2, "fees"), params.get(3, "mode"), None)?;
+    let built =
+        builder.transfer_assets(&unspent_of(index, &from), &transfers, &script_of(&from)?)?;
+    Ok(to_hex(built))
+}
+
+/// `burn_asset(oa_address, asset_id, fees = nil, mode = 'broadcast')`
+fn burn_asset(index: &Index, params: Params) -> Result<Value, RpcError> {
+    let from = params.address(index, 0, "oa_address")?;
+    let asset_id = parse_asset_id(index, params.string(1, "asset_id")?)?;
+    let builder = transaction_builder(index, params.get(2, "fees"), params.get(3, "mode"), None)?;
+    let built = builder.burn(&unspent_of(index, &from), &asset_id, &script_of(&from)?)?;
+    Ok(to_hex(built))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::coloring::output_roles;
+    use crate::openassets::network::{NetworkParams, BITCOIN, TAPYRUS_DEV, TAPYRUS_PROD};
+    use crate::openassets::rpc::{
+        format_amount, handle, APPLICATION_ERROR, INVALID_PARAMS, METHOD_NOT_FOUND, PARSE_ERROR,
+    };
+    use crate::openassets::server::Index;
+    use crate::openassets::test_helpers::*;
+    use serde_json::{json, Value};
+    use tapyrus::consensus::deserialize;
+    use tapyrus::{BlockHash, OutPoint, Transaction};
+
+    /// The Tapyrus and Open Assets addresses of `ISSUER_SCRIPT`.
+    const ISSUER: &str = "16UwLL9Risc3QfPqBUvKofHmBQ7wMtjvM";
+    const ISSUER_OA: &str = "akB4NBW9UuCmHuepksob6yfZs6naHtRCPNy";
+    const RECIPIENT_OA: &str = "anQin2TDYaubr6M5MQM8kNXMitHc2hsmfGc";
+
+    fn index() -> Index {
+        index_with(&TAPYRUS_PROD)
+    }
+
+    /// Issues 100 units to the issuer, who keeps 50_000 uncolored tapyrus.
+    fn index_with(params: &'static NetworkParams) -> Index {
+        let funding = funding_tx(
+            1,
+            vec![
+                txout(10_000, script(ISSUER_SCRIPT)),
+                txout(50_000, script(ISSUER_SCRIPT)),
+            ],
+        );
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![
+                txout(600, script(ISSUER_SCRIPT)),
+                marker(vec![100], b"u=https://example.com/asset.json"),
+            ],
+        );
+        let mut index = Index::with_params(params);
+        index
+            .add_block(&block(&BlockHash::default(), 0, &[funding, issuance]))
+            .unwrap();
+        index
+    }
+
+    fn call(index: &Index, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+        handle(index, request.to_string().as_bytes())
+    }
+
+    fn quantities(response: &Value) -> Vec<u64> {
+        let bytes = hex::decode(response["result"].as_str().unwrap()).unwrap();
+        let tx: Transaction = deserialize(&bytes).unwrap();
+        let (_, payload, _) = output_roles(&tx).unwrap();
+        payload.quantities
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!("0.00000600", format_amount(600));
+        assert_eq!("21.00000000", format_amount(2_100_000_000));
+    }
+
+    #[test]
+    fn test_queries() {
+        let index = index();
+        let unspent = call(&index, "list_unspent", json!([[ISSUER_OA]]));
+        let unspent = unspent["result"].as_array().unwrap();
+        assert_eq!(2, unspent.len());
+        let colored = unspent
+            .iter()
+            .find(|u| u["asset_id"] != Value::Null)
+            .unwrap();
+        assert_eq!(0, colored["vout"]);
+        assert_eq!(ISSUER, colored["address"]);
+        assert_eq!(ISSUER_OA, colored["oa_address"]);
+        assert_eq!(ISSUER_SCRIPT, colored["script"]);
+        assert_eq!("0.00000600", colored["amount"]);
+        assert_eq!(1, colored["confirmations"]);
+        assert_eq!(ISSUER_ASSET_ID, colored["asset_id"]);
+        assert_eq!("100", colored["asset_quantity"]);
+        assert_eq!(
+            "https://example.com/asset.json",
+            colored["asset_definition_url"]
+        );
+
+        let balance = call(&index, "get_balance", json!({ "address": ISSUER_OA }));
+        let balance = &balance["result"][0];
+        assert_eq!("0.00050600", balance["value"]);
+        assert_eq!(ISSUER_ASSET_ID, balance["assets"][0]["asset_id"]);
+        assert_eq!("100", balance["assets"][0]["quantity"]);
+    }
+
+    #[test]
+    fn test_transactions() {
+        let index = index();
+        let issued = call(
+            &index,
+            "issue_asset",
+            json!([
+                ISSUER_OA,
+                500,
+                "u=https://example.com",
+                null,
+                1000,
+                "unsigned"
+            ]),
+        );
+        assert_eq!(vec![500], quantities(&issued));
+
+        let sent = call(
+            &index,
+            "send_asset",
+            json!([
+                ISSUER_OA,
+                ISSUER_ASSET_ID,
+                30,
+                RECIPIENT_OA,
+                1000,
+                "unsigned"
+            ]),
+        );
+        assert_eq!(vec![30, 70], quantities(&sent));
+
+        let params = json!([{ "asset_id": ISSUER_ASSET_ID, "amount": "40", "to": RECIPIENT_OA }]);
+        let sent = call(
+            &index,
+            "send_assets",
+            json!({ "from": ISSUER_OA, "send_asset_params": params, "mode": "unsigned" }),
+        );
+        assert_eq!(vec![40, 60], quantities(&sent));
+        let params = json!([[ISSUER_ASSET_ID, "40", RECIPIENT_OA]]);
+        let positional = call(
+            &index,
+            "send_assets",
+            json!([ISSUER_OA, params, null, "unsigned"]),
+        );
+        assert_eq!(sent["result"], positional["result"]);
+
+        let burnt = call(
+            &index,
+            "burn_asset",
+            json!([ISSUER_OA, ISSUER_ASSET_ID, 1000, "unsigned"]),
+        );
+        let bytes = hex::decode(burnt["result"].as_str().unwrap()).unwrap();
+        let tx: Transaction = deserialize(&bytes).unwrap();
+        assert!(output_roles(&tx).is_none());
+    }
+
+    #[test]
+    fn test_bitcoin_index() {
+        let index = index_with(&BITCOIN);
+        // Bitcoin addresses and asset ids encode like those of Tapyrus
+        let balance = call(&index, "get_balance", json!([ISSUER]));
+        assert_eq!(
+            ISSUER_ASSET_ID,
+            balance["result"][0]["assets"][0]["asset_id"]
+        );
+        let sent = call(
+            &index,
+            "send_asset",
+            json!([
+                ISSUER_OA,
+                ISSUER_ASSET_ID,
+                30,
+                RECIPIENT_OA,
+                null,
+                "unsigned"
+            ]),
+        );
+        assert_eq!(vec![30, 70], quantities(&sent));
+
+        // addresses of other networks are rejected
+        let dev = call(
+            &Index::with_params(&TAPYRUS_DEV),
+            "get_balance",
+            json!([ISSUER_OA]),
+        );
+        assert_eq!(INVALID_PARAMS, dev["error"]["code"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let index = index();
+        let broadcast = call(
+            &index,
+            "send_asset",
+            json!([ISSUER_OA, ISSUER_ASSET_ID, 30, RECIPIENT_OA]),
+        );
+        assert_eq!(APPLICATION_ERROR, broadcast["error"]["code"]);
+        assert_eq!(1, broadcast["id"]);
+
+        let too_much = call(
+            &index,
+            "send_asset",
+            json!([
+                ISSUER_OA,
+                ISSUER_ASSET_ID,
+                101,
+                RECIPIENT_OA,
+                null,
+                "unsigned"
+            ]),
+        );
+        assert_eq!(APPLICATION_ERROR, too_much["error"]["code"]);
+
+        let missing = call(&index, "get_balance", json!([]));
+        assert_eq!(INVALID_PARAMS, missing["error"]["code"]);
+        let unknown = call(&index, "get_info", json!([]));
+        assert_eq!(METHOD_NOT_FOUND, unknown["error"]["code"]);
+
+        let parse = handle(&index, b"{");
+        assert_eq!(PARSE_ERROR, parse["error"]["code"]);
+        assert_eq!(Value::Null, parse["result"]);
+    }
+}
@@ -0,0 +1,530 @@
+//! A minimal HTTP server answering coloring and balance queries with JSON.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tapyrus::consensus::deserialize;
+use tapyrus::hashes::hex::FromHex;
+use tapyrus::network::constants::Network;
+use tapyrus::{Block, Script, Transaction, Txid};
+
+use crate::openassets::address::Address;
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::builder::Utxo;
+use crate::openassets::coloring::{self, find_marker, output_roles, ColoredOutput, OutputType};
+use crate::openassets::network::NetworkParams;
+use crate::openassets::pipeline::ChainColorer;
+use crate::openassets::rpc;
+
+/// The largest request body accepted, in bytes.
+pub const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// The default number of connections served at the same time.
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// The default time a connection may wait for each read or write.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The pause after a failed accept.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Coloring(coloring::Error),
+    /// The request is not a valid HTTP request.
+    BadRequest(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Coloring(ref e) => write!(f, "coloring error: {}", e),
+            Error::BadRequest(what) => write!(f, "bad request: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<coloring::Error> for Error {
+    fn from(e: coloring::Error) -> Self {
+        Error::Coloring(e)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Response {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+/// The issuances of an asset.
+#[derive(Debug, Clone)]
+struct AssetInfo {
+    asset_id: AssetId,
+    issued: u64,
+    issuances: Vec<Txid>,
+    metadata: Value,
+}
+
+/// The coloring of every transaction of a chain, with the issued assets.
+pub struct Index {
+    colorer: ChainColorer,
+    outputs: HashMap<Txid, Vec<ColoredOutput>>,
+    assets: HashMap<String, AssetInfo>,
+    /// The height of the block of each transaction, counted from the first block added.
+    heights: HashMap<Txid, u64>,
+    height: u64,
+}
+
+impl Index {
+    pub fn new(network: Network) -> Self {
+        Index::with_params(NetworkParams::tapyrus(network))
+    }
+
+    /// Indexes a chain with the asset ids and addresses of `params`.
+    pub fn with_params(params: &'static NetworkParams) -> Self {
+        Index {
+            colorer: ChainColorer::with_params(params),
+            outputs: HashMap::new(),
+            assets: HashMap::new(),
+            heights: HashMap::new(),
+            height: 0,
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.colorer.network()
+    }
+
+    pub fn params(&self) -> &'static NetworkParams {
+        self.colorer.params()
+    }
+
+    /// Colors `block`, which must follow the blocks already added.
+    pub fn add_block(&mut self, block: &Block) -> Result<(), Error> {
+        let colorings = self.colorer.color_block(block)?;
+        for (tx, coloring) in block.txdata.iter().zip(colorings) {
+            self.add_issuances(tx, &coloring.outputs);
+            self.heights.insert(coloring.txid, self.height);
+            self.outputs.insert(coloring.txid, coloring.outputs);
+        }
+        self.height += 1;
+        Ok(())
+    }
+
+    /// The number of blocks added.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// The number of blocks from the block of `txid` to the last block, both included.
+    pub fn confirmations(&self, txid: &Txid) -> Option<u64> {
+        self.heights.get(txid).map(|height| self.height - height)
+    }
+
+    /// The unspent outputs locked by `script`, sorted by outpoint.
+    pub fn unspent_outputs(&self, script: &Script) -> Vec<Utxo> {
+        let mut unspent: Vec<Utxo> = self
+            .colorer
+            .unspent()
+            .filter(|(_, o)| o.script_pubkey == *script)
+            .map(|(outpoint, output)| Utxo {
+                outpoint: *outpoint,
+                output: output.clone(),
+            })
+            .collect();
+        unspent.sort_by_key(|u| u.outpoint);
+        unspent
+    }
+
+    /// The `u=` URL in the metadata of the first issuance of `asset_id`, or
+    /// an empty string if there is none.
+    pub fn asset_definition_url(&self, asset_id: &AssetId) -> String {
+        self.assets
+            .get(&asset_id.to_string())
+            .and_then(|info| info.metadata["utf8"].as_str())
+            .and_then(|metadata| metadata.strip_prefix("u="))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn add_issuances(&mut self, tx: &Transaction, outputs: &[ColoredOutput]) {
+        for output in outputs
+            .iter()
+            .filter(|o| o.output_type == OutputType::Issuance)
+        {
+            let asset_id = match output.asset_id {
+                Some(ref asset_id) => asset_id,
+                None => continue,
+            };
+            let info = self
+                .assets
+                .entry(asset_id.to_string())
+                .or_insert_with(|| AssetInfo {
+                    asset_id: asset_id.clone(),
+                    issued: 0,
+                    issuances: vec![],
+                    metadata: find_marker(tx).map_or(Value::Null, |(_, p)| json!(p.metadata)),
+                });
+            info.issued += output.asset_quantity;
+            let txid = tx.txid();
+            if info.issuances.last() != Some(&txid) {
+                info.issuances.push(txid);
+            }
+        }
+    }
+
+    /// Answers `request`:
+    ///
+    /// * `GET /v1/txs/<txid>/outputs`: the colored outputs of a transaction
+    /// * `GET /v1/addresses/<oa address>/balance`: the assets held by an address
+    /// * `GET /v1/assets/<asset id>`: the supply and issuances of an asset
+    /// * `POST /v1/decode`: the marker output of the raw transaction hex in the body
+    /// * `POST /`: the JSON-RPC methods of `rpc`
+    pub fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), &segments[..]) {
+            ("POST", [""]) => Response::ok(rpc::handle(self, &request.body)),
+            ("GET", ["v1", "txs", txid, "outputs"]) => self.tx_outputs(txid),
+            ("GET", ["v1", "addresses", address, "balance"]) => self.balance(address),
+            ("GET", ["v1", "assets", asset_id]) => self.asset(asset_id),
+            ("POST", ["v1", "decode"]) => decode(&request.body),
+            (_, ["v1", "txs", _, "outputs"])
+            | (_, ["v1", "addresses", _, "balance"])
+            | (_, ["v1", "assets", _])
+            | (_, ["v1", "decode"]) => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    fn tx_outputs(&self, txid: &str) -> Response {
+        let txid = match Txid::from_hex(txid) {
+            Ok(txid) => txid,
+            Err(_) => return Response::error(400, "invalid txid"),
+        };
+        match self.outputs.get(&txid) {
+            Some(outputs) => Response::ok(json!({
+                "txid": txid.to_string(),
+                "outputs": outputs.iter().map(output_json).collect::<Vec<Value>>(),
+            })),
+            None => Response::error(404, "transaction not found"),
+        }
+    }
+
+    fn balance(&self, address: &str) -> Response {
+        let address = match Address::from_str_with_params(address, self.params()) {
+            Ok(address) => address,
+            Err(_) => return Response::error(400, "invalid Open Assets address"),
+        };
+        let script = match address.to_btc_addr() {
+            Ok(address) => address.script_pubkey(),
+            Err(_) => return Response::error(400, "invalid Open Assets address"),
+        };
+        let unspent = self.unspent_outputs(&script);
+        let mut value = 0;
+        let mut assets: Vec<(String, u64)> = vec![];
+        for Utxo { output, .. } in unspent.iter() {
+            value += output.value;
+            if let Some(ref asset_id) = output.asset_id {
+                let asset_id = asset_id.to_string();
+                match assets.iter_mut().find(|(id, _)| *id == asset_id) {
+                    Some((_, quantity)) => *quantity += output.asset_quantity,
+                    None => assets.push((asset_id, output.asset_quantity)),
+                }
+            }
+        }
+        Response::ok(json!({
+            "oa_address": address.to_string(),
+            "value": value,
+            "assets": assets
+                .iter()
+                .map(|(asset_id, quantity)| json!({ "asset_id": asset_id, "quantity": quantity }))
+                .collect::<Vec<Value>>(),
+            "unspent": unspent
+                .iter()
+                .map(|utxo| {
+                    let mut value = output_json(&utxo.output);
+                    value["txid"] = json!(utxo.outpoint.txid.to_string());
+                    value["vout"] = json!(utxo.outpoint.vout);
+                    value
+                })
+                .collect::<Vec<Value>>(),
+        }))
+    }
+
+    fn asset(&self, asset_id: &str) -> Response {
+        if AssetId::from_str_with_params(asset_id, self.params()).is_err() {
+            return Response::error(400, "invalid asset id");
+        }
+        let info = match self.assets.get(asset_id) {
+            Some(info) => info,
+            None => return Response::error(404, "asset not found"),
+        };
+        let circulating: u64 = self
+            .colorer
+            .unspent()
+            .filter(|(_, o)| o.asset_id.as_ref() == Some(&info.asset_id))
+            .map(|(_, o)| o.asset_quantity)
+            .sum();
+        Response::ok(json!({
+            "asset_id": asset_id,
+            "issued": info.issued,
+            "supply": circulating,
+            "issuances": info.issuances.iter().map(|t| t.to_string()).collect::<Vec<String>>(),
+            "metadata": info.metadata,
+        }))
+    }
+}
+
+fn output_json(output: &ColoredOutput) -> Value {
+    json!({
+        "value": output.value,
+        "script": hex::encode(output.script_pubkey.as_bytes()),
+        "asset_id": output.asset_id.as_ref().map(|id| id.to_string()),
+        "asset_quantity": output.asset_quantity,
+        "output_type": output.output_type.to_string(),
+    })
+}
+
+fn decode(body: &[u8]) -> Response {
+    let tx: Transaction = match std::str::from_utf8(body)
+        .ok()
+        .and_then(|hex| hex::decode(hex.trim()).ok())
+        .and_then(|bytes| deserialize(&bytes).ok())
+    {
+        Some(tx) => tx,
+        None => return Response::error(400, "invalid transaction hex"),
+    };
+    match output_roles(&tx) {
+        Some((marker, payload, roles)) => Response::ok(json!({
+            "txid": tx.txid().to_string(),
+            "marker_index": marker,
+            "quantities": payload.quantities,
+            "metadata": payload.metadata,
+            "outputs": roles
+                .iter()
+                .map(|r| json!({ "output_type": r.output_type.to_string(), "quantity": r.quantity }))
+                .collect::<Vec<Value>>(),
+        })),
+        None => Response::error(422, "the transaction has no valid marker output"),
+    }
+}
+
+/// Reads an HTTP/1.1 request with an optional `Content-Length` body.
+pub fn read_request<R: BufRead>(mut reader: R) -> Result<Request, Error> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(Error::BadRequest("invalid request line")),
+    };
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::BadRequest("unterminated headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = usize::from_str(value.trim())
+                .map_err(|_| Error::BadRequest("invalid content length"))?;
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(Error::BadRequest("body too large"));
+    }
+    let mut body = vec![0; content_length];
+    Read::read_exact(&mut reader, &mut body)?;
+    Ok(Request { method, path, body })
+}
+
+pub fn write_response<W: Write>(mut writer: W, response: &Response) -> Result<(), Error> {
+    let body = response.body.to_string();
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason,
+        body.len(),
+        body
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Serves the queries of an `Index` from a fixed number of worker threads.
+pub struct Server {
+    listener: TcpListener,
+    index: Index,
+    workers: usize,
+    timeout: Duration,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, index: Index) -> Result<Self, Error> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            index,
+            workers: DEFAULT_WORKERS,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Serves up to `workers` connections at the same time; further
+    /// connections wait to be accepted.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = std::cmp::max(workers, 1);
+        self
+    }
+
+    /// Closes connections which wait longer than `timeout` for a read or a
+    /// write, so that idle clients don't hold a worker. A zero timeout, which
+    /// sockets reject, is raised to one millisecond.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = std::cmp::max(timeout, Duration::from_millis(1));
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accepts connections forever. Failed accepts, such as connections reset
+    /// before being accepted or a full file table, are logged and skipped.
+    pub fn serve(&self) -> Result<(), Error> {
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+        let receiver = Mutex::new(receiver);
+        thread::scope(|scope| {
+            for _ in 0..self.workers {
+                scope.spawn(|| {
+                    loop {
+                        let stream = receiver.lock().unwrap().recv();
+                        match stream {
+                            // a broken connection only affects its own client
+                            Ok(stream) => drop(self.serve_connection(stream)),
+                            Err(_) => return,
+                        }
+                    }
+                });
+            }
+            let result = self.accept(&sender);
+            // stops the workers once they finish their connection
+            drop(sender);
+            result
+        })
+    }
+
+    fn accept(&self, workers: &mpsc::SyncSender<TcpStream>) -> Result<(), Error> {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if workers.send(stream).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("accept failed: {}", e);
+                    // gives a full file table time to drain
+                    thread::sleep(ACCEPT_BACKOFF);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_connection(&self, stream: TcpStream) -> Result<(), Error> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let response = match read_request(BufReader::new(&stream)) {
+            Ok(request) => self.index.handle(&request),
+            Err(Error::BadRequest(what)) => Response::error(400, what),
+            Err(e) => return Err(e),
+        };
+        write_response(&stream, &response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::server::{read_request, write_response, Request, Response};
+    use serde_json::json;
+
+    #[test]
+    fn test_read_request() {
+        let raw = b"POST /v1/decode HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nabcd";
+        assert_eq!(
+            Request {
+                method: "POST".to_string(),
+                path: "/v1/decode".to_string(),
+                body: b"abcd".to_vec(),
+            },
+            read_request(&raw[..]).unwrap()
+        );
+        assert!(read_request(&b"GET\r\n\r\n"[..]).is_err());
+        assert!(read_request(&b"GET / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc"[..]).is_err());
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut out = vec![];
+        let response = Response {
+            status: 404,
+            body: json!({ "error": "not found" }),
+        };
+        write_response(&mut out, &response).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(out.ends_with("\r\n\r\n{\"error\":\"not found\"}"));
+    }
+}
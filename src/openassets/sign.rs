@@ -0,0 +1,463 @@
+use std::fmt;
+
+use tapyrus::blockdata::opcodes;
+use tapyrus::blockdata::script::{Builder, Instruction};
+use tapyrus::consensus::encode::serialize;
+use tapyrus::hashes::Hash;
+use tapyrus::secp256k1::{self, Message, Secp256k1};
+use tapyrus::util::signature;
+use tapyrus::{PrivateKey, PublicKey, Script, SigHash, SigHashType, Transaction};
+
+/// The length of a serialized Tapyrus Schnorr signature, without sighash type.
+pub const SCHNORR_SIGNATURE_SIZE: usize = 64;
+
+#[derive(Debug)]
+pub enum Error {
+    Secp256k1(secp256k1::Error),
+    /// Schnorr signing or verification failed.
+    Schnorr(String),
+    /// The transaction has no input at the index.
+    InputNotFound(usize),
+    /// The redeem script is not a bare multisig script.
+    InvalidRedeemScript,
+    /// Fewer signers than required hold keys of the multisig script.
+    NotEnoughSigners {
+        index: usize,
+        required: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Secp256k1(ref e) => write!(f, "secp256k1 error: {}", e),
+            Error::Schnorr(ref e) => write!(f, "schnorr signature error: {}", e),
+            Error::InputNotFound(index) => write!(f, "input {} not found", index),
+            Error::InvalidRedeemScript => write!(f, "invalid multisig redeem script"),
+            Error::NotEnoughSigners {
+                index,
+                required,
+                found,
+            } => write!(
+                f,
+                "input {} requires {} signatures but only {} signers hold its keys",
+                index, required, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<secp256k1::Error> for Error {
+    fn from(e: secp256k1::Error) -> Self {
+        Error::Secp256k1(e)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SignatureScheme {
+    Ecdsa,
+    /// The Schnorr signature scheme of Tapyrus, accepted by OP_CHECKSIG and
+    /// OP_CHECKMULTISIG in place of ECDSA.
+    Schnorr,
+}
+
+/// Produces signatures for a public key, e.g. from a local private key or a
+/// hardware device.
+pub trait Signer {
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs `sighash`, returning the serialized signature without the sighash type.
+    fn sign(&self, sighash: &SigHash, scheme: SignatureScheme) -> Result<Vec<u8>, Error>;
+}
+
+impl<T: Signer + ?Sized> Signer for &T {
+    fn public_key(&self) -> PublicKey {
+        (**self).public_key()
+    }
+
+    fn sign(&self, sighash: &SigHash, scheme: SignatureScheme) -> Result<Vec<u8>, Error> {
+        (**self).sign(sighash, scheme)
+    }
+}
+
+impl Signer for PrivateKey {
+    fn public_key(&self) -> PublicKey {
+        PrivateKey::public_key(self, &Secp256k1::signing_only())
+    }
+
+    fn sign(&self, sighash: &SigHash, scheme: SignatureScheme) -> Result<Vec<u8>, Error> {
+        match scheme {
+            SignatureScheme::Ecdsa => {
+                let message = Message::from_slice(&sighash[..])?;
+                let sig = Secp256k1::signing_only().sign_ecdsa(&message, &self.key);
+                Ok(sig.serialize_der().to_vec())
+            }
+            SignatureScheme::Schnorr => {
+                let sig = signature::Signature::sign(self, &sighash.into_inner())
+                    .map_err(|e| Error::Schnorr(format!("{:?}", e)))?;
+                Ok(serialize(&sig))
+            }
+        }
+    }
+}
+
+/// Returns the signature hash of input `index` spending an output locked by `script_code`.
+pub fn sighash(
+    tx: &Transaction,
+    index: usize,
+    script_code: &Script,
+    sighash_type: SigHashType,
+) -> Result<SigHash, Error> {
+    if index >= tx.input.len() {
+        return Err(Error::InputNotFound(index));
+    }
+    Ok(tx.signature_hash(index, script_code, sighash_type.as_u32()))
+}
+
+/// Signs input `index` with `signer` and appends the sighash type.
+pub fn sign_input<S: Signer + ?Sized>(
+    tx: &Transaction,
+    index: usize,
+    script_code: &Script,
+    signer: &S,
+    scheme: SignatureScheme,
+    sighash_type: SigHashType,
+) -> Result<Vec<u8>, Error> {
+    let hash = sighash(tx, index, script_code, sighash_type)?;
+    let mut sig = signer.sign(&hash, scheme)?;
+    sig.push(sighash_type.as_u32() as u8);
+    Ok(sig)
+}
+
+/// Signs input `index` spending a P2PKH or colored P2PKH output locked by `script_pubkey`.
+pub fn sign_p2pkh<S: Signer + ?Sized>(
+    tx: &mut Transaction,
+    index: usize,
+    script_pubkey: &Script,
+    signer: &S,
+    scheme: SignatureScheme,
+) -> Result<(), Error> {
+    let sig = sign_input(tx, index, script_pubkey, signer, scheme, SigHashType::All)?;
+    tx.input[index].script_sig = Builder::new()
+        .push_slice(&sig)
+        .push_key(&signer.public_key())
+        .into_script();
+    Ok(())
+}
+
+/// Returns the number of required signatures and the public keys of a bare
+/// multisig script.
+pub fn parse_multisig(redeem_script: &Script) -> Result<(usize, Vec<PublicKey>), Error> {
+    let mut required = None;
+    let mut keys = vec![];
+    let mut total = None;
+    let mut closed = false;
+    for instruction in redeem_script.instructions() {
+        match instruction.map_err(|_| Error::InvalidRedeemScript)? {
+            Instruction::PushBytes(bytes) if required.is_some() && total.is_none() => {
+                keys.push(PublicKey::from_slice(bytes).map_err(|_| Error::InvalidRedeemScript)?);
+            }
+            Instruction::Op(op) if op == opcodes::all::OP_CHECKMULTISIG && total.is_some() => {
+                closed = true;
+            }
+            Instruction::Op(op) if !closed => {
+                let code = op.into_u8();
+                let first = opcodes::all::OP_PUSHNUM_1.into_u8();
+                let last = opcodes::all::OP_PUSHNUM_16.into_u8();
+                if code < first || code > last {
+                    return Err(Error::InvalidRedeemScript);
+                }
+                let n = (code - first + 1) as usize;
+                if required.is_none() {
+                    required = Some(n);
+                } else if total.is_none() {
+                    total = Some(n);
+                } else {
+                    return Err(Error::InvalidRedeemScript);
+                }
+            }
+            _ => return Err(Error::InvalidRedeemScript),
+        }
+    }
+    match (required, total) {
+        (Some(m), Some(n)) if closed && n == keys.len() && m <= n => Ok((m, keys)),
+        _ => Err(Error::InvalidRedeemScript),
+    }
+}
+
+/// Builds a bare `required`-of-`keys.len()` multisig script.
+pub fn multisig_redeem_script(required: usize, keys: &[PublicKey]) -> Script {
+    let mut builder = Builder::new().push_int(required as i64);
+    for key in keys {
+        builder = builder.push_key(key);
+    }
+    builder
+        .push_int(keys.len() as i64)
+        .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+        .into_script()
+}
+
+/// Builds the scriptSig of a P2SH multisig input from signatures given in
+/// the order of the keys in `redeem_script`.
+pub fn multisig_script_sig(signatures: &[Vec<u8>], redeem_script: &Script) -> Script {
+    let mut builder = Builder::new().push_opcode(opcodes::OP_FALSE);
+    for sig in signatures {
+        builder = builder.push_slice(sig);
+    }
+    builder.push_slice(redeem_script.as_bytes()).into_script()
+}
+
+/// Signs input `index` spending a P2SH multisig output with the first
+/// `required` signers holding keys of `redeem_script`.
+pub fn sign_p2sh_multisig<S: Signer>(
+    tx: &mut Transaction,
+    index: usize,
+    redeem_script: &Script,
+    signers: &[S],
+    scheme: SignatureScheme,
+) -> Result<(), Error> {
+    let (required, keys) = parse_multisig(redeem_script)?;
+    let mut signatures = vec![];
+    for key in keys.iter() {
+        if signatures.len() == required {
+            break;
+        }
+        if let Some(signer) = signers.iter().find(|s| s.public_key() == *key) {
+            signatures.push(sign_input(
+                tx,
+                index,
+                redeem_script,
+                signer,
+                scheme,
+                SigHashType::All,
+            )?);
+        }
+    }
+    if signatures.len() < required {
+        return Err(Error::NotEnoughSigners {
+            index,
+            required,
+            found: signatures.len(),
+        });
+    }
+    tx.input[index].script_sig = multisig_script_sig(&signatures, redeem_script);
+    Ok(())
+}
+
+/// How to sign one input of a transaction.
+pub enum SigningInput<'a> {
+    /// A P2PKH or colored P2PKH output locked by `script_pubkey`.
+    P2pkh {
+        script_pubkey: Script,
+        signer: &'a dyn Signer,
+    },
+    P2shMultisig {
+        redeem_script: Script,
+        signers: Vec<&'a dyn Signer>,
+    },
+}
+
+/// Returns a copy of `tx` with every input signed as described by `inputs`,
+/// given in input order.
+pub fn sign_transaction(
+    tx: &Transaction,
+    inputs: &[SigningInput],
+    scheme: SignatureScheme,
+) -> Result<Transaction, Error> {
+    if inputs.len() != tx.input.len() {
+        return Err(Error::InputNotFound(std::cmp::min(
+            inputs.len(),
+            tx.input.len(),
+        )));
+    }
+    let mut signed = tx.clone();
+    for (index, input) in inputs.iter().enumerate() {
+        match *input {
+            SigningInput::P2pkh {
+                ref script_pubkey,
+                signer,
+            } => sign_p2pkh(&mut signed, index, script_pubkey, signer, scheme)?,
+            SigningInput::P2shMultisig {
+                ref redeem_script,
+                ref signers,
+            } => sign_p2sh_multisig(&mut signed, index, redeem_script, signers, scheme)?,
+        }
+    }
+    Ok(signed)
+}
+
+/// Verifies `sig`, a signature with its sighash type, of input `index` by `key`.
+///
+/// As in Tapyrus, 64 byte signatures are Schnorr signatures and the others
+/// DER encoded ECDSA signatures.
+pub fn verify_signature(
+    tx: &Transaction,
+    index: usize,
+    script_code: &Script,
+    sig: &[u8],
+    key: &PublicKey,
+) -> Result<bool, Error> {
+    let (sighash_type, sig) = match sig.split_last() {
+        Some((&sighash_type, sig)) => (SigHashType::from_u32(sighash_type as u32), sig),
+        None => return Ok(false),
+    };
+    let hash = sighash(tx, index, script_code, sighash_type)?;
+    if sig.len() == SCHNORR_SIGNATURE_SIZE {
+        let sig: signature::Signature = match tapyrus::consensus::deserialize(sig) {
+            Ok(sig) => sig,
+            Err(_) => return Ok(false),
+        };
+        return Ok(sig.verify(&hash.into_inner(), key).is_ok());
+    }
+    let message = Message::from_slice(&hash[..])?;
+    let sig = match secp256k1::ecdsa::Signature::from_der(sig) {
+        Ok(sig) => sig,
+        Err(_) => return Ok(false),
+    };
+    Ok(Secp256k1::verification_only()
+        .verify_ecdsa(&message, &sig, &key.key)
+        .is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::sign::{
+        multisig_redeem_script, parse_multisig, sign_transaction, verify_signature, Error,
+        SignatureScheme, Signer, SigningInput, SCHNORR_SIGNATURE_SIZE,
+    };
+    use crate::openassets::test_helpers::*;
+    use tapyrus::blockdata::script::Instruction;
+    use tapyrus::network::constants::Network;
+    use tapyrus::secp256k1::SecretKey;
+    use tapyrus::{Address, OutPoint, PrivateKey, PublicKey, Script, Transaction};
+
+    fn key(n: u8) -> PrivateKey {
+        PrivateKey {
+            compressed: true,
+            network: Network::Prod,
+            key: SecretKey::from_slice(&[n; 32]).unwrap(),
+        }
+    }
+
+    fn pubkey(key: &PrivateKey) -> PublicKey {
+        Signer::public_key(key)
+    }
+
+    fn pushes(script: &Script) -> Vec<Vec<u8>> {
+        script
+            .instructions()
+            .map(|i| match i.unwrap() {
+                Instruction::PushBytes(bytes) => bytes.to_vec(),
+                Instruction::Op(_) => vec![],
+            })
+            .collect()
+    }
+
+    fn unsigned(inputs: usize) -> Transaction {
+        let funding = funding_tx(0, vec![]);
+        let outpoints: Vec<OutPoint> = (0..inputs)
+            .map(|i| OutPoint::new(funding.txid(), i as u32))
+            .collect();
+        tx(
+            &outpoints,
+            vec![
+                txout(600, p2pkh(1)),
+                marker(vec![10], b""),
+                txout(1000, p2pkh(2)),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_sign_p2pkh() {
+        let alice = key(1);
+        let bob = key(2);
+        let alice_script = Address::p2pkh(&pubkey(&alice), Network::Prod).script_pubkey();
+        let bob_script = Address::p2pkh(&pubkey(&bob), Network::Prod).script_pubkey();
+        let tx = unsigned(2);
+
+        for scheme in [SignatureScheme::Ecdsa, SignatureScheme::Schnorr] {
+            let inputs = [
+                SigningInput::P2pkh {
+                    script_pubkey: alice_script.clone(),
+                    signer: &alice,
+                },
+                SigningInput::P2pkh {
+                    script_pubkey: bob_script.clone(),
+                    signer: &bob,
+                },
+            ];
+            let signed = sign_transaction(&tx, &inputs, scheme).unwrap();
+            assert_eq!(tx.txid(), {
+                let mut stripped = signed.clone();
+                stripped
+                    .input
+                    .iter_mut()
+                    .for_each(|i| i.script_sig = Script::new());
+                stripped.txid()
+            });
+            for (index, (signer, script)) in [(&alice, &alice_script), (&bob, &bob_script)]
+                .into_iter()
+                .enumerate()
+            {
+                let key = pubkey(signer);
+                let data = pushes(&signed.input[index].script_sig);
+                assert_eq!(2, data.len());
+                assert_eq!(key.to_bytes(), data[1]);
+                assert_eq!(
+                    scheme == SignatureScheme::Schnorr,
+                    data[0].len() == SCHNORR_SIGNATURE_SIZE + 1
+                );
+                assert!(verify_signature(&signed, index, script, &data[0], &key).unwrap());
+                // the signature does not cover another input
+                let other = 1 - index;
+                assert!(!verify_signature(&signed, other, script, &data[0], &key).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sign_p2sh_multisig() {
+        let keys = [key(1), key(2), key(3)];
+        let pubkeys: Vec<PublicKey> = keys.iter().map(pubkey).collect();
+        let redeem_script = multisig_redeem_script(2, &pubkeys);
+        assert_eq!(
+            (2, pubkeys.clone()),
+            parse_multisig(&redeem_script).unwrap()
+        );
+        assert!(parse_multisig(&p2pkh(1)).is_err());
+
+        let stranger = key(4);
+        let tx = unsigned(1);
+        for scheme in [SignatureScheme::Ecdsa, SignatureScheme::Schnorr] {
+            // signers are matched to keys in script order
+            let inputs = [SigningInput::P2shMultisig {
+                redeem_script: redeem_script.clone(),
+                signers: vec![&keys[2] as &dyn Signer, &keys[0] as &dyn Signer],
+            }];
+            let signed = sign_transaction(&tx, &inputs, scheme).unwrap();
+            let data = pushes(&signed.input[0].script_sig);
+            assert_eq!(4, data.len());
+            assert!(data[0].is_empty());
+            assert_eq!(redeem_script.as_bytes(), &data[3][..]);
+            assert!(verify_signature(&signed, 0, &redeem_script, &data[1], &pubkeys[0]).unwrap());
+            assert!(verify_signature(&signed, 0, &redeem_script, &data[2], &pubkeys[2]).unwrap());
+
+            let inputs = [SigningInput::P2shMultisig {
+                redeem_script: redeem_script.clone(),
+                signers: vec![&keys[1] as &dyn Signer, &stranger as &dyn Signer],
+            }];
+            match sign_transaction(&tx, &inputs, scheme) {
+                Err(Error::NotEnoughSigners {
+                    index: 0,
+                    required: 2,
+                    found: 1,
+                }) => {}
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+    }
+}
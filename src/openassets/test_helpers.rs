@@ -0,0 +1,44 @@
+//! Fixtures shared by the unit tests of the openassets modules.
+
+use std::fs;
+use std::path::PathBuf;
+
+use tapyrus::network::constants::Network;
+use tapyrus::{OutPoint, Transaction, TxOut};
+
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::coloring::{ColoredOutput, OutputType};
+pub use crate::openassets::testing::*;
+
+/// A dust-sized output holding `quantity` units of `asset_id`.
+pub fn colored(quantity: u64, asset_id: &AssetId) -> ColoredOutput {
+    ColoredOutput {
+        value: 600,
+        script_pubkey: p2pkh(9),
+        asset_id: Some(asset_id.clone()),
+        asset_quantity: quantity,
+        output_type: OutputType::Transfer,
+    }
+}
+
+/// The prod asset id issued by `p2pkh(n)`.
+pub fn asset(n: u8) -> AssetId {
+    AssetId::new(&p2pkh(n), Network::Prod)
+}
+
+/// Spends `inputs` outputs of a dummy transaction into `outputs`.
+pub fn spend(outputs: Vec<TxOut>, inputs: usize) -> Transaction {
+    let dummy = funding_tx(0, vec![]);
+    let outpoints: Vec<OutPoint> = (0..inputs)
+        .map(|i| OutPoint::new(dummy.txid(), i as u32))
+        .collect();
+    tx(&outpoints, outputs)
+}
+
+/// Creates an empty directory for the test `name` under the system temp dir.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("openassets-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
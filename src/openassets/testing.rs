@@ -0,0 +1,90 @@
+//! Fixtures shared by the unit tests, the integration tests and the
+//! benchmarks, built with the `testing` feature that the dev-dependencies
+//! enable. Not part of the public API.
+
+use hex::decode as hex_decode;
+use tapyrus::blockdata::opcodes;
+use tapyrus::blockdata::script::Builder;
+use tapyrus::consensus::{deserialize, serialize, Encodable};
+use tapyrus::hashes::Hash;
+use tapyrus::{Block, BlockHash, OutPoint, Script, Transaction, TxOut, Txid, VarInt};
+
+use crate::openassets::marker_output::{Metadata, Payload};
+
+/// P2PKH script used as the issuer in the spec's asset id example.
+pub const ISSUER_SCRIPT: &str = "76a914010966776006953d5567439e5e39f86a0d273bee88ac";
+/// Asset id of `ISSUER_SCRIPT` on the prod network.
+pub const ISSUER_ASSET_ID: &str = "ALn3aK1fSuG27N96UGYB1kUYUpGKRhBuBC";
+
+pub fn script(hex: &str) -> Script {
+    Builder::from(hex_decode(hex).unwrap()).into_script()
+}
+
+pub fn p2pkh(n: u8) -> Script {
+    let mut bytes = hex_decode("76a914000000000000000000000000000000000000000088ac").unwrap();
+    bytes[3..23].iter_mut().for_each(|b| *b = n);
+    Builder::from(bytes).into_script()
+}
+
+pub fn marker(quantities: Vec<u64>, metadata: &[u8]) -> TxOut {
+    let payload = Payload {
+        quantities,
+        metadata: Metadata::new(metadata.to_vec()),
+    };
+    TxOut {
+        value: 0,
+        script_pubkey: Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_slice(&serialize(&payload))
+            .into_script(),
+    }
+}
+
+pub fn txout(value: u64, script_pubkey: Script) -> TxOut {
+    TxOut {
+        value,
+        script_pubkey,
+    }
+}
+
+/// Builds a version 1 transaction spending `inputs`, with empty scriptSigs,
+/// into `outputs`.
+pub fn tx(inputs: &[OutPoint], outputs: Vec<TxOut>) -> Transaction {
+    let mut bytes: Vec<u8> = vec![];
+    1i32.consensus_encode(&mut bytes).unwrap();
+    VarInt(inputs.len() as u64)
+        .consensus_encode(&mut bytes)
+        .unwrap();
+    for outpoint in inputs {
+        outpoint.consensus_encode(&mut bytes).unwrap();
+        Script::new().consensus_encode(&mut bytes).unwrap();
+        0xffff_ffffu32.consensus_encode(&mut bytes).unwrap();
+    }
+    outputs.consensus_encode(&mut bytes).unwrap();
+    0u32.consensus_encode(&mut bytes).unwrap();
+    deserialize(&bytes).unwrap()
+}
+
+/// A transaction without any marker that funds `outputs` from a dummy outpoint.
+pub fn funding_tx(seed: u32, outputs: Vec<TxOut>) -> Transaction {
+    let dummy = OutPoint::new(Txid::from_slice(&[0; 32]).unwrap(), seed);
+    tx(&[dummy], outputs)
+}
+
+/// Builds a block on top of `prev` without a proof.
+pub fn block(prev: &BlockHash, time: u32, txs: &[Transaction]) -> Block {
+    let mut bytes: Vec<u8> = vec![];
+    1i32.consensus_encode(&mut bytes).unwrap();
+    bytes.extend_from_slice(&prev[..]);
+    bytes.extend_from_slice(&[0u8; 64]); // merkle root and immutable merkle root
+    time.consensus_encode(&mut bytes).unwrap();
+    bytes.push(0); // no xfield
+    bytes.push(0); // no proof
+    VarInt(txs.len() as u64)
+        .consensus_encode(&mut bytes)
+        .unwrap();
+    for tx in txs {
+        tx.consensus_encode(&mut bytes).unwrap();
+    }
+    deserialize(&bytes).unwrap()
+}
@@ -0,0 +1,364 @@
+use std::fmt;
+
+use tapyrus::network::constants::Network;
+use tapyrus::Transaction;
+
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::coloring::{compute_asset_ids_with, ColoredOutput, Diagnosis, OutputType};
+use crate::openassets::marker_output::MarkerVersions;
+use crate::openassets::network::NetworkParams;
+
+/// An output the transaction is intended to color.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ExpectedOutput {
+    pub index: usize,
+    pub asset_id: AssetId,
+    pub quantity: u64,
+}
+
+impl ExpectedOutput {
+    pub fn new(index: usize, asset_id: AssetId, quantity: u64) -> Self {
+        ExpectedOutput {
+            index,
+            asset_id,
+            quantity,
+        }
+    }
+}
+
+/// A difference between the intended and the actual coloring of a transaction.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Discrepancy {
+    /// The transaction is not a valid Open Assets transaction, so none of
+    /// its outputs is colored.
+    Invalid(Diagnosis),
+    /// An expected output does not exist.
+    MissingOutput { index: usize },
+    /// An expected output is colored with another asset, or not at all.
+    AssetMismatch {
+        index: usize,
+        expected: AssetId,
+        actual: Option<AssetId>,
+    },
+    /// An expected output carries another quantity of the expected asset.
+    QuantityMismatch {
+        index: usize,
+        expected: u64,
+        actual: u64,
+    },
+    /// An output which is not expected is colored.
+    UnexpectedColoredOutput {
+        index: usize,
+        asset_id: AssetId,
+        quantity: u64,
+    },
+    /// Asset units held by the inputs are not transferred to any output.
+    Burn { asset_id: AssetId, quantity: u64 },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Discrepancy::Invalid(ref diagnosis) => write!(f, "invalid transaction: {}", diagnosis),
+            Discrepancy::MissingOutput { index } => write!(f, "output {} does not exist", index),
+            Discrepancy::AssetMismatch {
+                index,
+                ref expected,
+                ref actual,
+            } => match *actual {
+                Some(ref actual) => write!(
+                    f,
+                    "output {} is colored with {} instead of {}",
+                    index, actual, expected
+                ),
+                None => write!(f, "output {} is not colored with {}", index, expected),
+            },
+            Discrepancy::QuantityMismatch {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "output {} carries {} asset units instead of {}",
+                index, actual, expected
+            ),
+            Discrepancy::UnexpectedColoredOutput {
+                index,
+                ref asset_id,
+                quantity,
+            } => write!(
+                f,
+                "output {} is unexpectedly colored with {} units of {}",
+                index, quantity, asset_id
+            ),
+            Discrepancy::Burn {
+                ref asset_id,
+                quantity,
+            } => write!(f, "{} units of {} are burned", quantity, asset_id),
+        }
+    }
+}
+
+/// Sums the quantities of `outputs` per asset id, in order of first appearance.
+fn totals<'a, I: Iterator<Item = &'a ColoredOutput>>(outputs: I) -> Vec<(AssetId, u64)> {
+    let mut totals: Vec<(AssetId, u64)> = vec![];
+    for output in outputs {
+        if let Some(ref asset_id) = output.asset_id {
+            match totals.iter_mut().find(|(id, _)| id == asset_id) {
+                Some((_, total)) => *total = total.saturating_add(output.asset_quantity),
+                None => totals.push((asset_id.clone(), output.asset_quantity)),
+            }
+        }
+    }
+    totals
+}
+
+/// Checks that `tx` colors exactly the `expected` outputs before it is broadcast.
+///
+/// `inputs` are the colored outputs spent by `tx`, in input order. Outputs
+/// which are not expected must be uncolored, and every asset unit held by
+/// the inputs must be transferred. Returns an empty list if `tx` colors as
+/// intended.
+pub fn validate(
+    tx: &Transaction,
+    inputs: &[ColoredOutput],
+    expected: &[ExpectedOutput],
+    network: Network,
+) -> Vec<Discrepancy> {
+    validate_with(
+        tx,
+        inputs,
+        expected,
+        NetworkParams::tapyrus(network),
+        &MarkerVersions::default(),
+    )
+}
+
+/// Like `validate`, coloring with the asset ids of `params` and the marker
+/// versions of `versions`.
+///
+/// The expected asset ids must be of `params` too, as asset ids of other
+/// networks never match.
+pub fn validate_with(
+    tx: &Transaction,
+    inputs: &[ColoredOutput],
+    expected: &[ExpectedOutput],
+    params: &'static NetworkParams,
+    versions: &MarkerVersions,
+) -> Vec<Discrepancy> {
+    let mut discrepancies = vec![];
+    let outputs = match compute_asset_ids_with(tx, inputs, params, versions) {
+        Ok(outputs) => outputs,
+        Err(diagnosis) => {
+            // spending colored inputs without a marker is an ordinary burn
+            if diagnosis != Diagnosis::MarkerMissing || !expected.is_empty() {
+                discrepancies.push(Discrepancy::Invalid(diagnosis));
+            }
+            tx.output.iter().map(ColoredOutput::uncolored).collect()
+        }
+    };
+
+    for e in expected {
+        let output = match outputs.get(e.index) {
+            Some(output) => output,
+            None => {
+                discrepancies.push(Discrepancy::MissingOutput { index: e.index });
+                continue;
+            }
+        };
+        if output.asset_id.as_ref() != Some(&e.asset_id) {
+            discrepancies.push(Discrepancy::AssetMismatch {
+                index: e.index,
+                expected: e.asset_id.clone(),
+                actual: output.asset_id.clone(),
+            });
+        } else if output.asset_quantity != e.quantity {
+            discrepancies.push(Discrepancy::QuantityMismatch {
+                index: e.index,
+                expected: e.quantity,
+                actual: output.asset_quantity,
+            });
+        }
+    }
+
+    for (index, output) in outputs.iter().enumerate() {
+        if let Some(ref asset_id) = output.asset_id
+            && !expected.iter().any(|e| e.index == index)
+        {
+            discrepancies.push(Discrepancy::UnexpectedColoredOutput {
+                index,
+                asset_id: asset_id.clone(),
+                quantity: output.asset_quantity,
+            });
+        }
+    }
+
+    let transferred = totals(
+        outputs
+            .iter()
+            .filter(|o| o.output_type == OutputType::Transfer),
+    );
+    for (asset_id, held) in totals(inputs.iter()) {
+        let sent = transferred
+            .iter()
+            .find(|(id, _)| *id == asset_id)
+            .map_or(0, |(_, q)| *q);
+        if held > sent {
+            discrepancies.push(Discrepancy::Burn {
+                asset_id,
+                quantity: held - sent,
+            });
+        }
+    }
+    discrepancies
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::coloring::{ColoredOutput, Diagnosis};
+    use crate::openassets::marker_output::MarkerVersions;
+    use crate::openassets::network::BITCOIN;
+    use crate::openassets::test_helpers::*;
+    use crate::openassets::validator::{validate, validate_with, Discrepancy, ExpectedOutput};
+    use std::str::FromStr;
+    use tapyrus::network::constants::Network;
+
+    #[test]
+    fn test_valid_transaction() {
+        let tx = spend(
+            vec![
+                txout(600, p2pkh(1)),
+                marker(vec![10, 30, 20], b""),
+                txout(600, p2pkh(2)),
+                txout(600, p2pkh(3)),
+                txout(1000, p2pkh(4)),
+            ],
+            2,
+        );
+        let issuer = ColoredOutput::uncolored(&txout(1000, script(ISSUER_SCRIPT)));
+        let inputs = vec![issuer, colored(50, &asset(7))];
+        let issued = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        let expected = vec![
+            ExpectedOutput::new(0, issued, 10),
+            ExpectedOutput::new(2, asset(7), 30),
+            ExpectedOutput::new(3, asset(7), 20),
+        ];
+        assert!(validate(&tx, &inputs, &expected, Network::Prod).is_empty());
+    }
+
+    #[test]
+    fn test_discrepancies() {
+        let tx = spend(
+            vec![
+                marker(vec![30, 15], b""),
+                txout(600, p2pkh(2)),
+                txout(600, p2pkh(3)),
+                txout(1000, p2pkh(4)),
+            ],
+            2,
+        );
+        let inputs = vec![colored(40, &asset(7)), colored(10, &asset(7))];
+        let expected = vec![
+            ExpectedOutput::new(1, asset(7), 25),
+            ExpectedOutput::new(3, asset(7), 15),
+            ExpectedOutput::new(5, asset(7), 1),
+        ];
+        let discrepancies = validate(&tx, &inputs, &expected, Network::Prod);
+        assert_eq!(
+            vec![
+                Discrepancy::QuantityMismatch {
+                    index: 1,
+                    expected: 25,
+                    actual: 30
+                },
+                Discrepancy::AssetMismatch {
+                    index: 3,
+                    expected: asset(7),
+                    actual: None
+                },
+                Discrepancy::MissingOutput { index: 5 },
+                Discrepancy::UnexpectedColoredOutput {
+                    index: 2,
+                    asset_id: asset(7),
+                    quantity: 15
+                },
+                Discrepancy::Burn {
+                    asset_id: asset(7),
+                    quantity: 5
+                },
+            ],
+            discrepancies
+        );
+        assert_eq!(
+            "output 1 carries 30 asset units instead of 25",
+            discrepancies[0].to_string()
+        );
+        assert_eq!(
+            format!("5 units of {} are burned", asset(7)),
+            discrepancies[4].to_string()
+        );
+    }
+
+    #[test]
+    fn test_invalid_transaction_burns_inputs() {
+        let tx = spend(vec![marker(vec![60], b""), txout(600, p2pkh(2))], 1);
+        let inputs = vec![colored(50, &asset(7))];
+        let expected = vec![ExpectedOutput::new(1, asset(7), 60)];
+        assert_eq!(
+            vec![
+                Discrepancy::Invalid(Diagnosis::InsufficientInputQuantity {
+                    output: 1,
+                    quantity: 60,
+                    missing: 10,
+                    inputs: vec![0]
+                }),
+                Discrepancy::AssetMismatch {
+                    index: 1,
+                    expected: asset(7),
+                    actual: None
+                },
+                Discrepancy::Burn {
+                    asset_id: asset(7),
+                    quantity: 50
+                },
+            ],
+            validate(&tx, &inputs, &expected, Network::Prod)
+        );
+
+        // a plain payment spending colored outputs burns them
+        let tx = spend(vec![txout(600, p2pkh(2))], 1);
+        assert_eq!(
+            vec![Discrepancy::Burn {
+                asset_id: asset(7),
+                quantity: 50
+            }],
+            validate(&tx, &inputs, &[], Network::Prod)
+        );
+    }
+
+    #[test]
+    fn test_network_params() {
+        let tx = spend(vec![txout(600, p2pkh(1)), marker(vec![10], b"")], 1);
+        let inputs = vec![ColoredOutput::uncolored(&txout(
+            1000,
+            script(ISSUER_SCRIPT),
+        ))];
+        let versions = MarkerVersions::default();
+        let bitcoin = AssetId::with_params(&script(ISSUER_SCRIPT), &BITCOIN);
+        let expected = vec![ExpectedOutput::new(0, bitcoin.clone(), 10)];
+        assert!(validate_with(&tx, &inputs, &expected, &BITCOIN, &versions).is_empty());
+
+        // the Tapyrus asset with the same issuer is another asset
+        let tapyrus = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        let expected = vec![ExpectedOutput::new(0, tapyrus.clone(), 10)];
+        assert_eq!(
+            vec![Discrepancy::AssetMismatch {
+                index: 0,
+                expected: tapyrus,
+                actual: Some(bitcoin)
+            },],
+            validate_with(&tx, &inputs, &expected, &BITCOIN, &versions)
+        );
+    }
+}
@@ -0,0 +1,777 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use tapyrus::consensus::encode;
+use tapyrus::hashes::hex::FromHex;
+use tapyrus::network::constants::Network;
+use tapyrus::secp256k1::{Secp256k1, VerifyOnly};
+use tapyrus::util::bip32::{self, ChildNumber, ExtendedPubKey};
+use tapyrus::{Block, BlockHash, OutPoint, Script, Transaction, Txid};
+
+use crate::openassets::address::Address;
+use crate::openassets::asset_id::AssetId;
+use crate::openassets::coloring::{self, ColoredOutput, OutputType};
+use crate::openassets::hd_wallet::{Chain, DEFAULT_GAP_LIMIT};
+use crate::openassets::marker_output::MarkerVersions;
+use crate::openassets::network::NetworkParams;
+use crate::openassets::pipeline::ChainColorer;
+
+/// The version of the persisted state.
+pub const STATE_VERSION: u64 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Bip32(bip32::Error),
+    Encode(encode::Error),
+    Coloring(coloring::Error),
+    /// The persisted state can not be parsed.
+    Corrupted(&'static str),
+    /// The state was saved by a colorer accepting these marker versions, which
+    /// `load_with_versions` must be given.
+    MarkerVersions(Vec<u16>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Json(ref e) => write!(f, "JSON error: {}", e),
+            Error::Bip32(ref e) => write!(f, "BIP32 error: {}", e),
+            Error::Encode(ref e) => write!(f, "encoding error: {}", e),
+            Error::Coloring(ref e) => write!(f, "coloring error: {}", e),
+            Error::Corrupted(what) => write!(f, "corrupted wallet state: {}", what),
+            Error::MarkerVersions(ref versions) => {
+                write!(f, "state saved with marker versions {:?}", versions)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<bip32::Error> for Error {
+    fn from(e: bip32::Error) -> Self {
+        Error::Bip32(e)
+    }
+}
+
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+impl From<coloring::Error> for Error {
+    fn from(e: coloring::Error) -> Self {
+        Error::Coloring(e)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The net change of the holdings of one asset by one transaction.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Transfer {
+    pub txid: Txid,
+    pub height: u64,
+    pub asset_id: AssetId,
+    pub direction: Direction,
+    pub quantity: u64,
+}
+
+/// An extended public key of an account with the state of its chains.
+struct Account {
+    xpub: ExtendedPubKey,
+    derived: [u32; 2],
+    last_used: [Option<u32>; 2],
+}
+
+/// Tracks the colored outputs paying to a set of extended public keys or
+/// addresses, without holding any private key.
+pub struct WatchOnlyWallet {
+    secp: Secp256k1<VerifyOnly>,
+    params: &'static NetworkParams,
+    gap_limit: u32,
+    accounts: Vec<Account>,
+    addresses: Vec<Address>,
+    /// The watched scripts, with the account chain and index they derive from.
+    scripts: HashMap<Script, Option<(usize, Chain, u32)>>,
+    unspent: HashMap<OutPoint, ColoredOutput>,
+    history: Vec<Transfer>,
+    /// The number of blocks scanned and the hash of the last one.
+    height: u64,
+    tip: Option<BlockHash>,
+}
+
+impl WatchOnlyWallet {
+    /// Watches the receive and change chains of account level extended public keys.
+    pub fn from_xpubs(network: Network, xpubs: Vec<ExtendedPubKey>) -> Result<Self, Error> {
+        WatchOnlyWallet::from_xpubs_with_params(NetworkParams::tapyrus(network), xpubs)
+    }
+
+    /// Like `from_xpubs`, reporting the asset ids of `params`.
+    pub fn from_xpubs_with_params(
+        params: &'static NetworkParams,
+        xpubs: Vec<ExtendedPubKey>,
+    ) -> Result<Self, Error> {
+        let mut wallet = WatchOnlyWallet::new(params);
+        for xpub in xpubs {
+            wallet.accounts.push(Account {
+                xpub,
+                derived: [0; 2],
+                last_used: [None; 2],
+            });
+        }
+        wallet.extend_lookahead()?;
+        Ok(wallet)
+    }
+
+    pub fn from_addresses(network: Network, addresses: &[Address]) -> Result<Self, Error> {
+        WatchOnlyWallet::from_addresses_with_params(NetworkParams::tapyrus(network), addresses)
+    }
+
+    /// Like `from_addresses`, reporting the asset ids of `params`.
+    pub fn from_addresses_with_params(
+        params: &'static NetworkParams,
+        addresses: &[Address],
+    ) -> Result<Self, Error> {
+        let mut wallet = WatchOnlyWallet::new(params);
+        for address in addresses {
+            wallet.watch_address(address.clone())?;
+        }
+        Ok(wallet)
+    }
+
+    fn new(params: &'static NetworkParams) -> Self {
+        WatchOnlyWallet {
+            secp: Secp256k1::verification_only(),
+            params,
+            gap_limit: DEFAULT_GAP_LIMIT,
+            accounts: vec![],
+            addresses: vec![],
+            scripts: HashMap::new(),
+            unspent: HashMap::new(),
+            history: vec![],
+            height: 0,
+            tip: None,
+        }
+    }
+
+    pub fn with_gap_limit(mut self, gap_limit: u32) -> Result<Self, Error> {
+        self.gap_limit = gap_limit;
+        self.extend_lookahead()?;
+        Ok(self)
+    }
+
+    pub fn network(&self) -> Network {
+        self.params.network
+    }
+
+    pub fn params(&self) -> &'static NetworkParams {
+        self.params
+    }
+
+    /// The number of blocks scanned so far.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// The hash of the last block scanned.
+    pub fn tip(&self) -> Option<BlockHash> {
+        self.tip
+    }
+
+    pub fn is_watched(&self, script: &Script) -> bool {
+        self.scripts.contains_key(script)
+    }
+
+    /// The unspent outputs paying to the wallet.
+    pub fn unspent(&self) -> impl Iterator<Item = (&OutPoint, &ColoredOutput)> {
+        self.unspent.iter()
+    }
+
+    /// The transfers of assets to and from the wallet, oldest first.
+    pub fn history(&self) -> &[Transfer] {
+        &self.history
+    }
+
+    /// Returns the quantity of each asset held, in order of first appearance
+    /// among the unspent outputs sorted by outpoint.
+    pub fn balances(&self) -> Vec<(AssetId, u64)> {
+        let mut outpoints: Vec<&OutPoint> = self.unspent.keys().collect();
+        outpoints.sort();
+        totals(outpoints.into_iter().map(|o| &self.unspent[o]))
+    }
+
+    pub fn balance(&self, asset_id: &AssetId) -> u64 {
+        self.unspent
+            .values()
+            .filter(|o| o.asset_id.as_ref() == Some(asset_id))
+            .map(|o| o.asset_quantity)
+            .sum()
+    }
+
+    /// Colors `blocks`, following the blocks already scanned, with `colorer`
+    /// and records the outputs paying to and spent by the wallet.
+    ///
+    /// `colorer` must have colored every block before them.
+    pub fn scan_blocks(
+        &mut self,
+        colorer: &mut ChainColorer,
+        blocks: &[Block],
+    ) -> Result<(), Error> {
+        for block in blocks {
+            let colorings = colorer.color_block(block)?;
+            for (tx, coloring) in block.txdata.iter().zip(colorings.iter()) {
+                self.apply(tx, &coloring.outputs)?;
+            }
+            self.height += 1;
+            self.tip = Some(block.block_hash());
+        }
+        Ok(())
+    }
+
+    /// Records the effect of `tx`, whose outputs are colored as `outputs`, at
+    /// the current height.
+    pub fn apply(&mut self, tx: &Transaction, outputs: &[ColoredOutput]) -> Result<(), Error> {
+        let txid = tx.txid();
+        let mut spent = vec![];
+        for input in tx.input.iter() {
+            if let Some(output) = self.unspent.remove(&input.previous_output) {
+                spent.push(output);
+            }
+        }
+        let mut received = vec![];
+        for (vout, output) in outputs.iter().enumerate() {
+            if let Some(&key) = self.scripts.get(&output.script_pubkey) {
+                if let Some((account, chain, index)) = key {
+                    self.mark_used(account, chain, index)?;
+                }
+                self.unspent
+                    .insert(OutPoint::new(txid, vout as u32), output.clone());
+                received.push(output.clone());
+            }
+        }
+
+        let spent = totals(spent.iter());
+        let received = totals(received.iter());
+        let mut assets: Vec<&AssetId> = vec![];
+        for (asset_id, _) in spent.iter().chain(received.iter()) {
+            if !assets.contains(&asset_id) {
+                assets.push(asset_id);
+            }
+        }
+        for asset_id in assets {
+            let out = find_total(&spent, asset_id);
+            let got = find_total(&received, asset_id);
+            let (direction, quantity) = match got.cmp(&out) {
+                Ordering::Greater => (Direction::Incoming, got - out),
+                Ordering::Less => (Direction::Outgoing, out - got),
+                // change returned to the wallet
+                Ordering::Equal => continue,
+            };
+            self.history.push(Transfer {
+                txid,
+                height: self.height,
+                asset_id: asset_id.clone(),
+                direction,
+                quantity,
+            });
+        }
+        Ok(())
+    }
+
+    fn watch_address(&mut self, address: Address) -> Result<(), Error> {
+        let script = address.to_btc_addr()?.script_pubkey();
+        self.scripts.insert(script, None);
+        self.addresses.push(address);
+        Ok(())
+    }
+
+    fn mark_used(&mut self, account: usize, chain: Chain, index: u32) -> Result<(), Error> {
+        let c = chain.index() as usize;
+        let last_used = &mut self.accounts[account].last_used[c];
+        if last_used.is_none_or(|i| i < index) {
+            *last_used = Some(index);
+        }
+        self.extend_lookahead()
+    }
+
+    /// Derives the scripts of every account up to the gap limit past the last used key.
+    fn extend_lookahead(&mut self) -> Result<(), Error> {
+        for (a, account) in self.accounts.iter_mut().enumerate() {
+            for chain in [Chain::Receive, Chain::Change] {
+                let c = chain.index() as usize;
+                let limit = account.last_used[c].map_or(0, |i| i + 1) + self.gap_limit;
+                while account.derived[c] < limit {
+                    let index = account.derived[c];
+                    let path = [
+                        ChildNumber::from_normal_idx(chain.index())?,
+                        ChildNumber::from_normal_idx(index)?,
+                    ];
+                    let key = account.xpub.derive_pub(&self.secp, &path)?.public_key;
+                    let script = tapyrus::Address::p2pkh(&key, self.params.network).script_pubkey();
+                    self.scripts.insert(script, Some((a, chain, index)));
+                    account.derived[c] += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn to_json(&self, colorer: &ChainColorer) -> Value {
+        json!({
+            "version": STATE_VERSION,
+            "network": self.params.name,
+            "gap_limit": self.gap_limit,
+            "height": self.height,
+            "tip": self.tip.map(|hash| hash.to_string()),
+            "accounts": self.accounts.iter().map(|a| json!({
+                "xpub": a.xpub.to_string(),
+                "last_used": a.last_used,
+            })).collect::<Vec<Value>>(),
+            "addresses": self.addresses.iter().map(|a| a.to_string()).collect::<Vec<String>>(),
+            "unspent": unspent_to_json(self.unspent.iter()),
+            "marker_versions": colorer.marker_versions().versions(),
+            "colorer": unspent_to_json(colorer.colored_unspent()),
+            "history": self.history.iter().map(|t| json!({
+                "txid": t.txid.to_string(),
+                "height": t.height,
+                "asset_id": t.asset_id.to_string(),
+                "direction": t.direction.to_string(),
+                "quantity": t.quantity,
+            })).collect::<Vec<Value>>(),
+        })
+    }
+
+    fn from_json(value: &Value, versions: MarkerVersions) -> Result<(Self, ChainColorer), Error> {
+        if value["version"].as_u64() != Some(STATE_VERSION) {
+            return Err(Error::Corrupted("unsupported version"));
+        }
+        let params = value["network"]
+            .as_str()
+            .and_then(NetworkParams::by_name)
+            .ok_or(Error::Corrupted("network"))?;
+        let mut wallet = WatchOnlyWallet::new(params);
+        wallet.gap_limit = value["gap_limit"]
+            .as_u64()
+            .ok_or(Error::Corrupted("gap limit"))? as u32;
+        for account in array(&value["accounts"])? {
+            let xpub = account["xpub"]
+                .as_str()
+                .and_then(|s| ExtendedPubKey::from_str(s).ok())
+                .ok_or(Error::Corrupted("xpub"))?;
+            wallet.accounts.push(Account {
+                xpub,
+                derived: [0; 2],
+                last_used: serde_json::from_value(account["last_used"].clone())?,
+            });
+        }
+        wallet.extend_lookahead()?;
+
+        for address in array(&value["addresses"])? {
+            let address = address
+                .as_str()
+                .and_then(|s| Address::from_str_with_params(s, params).ok())
+                .ok_or(Error::Corrupted("address"))?;
+            wallet.watch_address(address)?;
+        }
+
+        wallet.height = value["height"].as_u64().ok_or(Error::Corrupted("height"))?;
+        wallet.tip = match value["tip"].as_str() {
+            Some(s) => Some(BlockHash::from_hex(s).map_err(|_| Error::Corrupted("tip"))?),
+            None => None,
+        };
+
+        wallet.unspent = parse_unspent(&value["unspent"], params)?;
+        let saved: Vec<u16> = serde_json::from_value(value["marker_versions"].clone())?;
+        if saved != versions.versions() {
+            return Err(Error::MarkerVersions(saved));
+        }
+        let mut colorer = ChainColorer::with_params(params)
+            .with_marker_versions(versions)
+            .with_missing_as_uncolored();
+        for (outpoint, output) in parse_unspent(&value["colorer"], params)? {
+            colorer.insert_unspent(outpoint, output);
+        }
+
+        for entry in array(&value["history"])? {
+            wallet.history.push(Transfer {
+                txid: parse_txid(&entry["txid"])?,
+                height: entry["height"].as_u64().ok_or(Error::Corrupted("height"))?,
+                asset_id: parse_asset_id(entry["asset_id"].as_str().unwrap_or(""), params)?,
+                direction: match entry["direction"].as_str() {
+                    Some("incoming") => Direction::Incoming,
+                    Some("outgoing") => Direction::Outgoing,
+                    _ => return Err(Error::Corrupted("direction")),
+                },
+                quantity: entry["quantity"]
+                    .as_u64()
+                    .ok_or(Error::Corrupted("quantity"))?,
+            });
+        }
+        Ok((wallet, colorer))
+    }
+
+    /// Writes the wallet state and the state of `colorer`, which scanned the
+    /// same blocks, to `path`, replacing the previous state atomically. Only
+    /// the outputs of `ChainColorer::colored_unspent` are kept.
+    pub fn save<P: AsRef<Path>>(&self, colorer: &ChainColorer, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut tmp = PathBuf::from(path);
+        tmp.set_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(&self.to_json(colorer))?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Restores a wallet saved by `save`, with a colorer ready to scan the
+    /// blocks following the last one scanned. The colorer takes the outputs
+    /// it does not know for uncolored, see `with_missing_as_uncolored`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<(Self, ChainColorer), Error> {
+        WatchOnlyWallet::load_with_versions(path, MarkerVersions::default())
+    }
+
+    /// Restores a wallet saved with a colorer accepting the marker versions
+    /// of `versions`, giving them back to the restored colorer.
+    pub fn load_with_versions<P: AsRef<Path>>(
+        path: P,
+        versions: MarkerVersions,
+    ) -> Result<(Self, ChainColorer), Error> {
+        let data = fs::read(path)?;
+        WatchOnlyWallet::from_json(&serde_json::from_slice(&data)?, versions)
+    }
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, Error> {
+    value
+        .as_array()
+        .ok_or(Error::Corrupted("expected an array"))
+}
+
+fn parse_txid(value: &Value) -> Result<Txid, Error> {
+    value
+        .as_str()
+        .and_then(|s| Txid::from_hex(s).ok())
+        .ok_or(Error::Corrupted("txid"))
+}
+
+fn parse_asset_id(s: &str, params: &'static NetworkParams) -> Result<AssetId, Error> {
+    AssetId::from_str_with_params(s, params).map_err(|_| Error::Corrupted("asset id"))
+}
+
+/// Serializes colored outputs sorted by outpoint.
+fn unspent_to_json<'a, I>(unspent: I) -> Vec<Value>
+where
+    I: Iterator<Item = (&'a OutPoint, &'a ColoredOutput)>,
+{
+    let mut unspent: Vec<(&OutPoint, &ColoredOutput)> = unspent.collect();
+    unspent.sort_by_key(|(outpoint, _)| **outpoint);
+    unspent
+        .iter()
+        .map(|(outpoint, output)| {
+            json!({
+                "txid": outpoint.txid.to_string(),
+                "vout": outpoint.vout,
+                "value": output.value,
+                "script": hex::encode(output.script_pubkey.as_bytes()),
+                "asset_id": output.asset_id.as_ref().map(|id| id.to_string()),
+                "asset_quantity": output.asset_quantity,
+                "output_type": output.output_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_unspent(
+    value: &Value,
+    params: &'static NetworkParams,
+) -> Result<HashMap<OutPoint, ColoredOutput>, Error> {
+    let mut unspent = HashMap::new();
+    for entry in array(value)? {
+        let txid = parse_txid(&entry["txid"])?;
+        let vout = entry["vout"].as_u64().ok_or(Error::Corrupted("vout"))? as u32;
+        let script = entry["script"]
+            .as_str()
+            .and_then(|s| hex::decode(s).ok())
+            .ok_or(Error::Corrupted("script"))?;
+        let output_type = match entry["output_type"].as_str() {
+            Some("uncolored") => OutputType::Uncolored,
+            Some("marker_output") => OutputType::MarkerOutput,
+            Some("issuance") => OutputType::Issuance,
+            Some("transfer") => OutputType::Transfer,
+            _ => return Err(Error::Corrupted("output type")),
+        };
+        let output = ColoredOutput {
+            value: entry["value"].as_u64().ok_or(Error::Corrupted("value"))?,
+            script_pubkey: Script::from(script),
+            asset_id: match entry["asset_id"].as_str() {
+                Some(s) => Some(parse_asset_id(s, params)?),
+                None => None,
+            },
+            asset_quantity: entry["asset_quantity"]
+                .as_u64()
+                .ok_or(Error::Corrupted("asset quantity"))?,
+            output_type,
+        };
+        unspent.insert(OutPoint::new(txid, vout), output);
+    }
+    Ok(unspent)
+}
+
+/// Sums the quantities of `outputs` per asset id, in order of first appearance.
+fn totals<'a, I: Iterator<Item = &'a ColoredOutput>>(outputs: I) -> Vec<(AssetId, u64)> {
+    let mut totals: Vec<(AssetId, u64)> = vec![];
+    for output in outputs {
+        if let Some(ref asset_id) = output.asset_id {
+            match totals.iter_mut().find(|(id, _)| id == asset_id) {
+                Some((_, total)) => *total += output.asset_quantity,
+                None => totals.push((asset_id.clone(), output.asset_quantity)),
+            }
+        }
+    }
+    totals
+}
+
+fn find_total(totals: &[(AssetId, u64)], asset_id: &AssetId) -> u64 {
+    totals
+        .iter()
+        .find(|(id, _)| id == asset_id)
+        .map_or(0, |(_, q)| *q)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openassets::address::Address;
+    use crate::openassets::asset_id::AssetId;
+    use crate::openassets::hd_wallet::{Chain, HdWallet, KeyIndex};
+    use crate::openassets::marker_output::{MarkerVersions, V1Decoder};
+    use crate::openassets::network::BITCOIN;
+    use crate::openassets::pipeline::ChainColorer;
+    use crate::openassets::test_helpers::*;
+    use crate::openassets::watch_only::{Direction, Error, Transfer, WatchOnlyWallet};
+    use std::str::FromStr;
+    use tapyrus::hashes::Hash;
+    use tapyrus::network::constants::Network;
+    use tapyrus::{Block, BlockHash, OutPoint, Script};
+
+    const SEED: [u8; 16] = [0x5a; 16];
+
+    fn key(chain: Chain, index: u32) -> KeyIndex {
+        KeyIndex { chain, index }
+    }
+
+    /// Issues 100 units to receive key 0, sends 70 of them away keeping 30 as
+    /// change, then receives 50 units on receive key 20.
+    fn chain(keys: &HdWallet) -> Vec<Block> {
+        let ours = |k| keys.address(k).unwrap().script_pubkey();
+        let funding = funding_tx(1, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+        let issuance = tx(
+            &[OutPoint::new(funding.txid(), 0)],
+            vec![
+                txout(600, ours(key(Chain::Receive, 0))),
+                txout(600, p2pkh(2)),
+                marker(vec![100, 50], b""),
+            ],
+        );
+        let spend = tx(
+            &[OutPoint::new(issuance.txid(), 0)],
+            vec![
+                marker(vec![70, 30], b""),
+                txout(600, p2pkh(3)),
+                txout(600, ours(key(Chain::Change, 0))),
+            ],
+        );
+        let receive = tx(
+            &[OutPoint::new(issuance.txid(), 1)],
+            vec![
+                marker(vec![50], b""),
+                txout(600, ours(key(Chain::Receive, 20))),
+            ],
+        );
+        let genesis = block(
+            &BlockHash::from_slice(&[0; 32]).unwrap(),
+            0,
+            &[funding, issuance],
+        );
+        let second = block(&genesis.block_hash(), 1, &[spend]);
+        let third = block(&second.block_hash(), 2, &[receive]);
+        vec![genesis, second, third]
+    }
+
+    #[test]
+    fn test_scan_xpub() {
+        let keys = HdWallet::from_seed(Network::Prod, &SEED, 1165, 0).unwrap();
+        let blocks = chain(&keys);
+        let mut wallet =
+            WatchOnlyWallet::from_xpubs(Network::Prod, vec![keys.account_xpub()]).unwrap();
+        let receive_20 = keys
+            .address(key(Chain::Receive, 20))
+            .unwrap()
+            .script_pubkey();
+        // beyond the gap limit until receive key 0 is used
+        assert!(!wallet.is_watched(&receive_20));
+
+        let mut colorer = ChainColorer::new(Network::Prod);
+        wallet.scan_blocks(&mut colorer, &blocks).unwrap();
+        assert!(wallet.is_watched(&receive_20));
+        assert_eq!(3, wallet.height());
+        assert_eq!(Some(blocks[2].block_hash()), wallet.tip());
+
+        let asset_id = AssetId::from_str(ISSUER_ASSET_ID).unwrap();
+        assert_eq!(vec![(asset_id.clone(), 80)], wallet.balances());
+        assert_eq!(80, wallet.balance(&asset_id));
+        assert_eq!(2, wallet.unspent().count());
+        let transfer = |tx: &tapyrus::Transaction, height, direction, quantity| Transfer {
+            txid: tx.txid(),
+            height,
+            asset_id: asset_id.clone(),
+            direction,
+            quantity,
+        };
+        assert_eq!(
+            &[
+                transfer(&blocks[0].txdata[1], 0, Direction::Incoming, 100),
+                transfer(&blocks[1].txdata[0], 1, Direction::Outgoing, 70),
+                transfer(&blocks[2].txdata[0], 2, Direction::Incoming, 50),
+            ][..],
+            wallet.history()
+        );
+    }
+
+    #[test]
+    fn test_scan_addresses() {
+        let keys = HdWallet::from_seed(Network::Prod, &SEED, 1165, 0).unwrap();
+        let blocks = chain(&keys);
+        let address = keys.oa_address(key(Chain::Receive, 0)).unwrap();
+        let mut wallet = WatchOnlyWallet::from_addresses(Network::Prod, &[address]).unwrap();
+        wallet
+            .scan_blocks(&mut ChainColorer::new(Network::Prod), &blocks)
+            .unwrap();
+
+        assert!(wallet.balances().is_empty());
+        assert_eq!(0, wallet.unspent().count());
+        let history: Vec<(Direction, u64)> = wallet
+            .history()
+            .iter()
+            .map(|t| (t.direction, t.quantity))
+            .collect();
+        assert_eq!(
+            vec![(Direction::Incoming, 100), (Direction::Outgoing, 100)],
+            history
+        );
+        assert!(!wallet.is_watched(&Script::new()));
+    }
+
+    #[test]
+    fn test_persistence() {
+        let keys = HdWallet::from_seed(Network::Prod, &SEED, 1165, 0).unwrap();
+        let blocks = chain(&keys);
+        let address = keys.oa_address(key(Chain::Change, 30)).unwrap();
+        let mut wallet = WatchOnlyWallet::from_xpubs(Network::Prod, vec![keys.account_xpub()])
+            .unwrap()
+            .with_gap_limit(5)
+            .unwrap();
+        wallet.watch_address(address).unwrap();
+        let mut colorer = ChainColorer::new(Network::Prod);
+        wallet.scan_blocks(&mut colorer, &blocks[..2]).unwrap();
+
+        let path = temp_dir("watch-only").join("wallet.json");
+        wallet.save(&colorer, &path).unwrap();
+        let (mut restored, mut restored_colorer) = WatchOnlyWallet::load(&path).unwrap();
+        assert_eq!(
+            wallet.to_json(&colorer),
+            restored.to_json(&restored_colorer)
+        );
+        assert_eq!(wallet.balances(), restored.balances());
+
+        // the third block spends an output colored before the save, so the
+        // restored colorer must know it; receive key 20 is beyond the gap
+        // limit of 5
+        restored
+            .scan_blocks(&mut restored_colorer, &blocks[2..])
+            .unwrap();
+        assert_eq!(30, restored.balances()[0].1);
+        assert_eq!(2, restored.history().len());
+
+        // only colored outputs are saved
+        let saved = wallet.to_json(&colorer);
+        let saved = saved["colorer"].as_array().unwrap();
+        assert_eq!(colorer.colored_unspent().count(), saved.len());
+        assert!(colorer.unspent().count() > saved.len());
+    }
+
+    #[test]
+    fn test_persistence_marker_versions() {
+        let keys = HdWallet::from_seed(Network::Prod, &SEED, 1165, 0).unwrap();
+        let wallet = WatchOnlyWallet::from_xpubs(Network::Prod, vec![keys.account_xpub()]).unwrap();
+        let versions = MarkerVersions::new().with_version(0x0200, V1Decoder);
+        let colorer = ChainColorer::new(Network::Prod).with_marker_versions(versions.clone());
+
+        let path = temp_dir("watch-only-versions").join("wallet.json");
+        wallet.save(&colorer, &path).unwrap();
+        match WatchOnlyWallet::load(&path) {
+            Err(Error::MarkerVersions(saved)) => assert_eq!(vec![0x0100, 0x0200], saved),
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+        let (_, restored) = WatchOnlyWallet::load_with_versions(&path, versions).unwrap();
+        assert_eq!(vec![0x0100, 0x0200], restored.marker_versions().versions());
+    }
+
+    #[test]
+    fn test_persistence_params() {
+        let keys = HdWallet::from_seed(Network::Prod, &SEED, 1165, 0).unwrap();
+        let blocks = chain(&keys);
+        let payload = keys.address(key(Chain::Receive, 0)).unwrap().payload;
+        let address = Address::with_params(payload, &BITCOIN).unwrap();
+        let mut wallet = WatchOnlyWallet::from_addresses_with_params(&BITCOIN, &[address]).unwrap();
+        let mut colorer = ChainColorer::with_params(&BITCOIN);
+        wallet.scan_blocks(&mut colorer, &blocks[..1]).unwrap();
+
+        let path = temp_dir("watch-only-params").join("wallet.json");
+        wallet.save(&colorer, &path).unwrap();
+        let (restored, restored_colorer) = WatchOnlyWallet::load(&path).unwrap();
+        assert_eq!("bitcoin", restored.params().name);
+        assert_eq!("bitcoin", restored_colorer.params().name);
+        let balances = restored.balances();
+        assert_eq!(100, balances[0].1);
+        assert_eq!("bitcoin", balances[0].0.params().name);
+        assert_eq!(wallet.balances(), balances);
+    }
+}
@@ -0,0 +1,212 @@
+//! Queries the HTTP server through a client running in the same process.
+//!
+//! Run with `cargo test --features server`.
+
+#![cfg(feature = "server")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use openassets_tapyrus::openassets::asset_id::AssetId;
+use openassets_tapyrus::openassets::server::{Index, Server, DEFAULT_TIMEOUT};
+use openassets_tapyrus::openassets::testing::{
+    block, funding_tx, marker, p2pkh, script, tx, txout, ISSUER_ASSET_ID, ISSUER_SCRIPT,
+};
+use serde_json::{json, Value};
+use tapyrus::consensus::serialize;
+use tapyrus::hashes::Hash;
+use tapyrus::network::constants::Network;
+use tapyrus::{Block, BlockHash, OutPoint, Txid};
+
+/// The Tapyrus and Open Assets addresses of the holder.
+const HOLDER: &str = "1F2AQr6oqNtcJQ6p9SiCLQTrHuM9en44H8";
+const HOLDER_OA: &str = "akQz3f1v9JrnJAeGBC4pNzGNRdWXKan4U6E";
+
+/// Issues 100 units to the holder, who then sends 60 of them away.
+fn chain() -> Vec<Block> {
+    let holder = tapyrus::Address::from_str(HOLDER).unwrap().script_pubkey();
+    let funding = funding_tx(0, vec![txout(10_000, script(ISSUER_SCRIPT))]);
+    let issuance = tx(
+        &[OutPoint::new(funding.txid(), 0)],
+        vec![
+            txout(600, holder.clone()),
+            marker(vec![100], b"u=https://example.com"),
+        ],
+    );
+    let transfer = tx(
+        &[OutPoint::new(issuance.txid(), 0)],
+        vec![
+            marker(vec![60, 40], b""),
+            txout(600, p2pkh(2)),
+            txout(600, holder),
+        ],
+    );
+    let genesis = block(
+        &BlockHash::from_slice(&[0; 32]).unwrap(),
+        0,
+        &[funding, issuance],
+    );
+    let next = block(&genesis.block_hash(), 0, &[transfer]);
+    vec![genesis, next]
+}
+
+fn index(blocks: &[Block]) -> Index {
+    let mut index = Index::new(Network::Prod);
+    for block in blocks {
+        index.add_block(block).unwrap();
+    }
+    index
+}
+
+fn spawn(server: Server) -> SocketAddr {
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.serve());
+    addr
+}
+
+fn start(blocks: &[Block]) -> SocketAddr {
+    spawn(Server::bind("127.0.0.1:0", index(blocks)).unwrap())
+}
+
+/// Connects and sends the start of a request, then waits.
+fn idle(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    stream
+}
+
+fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn test_queries() {
+    let blocks = chain();
+    let addr = start(&blocks);
+    let issuance = &blocks[0].txdata[1];
+    let transfer = &blocks[1].txdata[0];
+
+    let (status, outputs) = request(
+        addr,
+        "GET",
+        &format!("/v1/txs/{}/outputs", issuance.txid()),
+        "",
+    );
+    assert_eq!(200, status);
+    assert_eq!(json!(ISSUER_ASSET_ID), outputs["outputs"][0]["asset_id"]);
+    assert_eq!(json!(100), outputs["outputs"][0]["asset_quantity"]);
+    assert_eq!(json!("marker_output"), outputs["outputs"][1]["output_type"]);
+
+    let (status, balance) = request(
+        addr,
+        "GET",
+        &format!("/v1/addresses/{}/balance", HOLDER_OA),
+        "",
+    );
+    assert_eq!(200, status);
+    assert_eq!(
+        json!([{ "asset_id": ISSUER_ASSET_ID, "quantity": 40 }]),
+        balance["assets"]
+    );
+    assert_eq!(
+        json!(transfer.txid().to_string()),
+        balance["unspent"][0]["txid"]
+    );
+    assert_eq!(json!(2), balance["unspent"][0]["vout"]);
+
+    let (status, asset) = request(addr, "GET", &format!("/v1/assets/{}", ISSUER_ASSET_ID), "");
+    assert_eq!(200, status);
+    assert_eq!(json!(100), asset["issued"]);
+    assert_eq!(json!(100), asset["supply"]);
+    assert_eq!(json!([issuance.txid().to_string()]), asset["issuances"]);
+    assert_eq!(json!("u=https://example.com"), asset["metadata"]["utf8"]);
+
+    let (status, decoded) = request(
+        addr,
+        "POST",
+        "/v1/decode",
+        &hex::encode(serialize(transfer)),
+    );
+    assert_eq!(200, status);
+    assert_eq!(json!(0), decoded["marker_index"]);
+    assert_eq!(json!([60, 40]), decoded["quantities"]);
+}
+
+#[test]
+fn test_errors() {
+    let addr = start(&chain());
+    let unknown = Txid::from_slice(&[1; 32]).unwrap();
+    let cases = [
+        ("GET", format!("/v1/txs/{}/outputs", unknown), "", 404),
+        ("GET", "/v1/txs/xyz/outputs".to_string(), "", 400),
+        ("GET", format!("/v1/addresses/{}/balance", HOLDER), "", 400),
+        (
+            "GET",
+            format!("/v1/assets/{}", AssetId::new(&p2pkh(9), Network::Prod)),
+            "",
+            404,
+        ),
+        ("GET", "/v1/assets/xyz".to_string(), "", 400),
+        ("POST", format!("/v1/assets/{}", ISSUER_ASSET_ID), "", 405),
+        ("POST", "/v1/decode".to_string(), "zz", 400),
+        ("GET", "/".to_string(), "", 404),
+    ];
+    for (method, path, body, expected) in cases.iter() {
+        let (status, value) = request(addr, method, path, body);
+        assert_eq!(*expected, status, "{} {}", method, path);
+        assert!(value["error"].is_string());
+    }
+}
+
+#[test]
+fn test_idle_connection() {
+    let addr = start(&chain());
+    let _idle = idle(addr);
+    let (status, _) = request(addr, "GET", &format!("/v1/assets/{}", ISSUER_ASSET_ID), "");
+    assert_eq!(200, status);
+}
+
+#[test]
+fn test_idle_timeout() {
+    let server = Server::bind("127.0.0.1:0", index(&chain()))
+        .unwrap()
+        .with_workers(1)
+        .with_timeout(Duration::from_millis(200));
+    let addr = spawn(server);
+    let start = Instant::now();
+    let mut stream = idle(addr);
+    // the only worker is released once the idle connection times out
+    let (status, _) = request(addr, "GET", &format!("/v1/assets/{}", ISSUER_ASSET_ID), "");
+    assert_eq!(200, status);
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.is_empty());
+    assert!(start.elapsed() < DEFAULT_TIMEOUT);
+}
+
+#[test]
+fn test_zero_timeout() {
+    let server = Server::bind("127.0.0.1:0", index(&chain()))
+        .unwrap()
+        .with_timeout(Duration::ZERO);
+    let addr = spawn(server);
+    let (status, _) = request(addr, "GET", &format!("/v1/assets/{}", ISSUER_ASSET_ID), "");
+    assert_eq!(200, status);
+}
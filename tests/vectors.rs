@@ -0,0 +1,349 @@
+//! Runs the known-answer test vectors under `tests/vectors`.
+//!
+//! The vectors are plain JSON so that new ones can be added without touching
+//! this file. Every vector names its `source`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use openassets_tapyrus::openassets::address::{Address, OAAddressConverter};
+use openassets_tapyrus::openassets::asset_id::AssetId;
+use openassets_tapyrus::openassets::coloring::Colorer;
+use openassets_tapyrus::openassets::marker_output::{Metadata, Payload, TxOutExt};
+use openassets_tapyrus::openassets::network::{NetworkParams, TAPYRUS_DEV, TAPYRUS_PROD};
+use openassets_tapyrus::openassets::sign::{
+    sighash, sign_transaction, verify_signature, SignatureScheme, Signer, SigningInput,
+};
+use openassets_tapyrus::openassets::testing::{funding_tx, marker, tx, txout};
+use serde_json::Value;
+use tapyrus::blockdata::script::Builder;
+use tapyrus::consensus::{deserialize, serialize};
+use tapyrus::network::constants::Network;
+use tapyrus::secp256k1::SecretKey;
+use tapyrus::{OutPoint, PrivateKey, Script, SigHashType, Transaction, TxOut, Txid};
+
+fn load(name: &str) -> Value {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/vectors")
+        .join(format!("{}.json", name));
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn vectors<'a>(file: &'a Value, key: &str) -> &'a Vec<Value> {
+    file[key].as_array().unwrap()
+}
+
+fn bytes(value: &Value) -> Vec<u8> {
+    hex::decode(value.as_str().unwrap()).unwrap()
+}
+
+fn script(value: &Value) -> Script {
+    Builder::from(bytes(value)).into_script()
+}
+
+fn quantities(value: &Value) -> Vec<u64> {
+    value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|q| q.as_u64().unwrap())
+        .collect()
+}
+
+/// Maps "prod" and "dev" to the Tapyrus presets and other names to the
+/// preset of that name, e.g. "bitcoin" or "bitcoin-testnet".
+fn network(value: &Value) -> &'static NetworkParams {
+    match value.as_str().unwrap() {
+        "prod" => &TAPYRUS_PROD,
+        "dev" => &TAPYRUS_DEV,
+        other => {
+            NetworkParams::by_name(other).unwrap_or_else(|| panic!("unknown network {}", other))
+        }
+    }
+}
+
+fn payload(value: &Value) -> Payload {
+    Payload {
+        quantities: quantities(&value["quantities"]),
+        metadata: Metadata::new(bytes(&value["metadata"])),
+    }
+}
+
+#[test]
+fn test_marker_vectors() {
+    let file = load("marker");
+    for vector in vectors(&file, "valid") {
+        let description = vector["description"].as_str().unwrap();
+        let expected = payload(vector);
+        let decoded: Payload = deserialize(&bytes(&vector["payload"])).unwrap();
+        assert_eq!(expected, decoded, "{}", description);
+        assert_eq!(
+            bytes(&vector["payload"]),
+            serialize(&expected),
+            "{}",
+            description
+        );
+
+        let txout = TxOut {
+            value: 0,
+            script_pubkey: script(&vector["script"]),
+        };
+        assert!(txout.is_openassets_marker(), "{}", description);
+        assert_eq!(expected, txout.get_oa_payload().unwrap(), "{}", description);
+    }
+    for vector in vectors(&file, "invalid") {
+        let decoded: Result<Payload, _> = deserialize(&bytes(&vector["payload"]));
+        assert!(decoded.is_err(), "{}", vector["description"]);
+    }
+    for vector in vectors(&file, "invalid_scripts") {
+        let txout = TxOut {
+            value: 0,
+            script_pubkey: script(&vector["script"]),
+        };
+        assert!(!txout.is_openassets_marker(), "{}", vector["description"]);
+    }
+}
+
+#[test]
+fn test_asset_id_vectors() {
+    let file = load("asset_id");
+    for vector in vectors(&file, "vectors") {
+        let params = network(&vector["network"]);
+        let asset_id = AssetId::with_params(&script(&vector["script"]), params);
+        assert_eq!(
+            vector["asset_id"],
+            asset_id.to_string(),
+            "{}",
+            vector["description"]
+        );
+        assert_eq!(
+            AssetId::from_str_with_params(vector["asset_id"].as_str().unwrap(), params).unwrap(),
+            asset_id,
+            "{}",
+            vector["description"]
+        );
+    }
+}
+
+#[test]
+fn test_address_vectors() {
+    let file = load("address");
+    for vector in vectors(&file, "vectors") {
+        let description = vector["description"].as_str().unwrap();
+        let params = network(&vector["network"]);
+        // Bitcoin addresses share the version bytes of Tapyrus addresses
+        let address = tapyrus::Address::from_str(vector["address"].as_str().unwrap()).unwrap();
+        let oa_address = Address::with_params(address.payload.clone(), params).unwrap();
+        assert_eq!(
+            vector["oa_address"],
+            oa_address.to_string(),
+            "{}",
+            description
+        );
+        let parsed =
+            Address::from_str_with_params(vector["oa_address"].as_str().unwrap(), params).unwrap();
+        assert!(oa_address == parsed, "{}", description);
+
+        if NetworkParams::tapyrus(address.network).name == params.name {
+            assert!(
+                address.to_oa_address().unwrap() == oa_address,
+                "{}",
+                description
+            );
+            assert_eq!(address, parsed.to_btc_addr().unwrap(), "{}", description);
+        }
+    }
+    for vector in vectors(&file, "invalid") {
+        let parsed = Address::from_str(vector["oa_address"].as_str().unwrap());
+        assert!(parsed.is_err(), "{}", vector["description"]);
+    }
+}
+
+/// Builds the transaction described by `value`, resolving inputs against the
+/// transactions of the vector built before it.
+fn transaction(value: &Value, seed: u32, previous: &[Transaction]) -> Transaction {
+    if !value["hex"].is_null() {
+        return deserialize(&bytes(&value["hex"])).unwrap();
+    }
+    let mut inputs: Vec<OutPoint> = vec![];
+    for input in value["inputs"].as_array().unwrap() {
+        let input = quantities(input);
+        inputs.push(OutPoint::new(
+            previous[input[0] as usize].txid(),
+            input[1] as u32,
+        ));
+    }
+    if inputs.is_empty() {
+        return funding_tx(seed, outputs(value));
+    }
+    tx(&inputs, outputs(value))
+}
+
+fn outputs(value: &Value) -> Vec<TxOut> {
+    value["outputs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|output| match output.get("marker") {
+            Some(value) => marker(quantities(&value["quantities"]), &bytes(&value["metadata"])),
+            None => txout(output["value"].as_u64().unwrap(), script(&output["script"])),
+        })
+        .collect()
+}
+
+#[test]
+fn test_coloring_vectors() {
+    let file = load("coloring");
+    for vector in vectors(&file, "vectors") {
+        let description = vector["description"].as_str().unwrap();
+        let mut txs: Vec<Transaction> = vec![];
+        for (i, value) in vector["transactions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+        {
+            let tx = transaction(value, i as u32, &txs);
+            txs.push(tx);
+        }
+        let tx = txs.last().unwrap().clone();
+        let source: HashMap<Txid, Transaction> = txs.into_iter().map(|t| (t.txid(), t)).collect();
+        let mut colorer = Colorer::with_params(source, network(&vector["network"]));
+
+        let diagnosis = colorer.diagnose_transaction(&tx).unwrap();
+        assert_eq!(
+            vector["valid"].as_bool().unwrap(),
+            diagnosis.is_none(),
+            "{}: {:?}",
+            description,
+            diagnosis
+        );
+        if let Some(diagnosis) = diagnosis {
+            assert_eq!(vector["rule"], diagnosis.rule(), "{}", description);
+        }
+
+        let outputs = colorer.color_transaction(&tx).unwrap();
+        let expected = vectors(vector, "outputs");
+        assert_eq!(expected.len(), outputs.len(), "{}", description);
+        for (i, (expected, output)) in expected.iter().zip(outputs.iter()).enumerate() {
+            assert_eq!(
+                expected["type"],
+                output.output_type.to_string(),
+                "{}: output {}",
+                description,
+                i
+            );
+            assert_eq!(
+                expected["asset_id"],
+                output
+                    .asset_id
+                    .as_ref()
+                    .map_or(Value::Null, |id| id.to_string().into()),
+                "{}: output {}",
+                description,
+                i
+            );
+            assert_eq!(
+                expected["quantity"], output.asset_quantity,
+                "{}: output {}",
+                description, i
+            );
+        }
+    }
+}
+
+fn private_key(value: &Value) -> PrivateKey {
+    PrivateKey {
+        compressed: true,
+        network: Network::Prod,
+        key: SecretKey::from_slice(&bytes(value)).unwrap(),
+    }
+}
+
+#[test]
+fn test_sign_vectors() {
+    let file = load("sign");
+    for vector in vectors(&file, "vectors") {
+        let description = vector["description"].as_str().unwrap();
+        let scheme = match vector["scheme"].as_str().unwrap() {
+            "ecdsa" => SignatureScheme::Ecdsa,
+            "schnorr" => SignatureScheme::Schnorr,
+            other => panic!("unknown scheme {}", other),
+        };
+        let tx: Transaction = deserialize(&bytes(&vector["unsigned"])).unwrap();
+        let keys: Vec<Vec<PrivateKey>> = vectors(vector, "inputs")
+            .iter()
+            .map(|input| vectors(input, "keys").iter().map(private_key).collect())
+            .collect();
+        let mut inputs = vec![];
+        for (index, (input, keys)) in vectors(vector, "inputs")
+            .iter()
+            .zip(keys.iter())
+            .enumerate()
+        {
+            let script_code = script(&input["script_code"]);
+            let hash = sighash(&tx, index, &script_code, SigHashType::All).unwrap();
+            assert_eq!(bytes(&input["sighash"]), &hash[..], "{}", description);
+            for (sig, key) in vectors(input, "signatures").iter().zip(keys.iter()) {
+                let key = Signer::public_key(key);
+                assert!(
+                    verify_signature(&tx, index, &script_code, &bytes(sig), &key).unwrap(),
+                    "{}",
+                    description
+                );
+            }
+            inputs.push(match input["type"].as_str().unwrap() {
+                "p2pkh" => SigningInput::P2pkh {
+                    script_pubkey: script_code,
+                    signer: &keys[0],
+                },
+                "p2sh_multisig" => SigningInput::P2shMultisig {
+                    redeem_script: script_code,
+                    signers: keys.iter().map(|k| k as &dyn Signer).collect(),
+                },
+                other => panic!("unknown input type {}", other),
+            });
+        }
+
+        let signed = sign_transaction(&tx, &inputs, scheme).unwrap();
+        for (input, expected) in signed.input.iter().zip(vectors(vector, "inputs")) {
+            assert_eq!(
+                bytes(&expected["script_sig"]),
+                input.script_sig.as_bytes(),
+                "{}",
+                description
+            );
+        }
+        assert_eq!(
+            bytes(&vector["signed"]),
+            serialize(&signed),
+            "{}",
+            description
+        );
+    }
+}
+
+#[test]
+fn test_vector_sources() {
+    for (name, keys) in [
+        ("marker", &["valid", "invalid", "invalid_scripts"][..]),
+        ("asset_id", &["vectors"][..]),
+        ("address", &["vectors", "invalid"][..]),
+        ("coloring", &["vectors"][..]),
+        ("sign", &["vectors"][..]),
+    ] {
+        let file = load(name);
+        for key in keys {
+            for vector in vectors(&file, key) {
+                assert!(
+                    vector["source"].is_string(),
+                    "{}: {}",
+                    name,
+                    vector["description"]
+                );
+            }
+        }
+    }
+}
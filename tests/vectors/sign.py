@@ -0,0 +1,259 @@
+#!/usr/bin/env python3
+"""Generates sign.json, the known-answer vectors of the signing module.
+
+The values are computed from first principles with the Python standard
+library only: secp256k1 arithmetic, the legacy signature hash, the RFC 6979
+nonces of libsecp256k1, low-S DER encoded ECDSA and the Schnorr signatures of
+Tapyrus (nonce from RFC 6979 with the algorithm tag "SCHNORR + SHA256",
+R negated unless jacobi(R.y) = 1, e = sha256(R.x || P || m)). None of it
+shares code with the Rust implementation.
+
+Run with `python3 tests/vectors/sign.py > tests/vectors/sign.json`.
+"""
+
+import hashlib
+import hmac
+import json
+
+P = 2**256 - 2**32 - 977
+N = 0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141
+G = (
+    0x79BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798,
+    0x483ADA7726A3C4655DA4FBFC0E1108A8FD17B448A68554199C47D08FFB10D4B8,
+)
+SIGHASH_ALL = 1
+
+
+def point_add(a, b):
+    if a is None:
+        return b
+    if b is None:
+        return a
+    if a[0] == b[0] and (a[1] + b[1]) % P == 0:
+        return None
+    if a == b:
+        lam = 3 * a[0] * a[0] * pow(2 * a[1], P - 2, P) % P
+    else:
+        lam = (b[1] - a[1]) * pow(b[0] - a[0], P - 2, P) % P
+    x = (lam * lam - a[0] - b[0]) % P
+    return (x, (lam * (a[0] - x) - a[1]) % P)
+
+
+def point_mul(k, point=G):
+    result = None
+    while k:
+        if k & 1:
+            result = point_add(result, point)
+        point = point_add(point, point)
+        k >>= 1
+    return result
+
+
+def compressed(point):
+    return bytes([2 + (point[1] & 1)]) + point[0].to_bytes(32, "big")
+
+
+def jacobi(y):
+    return pow(y, (P - 1) // 2, P)
+
+
+def sha256(data):
+    return hashlib.sha256(data).digest()
+
+
+def hash160(data):
+    return hashlib.new("ripemd160", sha256(data)).digest()
+
+
+def rfc6979(key, message, algo16=b"", counter=0):
+    """The HMAC-SHA256 DRBG of libsecp256k1's nonce_function_rfc6979."""
+    keydata = key + message + algo16
+    k = b"\x00" * 32
+    v = b"\x01" * 32
+    k = hmac.new(k, v + b"\x00" + keydata, "sha256").digest()
+    v = hmac.new(k, v, "sha256").digest()
+    k = hmac.new(k, v + b"\x01" + keydata, "sha256").digest()
+    v = hmac.new(k, v, "sha256").digest()
+    for i in range(counter + 1):
+        if i > 0:
+            k = hmac.new(k, v + b"\x00", "sha256").digest()
+            v = hmac.new(k, v, "sha256").digest()
+        v = hmac.new(k, v, "sha256").digest()
+    return v
+
+
+def der_int(n):
+    data = n.to_bytes(32, "big").lstrip(b"\x00")
+    if data[0] & 0x80:
+        data = b"\x00" + data
+    return b"\x02" + bytes([len(data)]) + data
+
+
+def sign_ecdsa(secret, message):
+    counter = 0
+    while True:
+        k = int.from_bytes(rfc6979(secret.to_bytes(32, "big"), message, counter=counter), "big")
+        counter += 1
+        if not 0 < k < N:
+            continue
+        r = point_mul(k)[0] % N
+        if r == 0:
+            continue
+        z = int.from_bytes(message, "big") % N
+        s = pow(k, N - 2, N) * (z + r * secret) % N
+        if s == 0:
+            continue
+        s = min(s, N - s)
+        body = der_int(r) + der_int(s)
+        return b"\x30" + bytes([len(body)]) + body
+
+
+def sign_schnorr(secret, message):
+    counter = 0
+    while True:
+        nonce = rfc6979(secret.to_bytes(32, "big"), message, b"SCHNORR + SHA256", counter)
+        counter += 1
+        k = int.from_bytes(nonce, "big")
+        if 0 < k < N:
+            break
+    r = point_mul(k)
+    if jacobi(r[1]) != 1:
+        k = N - k
+    r_x = r[0].to_bytes(32, "big")
+    e = int.from_bytes(sha256(r_x + compressed(point_mul(secret)) + message), "big")
+    return r_x + ((k + e * secret) % N).to_bytes(32, "big")
+
+
+def varint(n):
+    if n < 0xFD:
+        return bytes([n])
+    assert n <= 0xFFFF
+    return b"\xfd" + n.to_bytes(2, "little")
+
+
+def push(data):
+    assert len(data) < 0x4C
+    return bytes([len(data)]) + data
+
+
+def serialize_tx(version, inputs, outputs, lock_time):
+    data = version.to_bytes(4, "little") + varint(len(inputs))
+    for txid, vout, script_sig, sequence in inputs:
+        data += txid + vout.to_bytes(4, "little") + varint(len(script_sig)) + script_sig
+        data += sequence.to_bytes(4, "little")
+    data += varint(len(outputs))
+    for value, script in outputs:
+        data += value.to_bytes(8, "little") + varint(len(script)) + script
+    return data + lock_time.to_bytes(4, "little")
+
+
+def sighash(inputs, outputs, index, script_code):
+    """The legacy SIGHASH_ALL hash, in the byte order it is signed."""
+    blanked = [
+        (txid, vout, script_code if i == index else b"", sequence)
+        for i, (txid, vout, _, sequence) in enumerate(inputs)
+    ]
+    data = serialize_tx(1, blanked, outputs, 0) + SIGHASH_ALL.to_bytes(4, "little")
+    return sha256(sha256(data))
+
+
+def p2pkh(pubkey_hash):
+    return b"\x76\xa9\x14" + pubkey_hash + b"\x88\xac"
+
+
+def multisig(required, pubkeys):
+    script = bytes([0x50 + required])
+    for key in pubkeys:
+        script += push(key)
+    return script + bytes([0x50 + len(pubkeys), 0xAE])
+
+
+SECRETS = [int.from_bytes(bytes([n]) * 32, "big") for n in (1, 2, 3)]
+PUBKEYS = [compressed(point_mul(s)) for s in SECRETS]
+MARKER = b"\x6a\x07" + bytes.fromhex("4f41010001" + "0a" + "00")
+OUTPUTS = [
+    (600, p2pkh(b"\x02" * 20)),
+    (0, MARKER),
+    (1000, p2pkh(b"\x03" * 20)),
+]
+INPUTS = [
+    (bytes([0x11] * 32), 0, b"", 0xFFFFFFFF),
+    (bytes([0x22] * 32), 1, b"", 0xFFFFFFFF),
+]
+SOURCE = (
+    "computed by tests/vectors/sign.py, an independent Python implementation of "
+    "the legacy sighash, RFC 6979, ECDSA and Tapyrus Schnorr"
+)
+
+
+def sign(scheme, secret, message):
+    if scheme == "ecdsa":
+        return sign_ecdsa(secret, message) + bytes([SIGHASH_ALL])
+    return sign_schnorr(secret, message) + bytes([SIGHASH_ALL])
+
+
+def vectors():
+    unsigned = serialize_tx(1, INPUTS, OUTPUTS, 0)
+    result = []
+    for scheme in ("ecdsa", "schnorr"):
+        # input 0 pays to key 1, input 1 to key 2
+        scripts = [p2pkh(hash160(PUBKEYS[0])), p2pkh(hash160(PUBKEYS[1]))]
+        signed = list(INPUTS)
+        entries = []
+        for index, script in enumerate(scripts):
+            hash_ = sighash(INPUTS, OUTPUTS, index, script)
+            sig = sign(scheme, SECRETS[index], hash_)
+            script_sig = push(sig) + push(PUBKEYS[index])
+            txid, vout, _, sequence = INPUTS[index]
+            signed[index] = (txid, vout, script_sig, sequence)
+            entries.append({
+                "type": "p2pkh",
+                "script_code": script.hex(),
+                "keys": [SECRETS[index].to_bytes(32, "big").hex()],
+                "sighash": hash_.hex(),
+                "signatures": [sig.hex()],
+                "script_sig": script_sig.hex(),
+            })
+        result.append({
+            "description": "two P2PKH inputs signed with " + scheme,
+            "source": SOURCE,
+            "scheme": scheme,
+            "unsigned": unsigned.hex(),
+            "inputs": entries,
+            "signed": serialize_tx(1, signed, OUTPUTS, 0).hex(),
+        })
+
+    inputs = INPUTS[:1]
+    unsigned = serialize_tx(1, inputs, OUTPUTS, 0)
+    redeem_script = multisig(2, PUBKEYS)
+    hash_ = sighash(inputs, OUTPUTS, 0, redeem_script)
+    for scheme in ("ecdsa", "schnorr"):
+        # signed by keys 1 and 3, in script order
+        sigs = [sign(scheme, SECRETS[i], hash_) for i in (0, 2)]
+        script_sig = b"\x00" + push(sigs[0]) + push(sigs[1])
+        script_sig += b"\x4c" + bytes([len(redeem_script)]) + redeem_script
+        txid, vout, _, sequence = inputs[0]
+        signed = [(txid, vout, script_sig, sequence)]
+        result.append({
+            "description": "a 2-of-3 P2SH multisig input signed with " + scheme,
+            "source": SOURCE,
+            "scheme": scheme,
+            "unsigned": unsigned.hex(),
+            "inputs": [{
+                "type": "p2sh_multisig",
+                "script_code": redeem_script.hex(),
+                "keys": [SECRETS[i].to_bytes(32, "big").hex() for i in (0, 2)],
+                "sighash": hash_.hex(),
+                "signatures": [sig.hex() for sig in sigs],
+                "script_sig": script_sig.hex(),
+            }],
+            "signed": serialize_tx(1, signed, OUTPUTS, 0).hex(),
+        })
+    return result
+
+
+if __name__ == "__main__":
+    print(json.dumps({
+        "description": "Known-answer vectors of sign_transaction. Keys are the secret keys of the signers of each input, sighashes are in the byte order they are signed and signatures end with the SIGHASH_ALL byte.",
+        "vectors": vectors(),
+    }, indent=2))